@@ -1,10 +1,14 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Coin, StdError, StdResult, Uint256};
+use cosmwasm_std::{Addr, Api, Coin, StdError, StdResult, Uint256};
 use cw_storage_plus::{Bound, Bounder, Key, KeyDeserialize, Prefixer, PrimaryKey};
 
-use crate::{neptune_map::NeptuneMap, traits::KeyVec};
+use crate::{
+    error::{NeptuneError, NeptuneResult},
+    neptune_map::NeptuneMap,
+    traits::KeyVec,
+};
 
 /// AssetInfo can represent either a native token or a token in cosmwasm.
 #[cw_serde]
@@ -18,6 +22,9 @@ pub enum AssetInfo {
 const NATIVE_TOKEN_DISCRIMINANT: u8 = 0;
 const TOKEN_DISCRIMINANT: u8 = 1;
 
+const NATIVE_TOKEN_PREFIX: &str = "native";
+const TOKEN_PREFIX: &str = "cw20";
+
 pub type AssetMap<T> = NeptuneMap<AssetInfo, T>;
 
 impl AssetInfo {
@@ -35,13 +42,110 @@ impl From<Addr> for AssetInfo {
     }
 }
 
+/// The compact form, `native:{denom}` or `cw20:{contract_addr}`.
 impl Display for AssetInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(
-            serde_json_wasm::to_string(self)
-                .map_err(|_| core::fmt::Error)?
-                .as_str(),
-        )
+        match self {
+            AssetInfo::NativeToken { denom } => write!(f, "{NATIVE_TOKEN_PREFIX}:{denom}"),
+            AssetInfo::Token { contract_addr } => write!(f, "{TOKEN_PREFIX}:{contract_addr}"),
+        }
+    }
+}
+
+/// Parses the compact form, or the JSON form for strings written before it.
+/// CW20 addresses are not validated, parse an `AssetInfoUnchecked` to check them.
+impl FromStr for AssetInfo {
+    type Err = StdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('{') {
+            return serde_json_wasm::from_str(s)
+                .map_err(|err| StdError::parse_err("AssetInfo", err.to_string()));
+        }
+        match s.split_once(':') {
+            Some((NATIVE_TOKEN_PREFIX, denom)) if !denom.is_empty() => {
+                Ok(AssetInfo::NativeToken {
+                    denom: denom.to_string(),
+                })
+            }
+            Some((TOKEN_PREFIX, contract_addr)) if !contract_addr.is_empty() => {
+                Ok(AssetInfo::Token {
+                    contract_addr: Addr::unchecked(contract_addr),
+                })
+            }
+            _ => Err(StdError::parse_err(
+                "AssetInfo",
+                format!("expected {NATIVE_TOKEN_PREFIX}:<denom> or {TOKEN_PREFIX}:<address>"),
+            )),
+        }
+    }
+}
+
+/// Serializes an `AssetInfo` as its compact form, for use with `#[serde(with = "...")]`.
+/// The schema of the field should be overridden with `#[schemars(with = "String")]`.
+pub mod compact {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::AssetInfo;
+
+    pub fn serialize<S: Serializer>(asset: &AssetInfo, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(asset)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AssetInfo, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Serializes an `AssetMap` as a JSON object keyed by the compact form of each asset,
+/// for use with `#[serde(with = "...")]`. The order of the entries is preserved.
+pub mod compact_keys {
+    use std::{fmt, marker::PhantomData};
+
+    use serde::{
+        de::{Error, MapAccess, Visitor},
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    use super::{AssetInfo, AssetMap};
+
+    pub fn serialize<S, V>(map: &AssetMap<V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        V: Serialize,
+    {
+        serializer.collect_map(map.iter().map(|(asset, value)| (asset.to_string(), value)))
+    }
+
+    pub fn deserialize<'de, D, V>(deserializer: D) -> Result<AssetMap<V>, D::Error>
+    where
+        D: Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        deserializer.deserialize_map(AssetMapVisitor(PhantomData))
+    }
+
+    struct AssetMapVisitor<V>(PhantomData<V>);
+
+    impl<'de, V: Deserialize<'de>> Visitor<'de> for AssetMapVisitor<V> {
+        type Value = AssetMap<V>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a map keyed by compact assets")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+            let mut map = AssetMap::new();
+            while let Some((key, value)) = access.next_entry::<String, V>()? {
+                let asset: AssetInfo = key.parse().map_err(A::Error::custom)?;
+                if map.insert(asset, value).is_some() {
+                    return Err(A::Error::custom(format!("duplicate asset {key}")));
+                }
+            }
+            Ok(map)
+        }
     }
 }
 
@@ -125,6 +229,87 @@ impl<'a> KeyDeserialize for &'a AssetInfo {
     }
 }
 
+/// An `AssetInfo` as received in a message, its address and denom are not yet validated.
+/// Serializes identically to `AssetInfo`.
+#[cw_serde]
+#[derive(Eq, PartialOrd, Ord)]
+pub enum AssetInfoUnchecked {
+    NativeToken { denom: String },
+    Token { contract_addr: String },
+}
+
+pub type AssetMapUnchecked<T> = NeptuneMap<AssetInfoUnchecked, T>;
+
+impl AssetInfoUnchecked {
+    /// Validates the CW20 address or the syntax of the denom.
+    pub fn check(&self, api: &dyn Api) -> NeptuneResult<AssetInfo> {
+        Ok(match self {
+            AssetInfoUnchecked::NativeToken { denom } => {
+                validate_denom(denom)?;
+                AssetInfo::NativeToken {
+                    denom: denom.clone(),
+                }
+            }
+            AssetInfoUnchecked::Token { contract_addr } => AssetInfo::Token {
+                contract_addr: api.addr_validate(contract_addr)?,
+            },
+        })
+    }
+}
+
+impl FromStr for AssetInfoUnchecked {
+    type Err = StdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(AssetInfo::from_str(s)?.into())
+    }
+}
+
+impl From<AssetInfo> for AssetInfoUnchecked {
+    fn from(info: AssetInfo) -> Self {
+        match info {
+            AssetInfo::NativeToken { denom } => AssetInfoUnchecked::NativeToken { denom },
+            AssetInfo::Token { contract_addr } => AssetInfoUnchecked::Token {
+                contract_addr: contract_addr.into_string(),
+            },
+        }
+    }
+}
+
+impl<T> NeptuneMap<AssetInfoUnchecked, T> {
+    /// Validates every asset, erroring if two are the same.
+    pub fn check(self, api: &dyn Api) -> NeptuneResult<AssetMap<T>> {
+        let mut map = AssetMap::new();
+        for (asset, value) in self {
+            let asset = asset.check(api)?;
+            if map.contains_key(&asset) {
+                return Err(NeptuneError::DuplicateKey(asset.to_string()));
+            }
+            map.insert(asset, value);
+        }
+        Ok(map)
+    }
+}
+
+impl<T> From<AssetMap<T>> for AssetMapUnchecked<T> {
+    fn from(map: AssetMap<T>) -> Self {
+        map.into_iter().map(|(asset, value)| (asset.into(), value)).collect()
+    }
+}
+
+/// Validates a denom against the syntax accepted by the bank module:
+/// a letter followed by 2 to 127 letters, digits or any of `/:._-`.
+pub fn validate_denom(denom: &str) -> NeptuneResult<()> {
+    let mut chars = denom.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && (3..=128).contains(&denom.len())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "/:._-".contains(c));
+    if !valid {
+        return Err(NeptuneError::InvalidDenom(denom.to_string()));
+    }
+    Ok(())
+}
+
 impl KeyVec<Self> for AssetInfo {
     fn key_vec(&self) -> Vec<Self> {
         vec![self.clone()]
@@ -137,6 +322,31 @@ pub struct AssetAmount {
     pub amount: Uint256,
 }
 
+/// An `AssetAmount` as received in a message, its asset is not yet validated.
+#[cw_serde]
+pub struct AssetAmountUnchecked {
+    pub info: AssetInfoUnchecked,
+    pub amount: Uint256,
+}
+
+impl AssetAmountUnchecked {
+    pub fn check(&self, api: &dyn Api) -> NeptuneResult<AssetAmount> {
+        Ok(AssetAmount {
+            info: self.info.check(api)?,
+            amount: self.amount,
+        })
+    }
+}
+
+impl From<AssetAmount> for AssetAmountUnchecked {
+    fn from(val: AssetAmount) -> Self {
+        Self {
+            info: val.info.into(),
+            amount: val.amount,
+        }
+    }
+}
+
 impl From<AssetAmount> for (AssetInfo, Uint256) {
     fn from(val: AssetAmount) -> Self {
         (val.info, val.amount)
@@ -261,6 +471,116 @@ mod tests {
         assert_eq!(string.as_str(), token.as_str());
     }
 
+    #[test]
+    fn test_check() {
+        let deps = mock_dependencies();
+        let api = deps.as_ref().api;
+
+        let token = AssetInfoUnchecked::Token {
+            contract_addr: "contract0".to_string(),
+        };
+        assert_eq!(
+            token.check(api).unwrap(),
+            AssetInfo::Token {
+                contract_addr: Addr::unchecked("contract0")
+            }
+        );
+        let not_normalized = AssetInfoUnchecked::Token {
+            contract_addr: "Contract0".to_string(),
+        };
+        assert!(not_normalized.check(api).is_err());
+
+        for denom in [
+            "uinj",
+            "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+            "factory/inj1abc/sub.denom-1",
+        ] {
+            let native = AssetInfoUnchecked::NativeToken {
+                denom: denom.to_string(),
+            };
+            assert!(native.check(api).is_ok(), "{denom}");
+        }
+        for denom in ["", "in", "1inj", "u inj", "inj!"] {
+            let native = AssetInfoUnchecked::NativeToken {
+                denom: denom.to_string(),
+            };
+            assert_eq!(
+                native.check(api).unwrap_err(),
+                NeptuneError::InvalidDenom(denom.to_string())
+            );
+        }
+
+        let amount = AssetAmountUnchecked {
+            info: token.clone(),
+            amount: 5u64.into(),
+        };
+        let checked = amount.check(api).unwrap();
+        assert_eq!(AssetAmountUnchecked::from(checked), amount);
+
+        // Unchecked assets are read from the same JSON as checked ones.
+        let json = serde_json_wasm::to_string(&token.check(api).unwrap()).unwrap();
+        let parsed: AssetInfoUnchecked = serde_json_wasm::from_str(&json).unwrap();
+        assert_eq!(parsed, token);
+
+        let native = AssetInfoUnchecked::NativeToken {
+            denom: "uinj".to_string(),
+        };
+        let map: AssetMapUnchecked<u64> = vec![(native.clone(), 1), (token.clone(), 2)].into();
+        let checked = map.clone().check(api).unwrap();
+        assert_eq!(AssetMapUnchecked::from(checked), map);
+
+        let duplicate: AssetMapUnchecked<u64> = vec![(native.clone(), 1), (native, 2)].into();
+        assert!(matches!(
+            duplicate.check(api),
+            Err(NeptuneError::DuplicateKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_compact_form() {
+        let native = AssetInfo::NativeToken {
+            denom: "factory/inj1abc/nept".to_string(),
+        };
+        let token = AssetInfo::Token {
+            contract_addr: Addr::unchecked("inj1qyqszqgpqyqszqgpqyqszqgpqyqszqgpc6k6ly"),
+        };
+        assert_eq!(native.to_string(), "native:factory/inj1abc/nept");
+        assert_eq!(
+            token.to_string(),
+            "cw20:inj1qyqszqgpqyqszqgpqyqszqgpqyqszqgpc6k6ly"
+        );
+        for asset in [&native, &token] {
+            assert_eq!(&asset.to_string().parse::<AssetInfo>().unwrap(), asset);
+        }
+
+        // The JSON form is still accepted.
+        let json = serde_json_wasm::to_string(&native).unwrap();
+        assert_eq!(json.parse::<AssetInfo>().unwrap(), native);
+
+        for invalid in ["", "uinj", "native:", "erc20:0xabc", "{\"native_token\":{}}"] {
+            assert!(invalid.parse::<AssetInfo>().is_err(), "{invalid}");
+        }
+
+        #[cw_serde]
+        struct Config {
+            #[serde(with = "compact")]
+            asset: AssetInfo,
+            #[serde(with = "compact_keys")]
+            limits: AssetMap<Uint256>,
+        }
+
+        let config = Config {
+            asset: token.clone(),
+            limits: vec![(token, 1u64.into()), (native, 2u64.into())].into(),
+        };
+        let json = serde_json_wasm::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            r#"{"asset":"cw20:inj1qyqszqgpqyqszqgpqyqszqgpqyqszqgpc6k6ly","limits":{"cw20:inj1qyqszqgpqyqszqgpqyqszqgpqyqszqgpc6k6ly":"1","native:factory/inj1abc/nept":"2"}}"#
+        );
+        assert_eq!(serde_json_wasm::from_str::<Config>(&json).unwrap(), config);
+    }
+
     #[test]
     fn test_coin_conversion() {
         let coin = Coin {
@@ -0,0 +1,330 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{CustomQuery, Deps, DepsMut, QuerierWrapper, StdError, Uint256};
+use cw_storage_plus::Map;
+
+#[cfg(feature = "metadata")]
+use crate::querier::query_denom_metadata;
+use crate::{
+    asset::{AssetAmount, AssetInfo},
+    error::{NeptuneError, NeptuneResult},
+    querier::query_token_info,
+};
+
+/// The decimals of the common representation which amounts of different assets are normalized to.
+pub const NORMALIZED_DECIMALS: u8 = 18;
+
+/// The metadata of each asset, cached the first time it is loaded.
+pub const ASSET_METADATA: Map<&AssetInfo, AssetMetadata> = Map::new("asset_metadata");
+
+/// The symbol and decimals of an asset.
+#[cw_serde]
+pub struct AssetMetadata {
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl AssetMetadata {
+    /// Scales an amount of the asset to [`NORMALIZED_DECIMALS`], rounding down.
+    pub fn normalize(&self, amount: Uint256) -> NeptuneResult<Uint256> {
+        scale(amount, self.decimals, NORMALIZED_DECIMALS)
+    }
+
+    /// Scales an amount in [`NORMALIZED_DECIMALS`] to the asset's decimals, rounding down.
+    pub fn denormalize(&self, amount: Uint256) -> NeptuneResult<Uint256> {
+        scale(amount, NORMALIZED_DECIMALS, self.decimals)
+    }
+}
+
+/// Queries the metadata of a cw20 from its token info,
+/// or of a coin from the bank module's display unit which requires the `metadata` feature.
+pub fn query_asset_metadata(
+    querier: QuerierWrapper<impl CustomQuery>,
+    asset: &AssetInfo,
+) -> NeptuneResult<AssetMetadata> {
+    match asset {
+        AssetInfo::Token { contract_addr } => {
+            let token_info = query_token_info(querier, contract_addr)?;
+            Ok(AssetMetadata {
+                symbol: token_info.symbol,
+                decimals: token_info.decimals,
+            })
+        }
+        #[cfg(not(feature = "metadata"))]
+        AssetInfo::NativeToken { denom } => Err(NeptuneError::Generic(format!(
+            "querying the metadata of {denom} requires the metadata feature"
+        ))),
+        #[cfg(feature = "metadata")]
+        AssetInfo::NativeToken { denom } => {
+            let metadata = query_denom_metadata(querier, denom.clone())?;
+            let exponent = metadata
+                .denom_units
+                .iter()
+                .find(|unit| unit.denom == metadata.display)
+                .map(|unit| unit.exponent)
+                .ok_or_else(|| NeptuneError::KeyNotFound(format!("display unit of {denom}")))?;
+            let symbol = if metadata.symbol.is_empty() {
+                metadata.display
+            } else {
+                metadata.symbol
+            };
+            let decimals = exponent.try_into().map_err(|_| {
+                NeptuneError::Conversion(format!("{denom} has {exponent} decimals"))
+            })?;
+            Ok(AssetMetadata { symbol, decimals })
+        }
+    }
+}
+
+/// Loads the metadata of an asset, querying and caching it on the first load.
+pub fn load_asset_metadata(
+    deps: DepsMut<'_, impl CustomQuery>,
+    asset: &AssetInfo,
+) -> NeptuneResult<AssetMetadata> {
+    if let Some(metadata) = ASSET_METADATA.may_load(deps.storage, asset)? {
+        return Ok(metadata);
+    }
+    let metadata = query_asset_metadata(deps.querier, asset)?;
+    ASSET_METADATA.save(deps.storage, asset, &metadata)?;
+    Ok(metadata)
+}
+
+/// Reads the cached metadata of an asset, querying it without caching if it was never loaded.
+pub fn read_asset_metadata(
+    deps: Deps<'_, impl CustomQuery>,
+    asset: &AssetInfo,
+) -> NeptuneResult<AssetMetadata> {
+    match ASSET_METADATA.may_load(deps.storage, asset)? {
+        Some(metadata) => Ok(metadata),
+        None => query_asset_metadata(deps.querier, asset),
+    }
+}
+
+/// Scales an amount to [`NORMALIZED_DECIMALS`] using the asset's cached metadata.
+pub fn normalize(
+    deps: DepsMut<'_, impl CustomQuery>,
+    amount: &AssetAmount,
+) -> NeptuneResult<Uint256> {
+    load_asset_metadata(deps, &amount.info)?.normalize(amount.amount)
+}
+
+/// Scales an amount in [`NORMALIZED_DECIMALS`] to an amount of the asset,
+/// using its cached metadata.
+pub fn denormalize(
+    deps: DepsMut<'_, impl CustomQuery>,
+    asset: &AssetInfo,
+    amount: Uint256,
+) -> NeptuneResult<AssetAmount> {
+    Ok(AssetAmount {
+        info: asset.clone(),
+        amount: load_asset_metadata(deps, asset)?.denormalize(amount)?,
+    })
+}
+
+/// Scales an amount to [`NORMALIZED_DECIMALS`] without caching the asset's metadata.
+pub fn normalize_readonly(
+    deps: Deps<'_, impl CustomQuery>,
+    amount: &AssetAmount,
+) -> NeptuneResult<Uint256> {
+    read_asset_metadata(deps, &amount.info)?.normalize(amount.amount)
+}
+
+/// Scales an amount in [`NORMALIZED_DECIMALS`] to an amount of the asset
+/// without caching its metadata.
+pub fn denormalize_readonly(
+    deps: Deps<'_, impl CustomQuery>,
+    asset: &AssetInfo,
+    amount: Uint256,
+) -> NeptuneResult<AssetAmount> {
+    Ok(AssetAmount {
+        info: asset.clone(),
+        amount: read_asset_metadata(deps, asset)?.denormalize(amount)?,
+    })
+}
+
+/// Multiplies `amount` by `10^(to - from)`, rounding down.
+fn scale(amount: Uint256, from: u8, to: u8) -> NeptuneResult<Uint256> {
+    let factor = Uint256::from(10u8)
+        .checked_pow(from.abs_diff(to).into())
+        .map_err(StdError::from)?;
+    Ok(if to >= from {
+        amount.checked_mul(factor).map_err(StdError::from)?
+    } else {
+        amount / factor
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{
+        from_json,
+        testing::{mock_dependencies, MockQuerier},
+        to_json_binary, Addr, ContractResult, Empty, SystemResult, Uint128, WasmQuery,
+    };
+    #[cfg(feature = "metadata")]
+    use cosmwasm_std::{DenomMetadata, DenomUnit};
+    use cw20::{Cw20QueryMsg, TokenInfoResponse};
+
+    use super::*;
+
+    fn mock_querier() -> MockQuerier<Empty> {
+        let mut querier = MockQuerier::new(&[]);
+        #[cfg(feature = "metadata")]
+        querier.set_denom_metadata(&[DenomMetadata {
+            description: String::new(),
+            denom_units: vec![
+                DenomUnit {
+                    denom: "uatom".to_string(),
+                    exponent: 0,
+                    aliases: vec![],
+                },
+                DenomUnit {
+                    denom: "atom".to_string(),
+                    exponent: 6,
+                    aliases: vec![],
+                },
+            ],
+            base: "uatom".to_string(),
+            display: "atom".to_string(),
+            name: "Cosmos Hub Atom".to_string(),
+            symbol: "ATOM".to_string(),
+            uri: String::new(),
+            uri_hash: String::new(),
+        }]);
+        querier.update_wasm(|query| {
+            let WasmQuery::Smart { msg, .. } = query else {
+                panic!("unexpected query {query:?}");
+            };
+            let Cw20QueryMsg::TokenInfo {} = from_json::<Cw20QueryMsg>(msg).unwrap() else {
+                panic!("unexpected query {query:?}");
+            };
+            SystemResult::Ok(ContractResult::Ok(
+                to_json_binary(&TokenInfoResponse {
+                    name: "Wrapped Ether".to_string(),
+                    symbol: "WETH".to_string(),
+                    decimals: 8,
+                    total_supply: Uint128::zero(),
+                })
+                .unwrap(),
+            ))
+        });
+        querier
+    }
+
+    fn atom() -> AssetInfo {
+        AssetInfo::NativeToken {
+            denom: "uatom".to_string(),
+        }
+    }
+
+    fn weth() -> AssetInfo {
+        AssetInfo::Token {
+            contract_addr: Addr::unchecked("weth"),
+        }
+    }
+
+    #[test]
+    fn test_asset_metadata() {
+        let mut deps = mock_dependencies();
+        deps.querier = mock_querier();
+        let weth = weth();
+
+        let normalized = normalize(
+            deps.as_mut(),
+            &AssetAmount {
+                info: weth.clone(),
+                amount: 150_000_000u64.into(),
+            },
+        )
+        .unwrap();
+        assert_eq!(normalized, Uint256::from(1_500_000_000_000_000_000u128));
+        let amount = denormalize(deps.as_mut(), &weth, normalized + Uint256::one()).unwrap();
+        assert_eq!(amount.amount, Uint256::from(150_000_000u64));
+        let metadata = ASSET_METADATA.load(&deps.storage, &weth).unwrap();
+        assert_eq!(
+            metadata,
+            AssetMetadata {
+                symbol: "WETH".to_string(),
+                decimals: 8,
+            }
+        );
+
+        // Cached metadata is loaded without querying.
+        deps.querier = MockQuerier::new(&[]);
+        assert_eq!(load_asset_metadata(deps.as_mut(), &weth).unwrap(), metadata);
+
+        let precise = AssetMetadata {
+            symbol: "PRECISE".to_string(),
+            decimals: 24,
+        };
+        assert_eq!(
+            precise.normalize(Uint256::from(1_234_567u64)).unwrap(),
+            Uint256::one()
+        );
+        assert_eq!(
+            precise.denormalize(Uint256::one()).unwrap(),
+            Uint256::from(1_000_000u64)
+        );
+    }
+
+    #[test]
+    fn test_readonly_metadata() {
+        let mut deps = mock_dependencies();
+        deps.querier = mock_querier();
+        let weth = weth();
+        let amount = AssetAmount {
+            info: weth.clone(),
+            amount: 150_000_000u64.into(),
+        };
+
+        // Metadata which was never loaded is queried but not cached.
+        let normalized = normalize_readonly(deps.as_ref(), &amount).unwrap();
+        assert_eq!(normalized, Uint256::from(1_500_000_000_000_000_000u128));
+        assert_eq!(
+            denormalize_readonly(deps.as_ref(), &weth, normalized).unwrap(),
+            amount
+        );
+        assert!(!ASSET_METADATA.has(&deps.storage, &weth));
+
+        // Cached metadata takes precedence over the query.
+        let cached = AssetMetadata {
+            symbol: "WETH".to_string(),
+            decimals: 18,
+        };
+        ASSET_METADATA
+            .save(&mut deps.storage, &weth, &cached)
+            .unwrap();
+        assert_eq!(read_asset_metadata(deps.as_ref(), &weth).unwrap(), cached);
+        assert_eq!(
+            normalize_readonly(deps.as_ref(), &amount).unwrap(),
+            amount.amount
+        );
+    }
+
+    #[cfg(feature = "metadata")]
+    #[test]
+    fn test_native_metadata() {
+        let mut deps = mock_dependencies();
+        deps.querier = mock_querier();
+        let atom = atom();
+
+        let metadata = load_asset_metadata(deps.as_mut(), &atom).unwrap();
+        assert_eq!(
+            metadata,
+            AssetMetadata {
+                symbol: "ATOM".to_string(),
+                decimals: 6,
+            }
+        );
+        assert_eq!(ASSET_METADATA.load(&deps.storage, &atom).unwrap(), metadata);
+    }
+
+    #[cfg(not(feature = "metadata"))]
+    #[test]
+    fn test_native_metadata_requires_feature() {
+        let deps = mock_dependencies();
+        assert!(matches!(
+            read_asset_metadata(deps.as_ref(), &atom()),
+            Err(NeptuneError::Generic(_))
+        ));
+    }
+}
@@ -25,12 +25,24 @@ pub enum NeptuneError {
     #[error("Key not found: {0}")]
     KeyNotFound(String),
 
+    #[error("Duplicate key: {0}")]
+    DuplicateKey(String),
+
+    #[error("Invalid denom: {0}")]
+    InvalidDenom(String),
+
     #[error("Only one tx is allowed per block")]
     MultipleTx,
 
     #[error("Missing Cw20HookMg")]
     MissingHookMsg,
 
+    #[error("Price not found: {0}")]
+    PriceNotFound(String),
+
+    #[error("Price of {asset} is stale, last updated at {timestamp}")]
+    StalePrice { asset: String, timestamp: u64 },
+
     #[error("{0}")]
     Conversion(String),
 
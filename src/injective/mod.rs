@@ -0,0 +1,125 @@
+pub mod oracle;
+pub mod subaccount;
+
+use cosmwasm_std::{Decimal, Decimal256, SignedDecimal256, Uint128, Uint256};
+use injective_math::FPDecimal;
+use std::str::FromStr;
+
+use crate::error::{NeptuneError, NeptuneResult};
+
+pub fn into_fp_decimal(value: Decimal256) -> FPDecimal {
+    let atomics = value.atomics().to_be_bytes();
+    FPDecimal {
+        num: atomics.into(),
+        sign: 1,
+    }
+}
+
+pub fn decimal_into_fp_decimal(value: Decimal) -> FPDecimal {
+    into_fp_decimal(value.into())
+}
+
+pub fn signed_into_fp_decimal(value: SignedDecimal256) -> FPDecimal {
+    let abs = into_fp_decimal(Decimal256::new(value.atomics().unsigned_abs()));
+    if value.is_negative() {
+        -abs
+    } else {
+        abs
+    }
+}
+
+pub fn uint_128_into_fp_decimal(value: Uint128) -> FPDecimal {
+    Uint256::from(value).into()
+}
+
+/// Panics on negative values, see [`try_into_decimal_256`].
+pub fn into_decimal_256(value: FPDecimal) -> Decimal256 {
+    if value.is_negative() {
+        panic!("Negative value can't be converted")
+    }
+    let atomics: [u8; 32] = value.num.into();
+    Decimal256::new(Uint256::from_be_bytes(atomics))
+}
+
+/// Panics on negative or fractional values, see [`try_into_uint_256`].
+pub fn into_uint_256(value: FPDecimal) -> Uint256 {
+    // Error for negative values handled implicitly here.
+    Uint256::from_str(&value.to_string()).unwrap()
+}
+
+/// Errors on negative values.
+pub fn try_into_decimal_256(value: FPDecimal) -> NeptuneResult<Decimal256> {
+    if value.is_negative() && !value.is_zero() {
+        return Err(NeptuneError::Conversion(format!(
+            "negative value {value} can't be converted to Decimal256"
+        )));
+    }
+    let atomics: [u8; 32] = value.num.into();
+    Ok(Decimal256::new(Uint256::from_be_bytes(atomics)))
+}
+
+/// Errors on negative values or values out of range.
+pub fn try_into_decimal(value: FPDecimal) -> NeptuneResult<Decimal> {
+    Decimal::try_from(try_into_decimal_256(value)?)
+        .map_err(|err| NeptuneError::Conversion(err.to_string()))
+}
+
+/// Errors on values out of range.
+pub fn try_into_signed_decimal_256(value: FPDecimal) -> NeptuneResult<SignedDecimal256> {
+    SignedDecimal256::from_str(&value.to_string())
+        .map_err(|err| NeptuneError::Conversion(err.to_string()))
+}
+
+/// Errors on negative or fractional values, use `FPDecimal::int` to round down first.
+pub fn try_into_uint_256(value: FPDecimal) -> NeptuneResult<Uint256> {
+    Uint256::from_str(&value.to_string())
+        .map_err(|_| NeptuneError::Conversion(format!("{value} can't be converted to Uint256")))
+}
+
+/// Errors on negative or fractional values, or values out of range.
+pub fn try_into_uint_128(value: FPDecimal) -> NeptuneResult<Uint128> {
+    Ok(Uint128::try_from(try_into_uint_256(value)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_fp_decimal() {
+        let string = "23423498725.1238476198263".to_string();
+        let dec_256 = Decimal256::from_str(string.as_str()).unwrap();
+        let fp_dec: FPDecimal = into_fp_decimal(dec_256);
+        assert_eq!(fp_dec, FPDecimal::from_str(string.as_str()).unwrap());
+    }
+
+    #[test]
+    fn test_into_decimal_256() {
+        let string = "23423498725.1238476198263".to_string();
+        let fp_dec = FPDecimal::from_str(string.as_str()).unwrap();
+        let dec_256: Decimal256 = into_decimal_256(fp_dec);
+        assert_eq!(dec_256, Decimal256::from_str(string.as_str()).unwrap());
+    }
+
+    #[test]
+    fn test_try_conversions() {
+        let negative = FPDecimal::from_str("-1.5").unwrap();
+        assert!(try_into_decimal_256(negative).is_err());
+        assert!(try_into_uint_256(negative).is_err());
+        assert_eq!(try_into_decimal_256(FPDecimal::ZERO).unwrap(), Decimal256::zero());
+
+        let signed = try_into_signed_decimal_256(negative).unwrap();
+        assert_eq!(signed, SignedDecimal256::from_str("-1.5").unwrap());
+        assert_eq!(signed_into_fp_decimal(signed), negative);
+
+        let decimal = Decimal::from_str("2.25").unwrap();
+        let fp_dec = decimal_into_fp_decimal(decimal);
+        assert_eq!(try_into_decimal(fp_dec).unwrap(), decimal);
+
+        assert_eq!(try_into_uint_128(FPDecimal::from(7u128)).unwrap(), Uint128::new(7));
+        assert!(try_into_uint_128(FPDecimal::from_str("7.5").unwrap()).is_err());
+        assert_eq!(uint_128_into_fp_decimal(Uint128::new(7)), FPDecimal::from(7u128));
+        let overflow = FPDecimal::from(u128::MAX) * FPDecimal::from(10u128);
+        assert!(try_into_uint_128(overflow.int()).is_err());
+    }
+}
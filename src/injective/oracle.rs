@@ -0,0 +1,247 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Decimal256, Deps, Env};
+use injective_cosmwasm::{InjectiveQuerier, OracleType};
+
+use crate::{
+    asset::{AssetInfo, AssetMap},
+    error::{NeptuneError, NeptuneResult},
+    query_wrapper::QueryWrapper,
+};
+
+use super::try_into_decimal_256;
+
+/// An oracle of the Injective oracle module which an asset is priced by.
+#[cw_serde]
+pub enum PriceSource {
+    /// A Pyth price feed, identified by its hex encoded price id.
+    Pyth { price_id: String },
+    /// A Band price of a symbol relayed over IBC, quoted in USD.
+    Band { symbol: String },
+    /// A price-feed price of a base symbol quoted in another.
+    PriceFeed { base: String, quote: String },
+}
+
+/// A price along with the block time at which the oracle last updated it, in seconds.
+#[cw_serde]
+pub struct OraclePrice {
+    pub price: Decimal256,
+    pub timestamp: u64,
+}
+
+impl PriceSource {
+    /// Queries the latest price of the source, regardless of its age.
+    pub fn query_price(&self, deps: Deps<QueryWrapper>) -> NeptuneResult<OraclePrice> {
+        let querier = InjectiveQuerier::new(&deps.querier);
+        match self {
+            PriceSource::Pyth { price_id } => {
+                let state = querier
+                    .query_pyth_price(price_id)?
+                    .price_state
+                    .ok_or_else(|| NeptuneError::PriceNotFound(price_id.clone()))?;
+                Ok(OraclePrice {
+                    price: try_into_decimal_256(state.price_state.price)?,
+                    timestamp: timestamp(state.price_state.timestamp),
+                })
+            }
+            PriceSource::Band { symbol } => {
+                let state = querier
+                    .query_oracle_price(&OracleType::BandIBC, symbol, "USD")?
+                    .price_pair_state
+                    .ok_or_else(|| NeptuneError::PriceNotFound(symbol.clone()))?;
+                // The quote is USD which is never updated.
+                Ok(OraclePrice {
+                    price: try_into_decimal_256(state.pair_price)?,
+                    timestamp: timestamp(state.base_timestamp),
+                })
+            }
+            PriceSource::PriceFeed { base, quote } => {
+                let state = querier
+                    .query_oracle_price(&OracleType::PriceFeed, base, quote)?
+                    .price_pair_state
+                    .ok_or_else(|| NeptuneError::PriceNotFound(format!("{base}/{quote}")))?;
+                // A USD quote is never updated, nor is any quote with a zero timestamp.
+                let updated = if quote == "USD" || state.quote_timestamp == 0 {
+                    state.base_timestamp
+                } else {
+                    state.base_timestamp.min(state.quote_timestamp)
+                };
+                Ok(OraclePrice {
+                    price: try_into_decimal_256(state.pair_price)?,
+                    timestamp: timestamp(updated),
+                })
+            }
+        }
+    }
+
+    /// Queries the latest price of the source,
+    /// failing if it was last updated more than `max_age` seconds ago.
+    pub fn query_fresh_price(
+        &self,
+        deps: Deps<QueryWrapper>,
+        env: &Env,
+        asset: &AssetInfo,
+        max_age: u64,
+    ) -> NeptuneResult<Decimal256> {
+        let OraclePrice { price, timestamp } = self.query_price(deps)?;
+        if env.block.time.seconds().saturating_sub(timestamp) > max_age {
+            return Err(NeptuneError::StalePrice {
+                asset: asset.to_string(),
+                timestamp,
+            });
+        }
+        Ok(price)
+    }
+}
+
+/// Queries the price of each asset from its source,
+/// failing if any was last updated more than `max_age` seconds ago.
+/// Prices are of a whole unit of each asset, not of its smallest denomination.
+pub fn query_prices(
+    deps: Deps<QueryWrapper>,
+    env: &Env,
+    sources: &AssetMap<PriceSource>,
+    max_age: u64,
+) -> NeptuneResult<AssetMap<Decimal256>> {
+    sources
+        .iter()
+        .map(|(asset, source)| {
+            let price = source.query_fresh_price(deps, env, asset, max_age)?;
+            Ok((asset.clone(), price))
+        })
+        .collect()
+}
+
+/// Oracle timestamps are signed, a negative one is treated as never updated.
+fn timestamp(timestamp: i64) -> u64 {
+    u64::try_from(timestamp).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{
+        testing::{mock_env, MockApi, MockQuerier, MockStorage},
+        Binary, ContractResult, OwnedDeps, SystemResult, Timestamp,
+    };
+    use injective_cosmwasm::InjectiveQuery;
+
+    use super::*;
+    use crate::testing::mock_dependencies;
+
+    /// Answers Pyth prices and oracle prices keyed by their base symbol.
+    /// `WSTETH` is priced in a quote updated at 800, other bases in a quote never updated.
+    /// `NEGATIVE` has a negative price.
+    fn mock_oracle() -> OwnedDeps<MockStorage, MockApi, MockQuerier<QueryWrapper>, QueryWrapper> {
+        let mut deps = mock_dependencies();
+        deps.querier = deps.querier.with_custom_handler(|query| {
+            let res: &[u8] = match &query.query_data {
+                InjectiveQuery::PythPrice { .. } => {
+                    br#"{"price_state":{"price_id":"0x01","ema_price":"10.1","ema_conf":"0","conf":"0","publish_time":1000,"price_state":{"price":"10.5","cumulative_price":"0","timestamp":1000}}}"#
+                }
+                InjectiveQuery::OraclePrice { base, .. } if base == "MISSING" => {
+                    br#"{"price_pair_state":null}"#
+                }
+                InjectiveQuery::OraclePrice { base, .. } if base == "NEGATIVE" => {
+                    br#"{"price_pair_state":{"pair_price":"-1","base_price":"-1","quote_price":"1","base_cumulative_price":"0","quote_cumulative_price":"0","base_timestamp":1000,"quote_timestamp":1000}}"#
+                }
+                InjectiveQuery::OraclePrice { base, .. } if base == "WSTETH" => {
+                    br#"{"price_pair_state":{"pair_price":"1.15","base_price":"1.15","quote_price":"1","base_cumulative_price":"0","quote_cumulative_price":"0","base_timestamp":1000,"quote_timestamp":800}}"#
+                }
+                InjectiveQuery::OraclePrice { .. } => {
+                    br#"{"price_pair_state":{"pair_price":"1.0001","base_price":"1.0001","quote_price":"1","base_cumulative_price":"0","quote_cumulative_price":"0","base_timestamp":900,"quote_timestamp":0}}"#
+                }
+                _ => br#"{}"#,
+            };
+            SystemResult::Ok(ContractResult::Ok(Binary::from(res.to_vec())))
+        });
+        deps
+    }
+
+    #[test]
+    fn test_query_prices() {
+        let deps = mock_oracle();
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(1060);
+        let (inj, usdt) = (
+            AssetInfo::NativeToken {
+                denom: "inj".to_string(),
+            },
+            AssetInfo::NativeToken {
+                denom: "usdt".to_string(),
+            },
+        );
+        let sources: AssetMap<PriceSource> = vec![
+            (
+                inj.clone(),
+                PriceSource::Pyth {
+                    price_id: "0x01".to_string(),
+                },
+            ),
+            (
+                usdt.clone(),
+                PriceSource::Band {
+                    symbol: "USDT".to_string(),
+                },
+            ),
+        ]
+        .into();
+
+        let prices = query_prices(deps.as_ref(), &env, &sources, 300).unwrap();
+        assert_eq!(prices.get(&inj), Some(&Decimal256::percent(1050)));
+        assert_eq!(
+            prices.get(&usdt),
+            Some(&Decimal256::from_ratio(10001u64, 10000u64))
+        );
+
+        // The Band price was updated 160 seconds ago.
+        let err = query_prices(deps.as_ref(), &env, &sources, 120).unwrap_err();
+        assert_eq!(
+            err,
+            NeptuneError::StalePrice {
+                asset: usdt.to_string(),
+                timestamp: 900,
+            }
+        );
+    }
+
+    #[test]
+    fn test_price_feed() {
+        let deps = mock_oracle();
+        let price_feed = |base: &str, quote: &str| {
+            PriceSource::PriceFeed {
+                base: base.to_string(),
+                quote: quote.to_string(),
+            }
+            .query_price(deps.as_ref())
+        };
+
+        // The quote was last updated before the base.
+        assert_eq!(
+            price_feed("WSTETH", "ETH").unwrap(),
+            OraclePrice {
+                price: Decimal256::percent(115),
+                timestamp: 800,
+            }
+        );
+        // A USD quote is never updated.
+        assert_eq!(price_feed("WSTETH", "USD").unwrap().timestamp, 1000);
+        // Neither is a quote with a zero timestamp.
+        assert_eq!(price_feed("USDT", "USDC").unwrap().timestamp, 900);
+
+        assert_eq!(
+            price_feed("MISSING", "USD").unwrap_err(),
+            NeptuneError::PriceNotFound("MISSING/USD".to_string())
+        );
+        let err = PriceSource::Band {
+            symbol: "MISSING".to_string(),
+        }
+        .query_price(deps.as_ref())
+        .unwrap_err();
+        assert_eq!(err, NeptuneError::PriceNotFound("MISSING".to_string()));
+
+        // Negative prices are rejected rather than read as their absolute value.
+        assert!(matches!(
+            price_feed("NEGATIVE", "USD").unwrap_err(),
+            NeptuneError::Conversion(_)
+        ));
+    }
+}
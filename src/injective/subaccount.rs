@@ -0,0 +1,148 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, Coin, CosmosMsg, Deps, QueryRequest, Uint256};
+use injective_cosmwasm::{
+    checked_address_to_subaccount_id, InjectiveMsg, InjectiveQuery, InjectiveRoute,
+    SubaccountDepositResponse, SubaccountId,
+};
+
+use crate::{
+    asset::{AssetInfo, AssetMap},
+    error::NeptuneResult,
+    msg_wrapper::MsgWrapper,
+    query_wrapper::QueryWrapper,
+};
+
+use super::try_into_uint_256;
+
+/// Returns the id of an address's subaccount, the default subaccount has a nonce of zero.
+/// Exchange orders from the default subaccount are funded from the bank balance,
+/// other subaccounts must hold deposits.
+pub fn subaccount_id(addr: &Addr, nonce: u32) -> SubaccountId {
+    checked_address_to_subaccount_id(addr, nonce)
+}
+
+/// Moves funds from the sender's bank balance to one of its subaccounts.
+pub fn deposit(sender: &Addr, subaccount_id: SubaccountId, amount: Coin) -> CosmosMsg<MsgWrapper> {
+    exchange_msg(InjectiveMsg::Deposit {
+        sender: sender.clone(),
+        subaccount_id,
+        amount,
+    })
+}
+
+/// Moves funds from one of the sender's subaccounts to its bank balance.
+pub fn withdraw(sender: &Addr, subaccount_id: SubaccountId, amount: Coin) -> CosmosMsg<MsgWrapper> {
+    exchange_msg(InjectiveMsg::Withdraw {
+        sender: sender.clone(),
+        subaccount_id,
+        amount,
+    })
+}
+
+/// Moves funds between two of the sender's subaccounts.
+pub fn transfer(
+    sender: &Addr,
+    source_subaccount_id: SubaccountId,
+    destination_subaccount_id: SubaccountId,
+    amount: Coin,
+) -> CosmosMsg<MsgWrapper> {
+    exchange_msg(InjectiveMsg::SubaccountTransfer {
+        sender: sender.clone(),
+        source_subaccount_id,
+        destination_subaccount_id,
+        amount,
+    })
+}
+
+/// The deposits of a subaccount.
+#[cw_serde]
+pub struct SubaccountBalances {
+    /// The deposits which are not locked by resting orders.
+    pub available: AssetMap<Uint256>,
+    pub total: AssetMap<Uint256>,
+}
+
+/// Queries the deposits of a subaccount in each of the given denoms.
+pub fn query_balances(
+    deps: Deps<QueryWrapper>,
+    subaccount_id: &SubaccountId,
+    denoms: &[String],
+) -> NeptuneResult<SubaccountBalances> {
+    let mut balances = SubaccountBalances {
+        available: AssetMap::new(),
+        total: AssetMap::new(),
+    };
+    for denom in denoms {
+        let wrapper = QueryWrapper {
+            route: InjectiveRoute::Exchange,
+            query_data: InjectiveQuery::SubaccountDeposit {
+                subaccount_id: subaccount_id.clone(),
+                denom: denom.clone(),
+            },
+        };
+        let res: SubaccountDepositResponse = deps.querier.query(&QueryRequest::Custom(wrapper))?;
+        let asset = AssetInfo::NativeToken {
+            denom: denom.clone(),
+        };
+        balances.available.insert(
+            asset.clone(),
+            try_into_uint_256(res.deposits.available_balance.int())?,
+        );
+        balances
+            .total
+            .insert(asset, try_into_uint_256(res.deposits.total_balance.int())?);
+    }
+    Ok(balances)
+}
+
+fn exchange_msg(msg_data: InjectiveMsg) -> CosmosMsg<MsgWrapper> {
+    CosmosMsg::Custom(MsgWrapper {
+        route: InjectiveRoute::Exchange,
+        msg_data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{Binary, ContractResult, SystemResult};
+
+    use super::*;
+    use crate::testing::{mock_dependencies, MOCK_CONTRACT};
+
+    #[test]
+    fn test_subaccounts() {
+        let contract = Addr::unchecked(MOCK_CONTRACT);
+        let default_subaccount = subaccount_id(&contract, 0);
+        let trading_subaccount = subaccount_id(&contract, 1);
+        assert_ne!(default_subaccount, trading_subaccount);
+
+        let msg = transfer(
+            &contract,
+            default_subaccount,
+            trading_subaccount.clone(),
+            Coin::new(100, "inj"),
+        );
+        let CosmosMsg::Custom(MsgWrapper {
+            msg_data: InjectiveMsg::SubaccountTransfer { amount, .. },
+            ..
+        }) = &msg
+        else {
+            panic!("expected a subaccount transfer, got {msg:?}");
+        };
+        assert_eq!(amount, &Coin::new(100, "inj"));
+
+        let mut deps = mock_dependencies();
+        deps.querier = deps.querier.with_custom_handler(|_| {
+            SystemResult::Ok(ContractResult::Ok(Binary::from(
+                br#"{"deposits":{"available_balance":"100.5","total_balance":"150"}}"#.to_vec(),
+            )))
+        });
+        let balances =
+            query_balances(deps.as_ref(), &trading_subaccount, &["inj".to_string()]).unwrap();
+        let inj = AssetInfo::NativeToken {
+            denom: "inj".to_string(),
+        };
+        assert_eq!(balances.available.get(&inj), Some(&Uint256::from(100u64)));
+        assert_eq!(balances.total.get(&inj), Some(&Uint256::from(150u64)));
+    }
+}
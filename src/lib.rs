@@ -1,4 +1,5 @@
 pub mod asset;
+pub mod asset_metadata;
 pub mod debug;
 pub mod error;
 pub mod math;
@@ -12,6 +13,9 @@ pub mod storage;
 pub mod traits;
 pub mod utilities;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod testing;
+
 #[cfg(feature = "swap")]
 pub mod astroport;
 
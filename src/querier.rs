@@ -2,6 +2,8 @@ use cosmwasm_std::{
     to_json_binary, Addr, BalanceResponse, BankQuery, CustomQuery, QuerierWrapper, QueryRequest,
     Uint256, WasmQuery,
 };
+#[cfg(feature = "metadata")]
+use cosmwasm_std::{DenomMetadata, DenomMetadataResponse};
 use cw20::{BalanceResponse as Cw20BalanceResponse, Cw20QueryMsg, TokenInfoResponse};
 
 use crate::{asset::AssetInfo, error::NeptuneError};
@@ -39,12 +41,31 @@ pub fn query_supply(
     querier: QuerierWrapper<impl CustomQuery>,
     contract_addr: &Addr,
 ) -> Result<Uint256, NeptuneError> {
-    let token_info: TokenInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+    let token_info = query_token_info(querier, contract_addr)?;
+
+    Ok(token_info.total_supply.into())
+}
+
+/// Queries the name, symbol, decimals and supply of a cw20 token.
+pub fn query_token_info(
+    querier: QuerierWrapper<impl CustomQuery>,
+    contract_addr: &Addr,
+) -> Result<TokenInfoResponse, NeptuneError> {
+    Ok(querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
         contract_addr: contract_addr.to_string(),
         msg: to_json_binary(&Cw20QueryMsg::TokenInfo {})?,
-    }))?;
+    }))?)
+}
 
-    Ok(token_info.total_supply.into())
+/// Queries the bank module's metadata of a coin.
+#[cfg(feature = "metadata")]
+pub fn query_denom_metadata(
+    querier: QuerierWrapper<impl CustomQuery>,
+    denom: String,
+) -> Result<DenomMetadata, NeptuneError> {
+    let res: DenomMetadataResponse =
+        querier.query(&QueryRequest::Bank(BankQuery::DenomMetadata { denom }))?;
+    Ok(res.metadata)
 }
 
 /// Queries the balance of an asset for a specific account.
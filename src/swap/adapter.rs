@@ -0,0 +1,421 @@
+use cosmwasm_schema::{cw_serde, QueryResponses};
+use cosmwasm_std::{to_json_binary, Addr, Decimal256, Deps, Env, Uint256};
+
+use crate::{
+    asset::{AssetAmount, AssetInfo},
+    error::NeptuneResult,
+    query_wrapper::QueryWrapper,
+    send_asset::send_assets,
+};
+
+use super::{plan::SwapPlan, slippage::Slippage, Swap};
+
+/// A venue implemented by an external contract which supports the swap adapter interface.
+#[cw_serde]
+pub struct Adapter {
+    pub addr: Addr,
+}
+
+/// The messages a swap adapter must execute.
+/// The offer asset is attached as funds, or sent along with the message as a cw20 `Send`.
+#[cw_serde]
+pub enum AdapterExecuteMsg {
+    /// Swaps all of the attached offer asset, failing if less than `min_receive` is returned.
+    Swap {
+        offer_asset: AssetInfo,
+        ask_asset: AssetInfo,
+        min_receive: Uint256,
+        to: Option<String>,
+    },
+    /// Swaps for exactly `ask_amount`, refunding any unused offer asset to the sender.
+    SwapAsk {
+        offer_asset: AssetInfo,
+        ask_asset: AssetInfo,
+        ask_amount: Uint256,
+        to: Option<String>,
+    },
+}
+
+/// The queries a swap adapter must answer, mirroring the [`Swap`] trait.
+#[cw_serde]
+#[derive(QueryResponses)]
+pub enum AdapterQueryMsg {
+    #[returns(SimulationResponse)]
+    Simulation {
+        offer_asset: AssetInfo,
+        ask_asset: AssetInfo,
+        offer_amount: Uint256,
+    },
+    #[returns(ReverseSimulationResponse)]
+    ReverseSimulation {
+        offer_asset: AssetInfo,
+        ask_asset: AssetInfo,
+        ask_amount: Uint256,
+    },
+    /// The volume of the largest swap where the ratio of offer to ask is less than `max_ratio`.
+    #[returns(AskAmountAtPriceResponse)]
+    AskAmountAtPrice {
+        offer_asset: AssetInfo,
+        ask_asset: AssetInfo,
+        max_ratio: Decimal256,
+    },
+}
+
+#[cw_serde]
+pub struct SimulationResponse {
+    pub return_amount: Uint256,
+    /// The fee paid in the ask asset.
+    pub commission_amount: Uint256,
+    /// The fraction of the return lost to the depth of the venue.
+    pub price_impact: Decimal256,
+}
+
+#[cw_serde]
+pub struct ReverseSimulationResponse {
+    pub offer_amount: Uint256,
+}
+
+#[cw_serde]
+pub struct AskAmountAtPriceResponse {
+    pub ask_amount: Uint256,
+}
+
+impl Adapter {
+    fn simulate(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+    ) -> NeptuneResult<SimulationResponse> {
+        Ok(deps.querier.query_wasm_smart(
+            &self.addr,
+            &AdapterQueryMsg::Simulation {
+                offer_asset: offer_asset.clone(),
+                ask_asset: ask_asset.clone(),
+                offer_amount,
+            },
+        )?)
+    }
+
+    /// Creates the plan of a swap through the adapter from its simulation.
+    fn plan(
+        &self,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+        sim: SimulationResponse,
+        min_receive: Uint256,
+        msg: &AdapterExecuteMsg,
+    ) -> NeptuneResult<SwapPlan> {
+        let msg = send_assets(
+            &self.addr,
+            offer_amount,
+            offer_asset.clone(),
+            to_json_binary(msg)?,
+        )?;
+        Ok(SwapPlan {
+            msgs: vec![msg],
+            offer: AssetAmount {
+                info: offer_asset.clone(),
+                amount: offer_amount,
+            },
+            ask: AssetAmount {
+                info: ask_asset.clone(),
+                amount: sim.return_amount,
+            },
+            commission: (ask_asset.clone(), sim.commission_amount).into(),
+            price_impact: sim.price_impact,
+            min_receive,
+            surplus: Uint256::zero(),
+            venue: None,
+            legs: vec![],
+        })
+    }
+}
+
+impl Swap for Adapter {
+    fn swap(
+        &self,
+        deps: Deps<QueryWrapper>,
+        _env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        if offer_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
+        }
+        let sim = self.simulate(deps, offer_asset, ask_asset, offer_amount)?;
+        if sim.return_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
+        }
+        let min_receive = slippage.assert(offer_amount, sim.return_amount)?;
+        let msg = AdapterExecuteMsg::Swap {
+            offer_asset: offer_asset.clone(),
+            ask_asset: ask_asset.clone(),
+            min_receive,
+            to: None,
+        };
+        self.plan(offer_asset, ask_asset, offer_amount, sim, min_receive, &msg)
+    }
+
+    /// Attaches the largest offer amount the slippage policy accepts for the ask amount,
+    /// the adapter refunds what it does not use.
+    fn swap_ask(
+        &self,
+        deps: Deps<QueryWrapper>,
+        _env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        if ask_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
+        }
+        let offer_amount = self.query_reverse_sim(deps, offer_asset, ask_asset, ask_amount)?;
+        let max_offer_amount = slippage.max_offer_amount(offer_amount, ask_amount)?;
+        let mut sim = self.simulate(deps, offer_asset, ask_asset, offer_amount)?;
+        let msg = AdapterExecuteMsg::SwapAsk {
+            offer_asset: offer_asset.clone(),
+            ask_asset: ask_asset.clone(),
+            ask_amount,
+            to: None,
+        };
+        sim.return_amount = ask_amount;
+        let mut plan = self.plan(
+            offer_asset,
+            ask_asset,
+            max_offer_amount,
+            sim,
+            ask_amount,
+            &msg,
+        )?;
+        plan.offer.amount = offer_amount;
+        Ok(plan)
+    }
+
+    fn query_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        if offer_amount.is_zero() {
+            return Ok(Uint256::zero());
+        }
+        Ok(self
+            .simulate(deps, offer_asset, ask_asset, offer_amount)?
+            .return_amount)
+    }
+
+    fn query_reverse_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        if ask_amount.is_zero() {
+            return Ok(Uint256::zero());
+        }
+        let res: ReverseSimulationResponse = deps.querier.query_wasm_smart(
+            &self.addr,
+            &AdapterQueryMsg::ReverseSimulation {
+                offer_asset: offer_asset.clone(),
+                ask_asset: ask_asset.clone(),
+                ask_amount,
+            },
+        )?;
+        Ok(res.offer_amount)
+    }
+
+    fn query_ask_amount_at_price(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        max_ratio: Decimal256,
+    ) -> NeptuneResult<Uint256> {
+        let res: AskAmountAtPriceResponse = deps.querier.query_wasm_smart(
+            &self.addr,
+            &AdapterQueryMsg::AskAmountAtPrice {
+                offer_asset: offer_asset.clone(),
+                ask_asset: ask_asset.clone(),
+                max_ratio,
+            },
+        )?;
+        Ok(res.ask_amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{
+        coin, from_json, testing::mock_env, ContractResult, CosmosMsg, QuerierResult, SystemResult,
+        WasmMsg, WasmQuery,
+    };
+
+    use super::*;
+    use crate::{swap::error::SwapError, testing::mock_dependencies};
+
+    /// Answers the queries of an adapter which returns twice the offer amount,
+    /// keeping a hundredth of the return as commission.
+    fn handle_adapter_query(query: &WasmQuery) -> QuerierResult {
+        let WasmQuery::Smart { msg, .. } = query else {
+            panic!("unexpected query {query:?}");
+        };
+        let res = match from_json(msg).unwrap() {
+            AdapterQueryMsg::Simulation { offer_amount, .. } => {
+                let total = offer_amount * Uint256::from(2u8);
+                let commission_amount = total / Uint256::from(100u8);
+                to_json_binary(&SimulationResponse {
+                    return_amount: total - commission_amount,
+                    commission_amount,
+                    price_impact: Decimal256::permille(5),
+                })
+            }
+            AdapterQueryMsg::ReverseSimulation { ask_amount, .. } => {
+                to_json_binary(&ReverseSimulationResponse {
+                    offer_amount: ask_amount * Uint256::from(100u8) / Uint256::from(198u8)
+                        + Uint256::one(),
+                })
+            }
+            // The adapter has a depth of 10_000 of the ask asset at its ratio of 100 / 198.
+            AdapterQueryMsg::AskAmountAtPrice { max_ratio, .. } => {
+                let ask_amount = if max_ratio < Decimal256::from_ratio(100u8, 198u8) {
+                    Uint256::zero()
+                } else {
+                    Uint256::from(10_000u64)
+                };
+                to_json_binary(&AskAmountAtPriceResponse { ask_amount })
+            }
+        };
+        SystemResult::Ok(ContractResult::Ok(res.unwrap()))
+    }
+
+    fn native(denom: &str) -> AssetInfo {
+        AssetInfo::NativeToken {
+            denom: denom.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_adapter_swap() {
+        let mut deps = mock_dependencies();
+        deps.querier.update_wasm(handle_adapter_query);
+        let adapter = Adapter {
+            addr: Addr::unchecked("adapter"),
+        };
+        let (offer_asset, ask_asset) = (native("uatom"), native("uusdc"));
+
+        let plan = adapter
+            .swap(
+                deps.as_ref(),
+                &mock_env(),
+                &offer_asset,
+                &ask_asset,
+                1_000u64.into(),
+                &Slippage::Tolerance(Decimal256::percent(1)),
+            )
+            .unwrap();
+        assert_eq!(plan.ask.amount, Uint256::from(1_980u64));
+        assert_eq!(plan.min_receive, Uint256::from(1_960u64));
+        assert_eq!(plan.price_impact, Decimal256::permille(5));
+        assert_eq!(
+            plan.msgs,
+            vec![CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "adapter".to_string(),
+                msg: to_json_binary(&AdapterExecuteMsg::Swap {
+                    offer_asset: offer_asset.clone(),
+                    ask_asset: ask_asset.clone(),
+                    min_receive: 1_960u64.into(),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![coin(1_000, "uatom")],
+            })]
+        );
+
+        let plan = adapter
+            .swap_ask(
+                deps.as_ref(),
+                &mock_env(),
+                &offer_asset,
+                &ask_asset,
+                1_980u64.into(),
+                &Slippage::Tolerance(Decimal256::percent(1)),
+            )
+            .unwrap();
+        assert_eq!(plan.offer.amount, Uint256::from(1_001u64));
+        assert_eq!(plan.ask.amount, Uint256::from(1_980u64));
+        assert_eq!(plan.min_receive, Uint256::from(1_980u64));
+        // The offer attached allows the ratio of offer to ask to worsen by the tolerance.
+        assert_eq!(
+            plan.msgs,
+            vec![CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "adapter".to_string(),
+                msg: to_json_binary(&AdapterExecuteMsg::SwapAsk {
+                    offer_asset,
+                    ask_asset,
+                    ask_amount: 1_980u64.into(),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![coin(1_011, "uatom")],
+            })]
+        );
+
+        assert_eq!(
+            adapter.swap_ask(
+                deps.as_ref(),
+                &mock_env(),
+                &native("uatom"),
+                &native("uusdc"),
+                1_980u64.into(),
+                &Slippage::MinimumReceive(2_000u64.into()),
+            ),
+            Err(SwapError::SlippageExceeded {
+                minimum: 2_000u64.into(),
+                actual: 1_980u64.into()
+            }
+            .into())
+        );
+    }
+
+    #[test]
+    fn test_adapter_ask_amount_at_price() {
+        let mut deps = mock_dependencies();
+        deps.querier.update_wasm(handle_adapter_query);
+        let adapter = Adapter {
+            addr: Addr::unchecked("adapter"),
+        };
+        let (offer_asset, ask_asset) = (native("uatom"), native("uusdc"));
+
+        assert_eq!(
+            adapter
+                .query_ask_amount_at_price(
+                    deps.as_ref(),
+                    &offer_asset,
+                    &ask_asset,
+                    Decimal256::percent(51)
+                )
+                .unwrap(),
+            Uint256::from(10_000u64)
+        );
+        assert_eq!(
+            adapter
+                .query_ask_amount_at_price(
+                    deps.as_ref(),
+                    &offer_asset,
+                    &ask_asset,
+                    Decimal256::percent(50)
+                )
+                .unwrap(),
+            Uint256::zero()
+        );
+    }
+}
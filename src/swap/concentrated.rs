@@ -0,0 +1,470 @@
+use cosmwasm_std::{Decimal256, Uint256};
+
+use crate::error::NeptuneResult;
+
+use super::error::SwapError;
+
+/// `N^N` for the two assets of an Astroport concentrated liquidity pair.
+const N_POW2: u8 = 4;
+
+/// Upper bound on the Newton steps taken to solve the invariant for `D` or for a reserve,
+/// as in Astroport's concentrated liquidity pairs.
+const MAX_ITERATIONS: u32 = 64;
+
+/// Upper bound on the steps taken to find the reserves at a marginal ratio,
+/// each of which solves the invariant for a reserve.
+const MAX_PRICE_ITERATIONS: u32 = 16;
+
+/// Relative precision at which the iterations stop.
+const PRECISION: u64 = 1_000_000_000_000;
+
+/// The invariant of an Astroport concentrated liquidity pair, which is satisfied by reserves
+/// `x` and `y` when `K * D * (x + y) + x * y = K * D^2 + D^2 / 4` where
+/// `K0 = 4 * x * y / D^2` and `K = leverage * K0 * gamma^2 / (gamma + 1 - K0)^2`.
+/// Reserves are in whole units of each asset, the second asset of the pair
+/// multiplied by the price scale.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Invariant {
+    /// `amp * N^N`.
+    pub leverage: Decimal256,
+    pub gamma: Decimal256,
+}
+
+/// `K0`, `K` and `dK / dK0` at some reserves and invariant.
+struct Terms {
+    k0: Decimal256,
+    k: Decimal256,
+    dk: Decimal256,
+}
+
+impl Invariant {
+    /// Errors on a zero `amp` or `gamma`, for which the invariant is degenerate.
+    pub fn new(amp: Decimal256, gamma: Decimal256) -> NeptuneResult<Self> {
+        if amp.is_zero() || gamma.is_zero() {
+            return Err(SwapError::InvalidPool.into());
+        }
+        Ok(Self {
+            leverage: mul(amp, Decimal256::from_ratio(N_POW2, 1u8))?,
+            gamma,
+        })
+    }
+
+    /// Computes the invariant `D` of a pool with reserves `x` and `y`.
+    pub fn compute_d(&self, x: Decimal256, y: Decimal256) -> NeptuneResult<Decimal256> {
+        if x.is_zero() || y.is_zero() {
+            return Err(SwapError::InsufficientLiquidity.into());
+        }
+        // The invariant is non-negative at twice the geometric mean of the reserves
+        // and non-positive at their sum.
+        let lower = mul(mul(x, y)?.sqrt(), Decimal256::from_ratio(2u8, 1u8))?;
+        let upper = add(x, y)?;
+        if upper <= lower {
+            return Ok(lower);
+        }
+        newton(upper, lower, upper, false, |d| {
+            let terms = self.terms(x, y, d)?;
+            Ok((
+                self.value(x, y, d, &terms)?,
+                self.derivative_d(x, y, d, &terms)?,
+            ))
+        })
+    }
+
+    /// Computes the reserve of one asset given the reserve `x` of the other asset and the invariant.
+    pub fn compute_y(&self, x: Decimal256, d: Decimal256) -> NeptuneResult<Decimal256> {
+        self.solve_y(x, d, None)
+    }
+
+    /// The marginal ratio of `x` offered to `y` received at the given reserves.
+    pub fn marginal_ratio(
+        &self,
+        d: Decimal256,
+        x: Decimal256,
+        y: Decimal256,
+    ) -> NeptuneResult<Decimal256> {
+        let (dx, dy) = (self.partial(x, y, d)?, self.partial(y, x, d)?);
+        if dx.negative || dy.negative {
+            return Err(SwapError::InvalidPool.into());
+        }
+        div(dy.abs, dx.abs)
+    }
+
+    /// The invariant is negative for a vanishing reserve `y`
+    /// and non-negative once `x * y` reaches `D^2 / 4`.
+    /// Newton's method starts from `start` when it is below that bound.
+    fn solve_y(
+        &self,
+        x: Decimal256,
+        d: Decimal256,
+        start: Option<Decimal256>,
+    ) -> NeptuneResult<Decimal256> {
+        if x.is_zero() {
+            return Err(SwapError::InsufficientLiquidity.into());
+        }
+        let upper = div(mul(d, d)?, mul(Decimal256::from_ratio(N_POW2, 1u8), x)?)?;
+        let start = start.filter(|start| *start < upper).unwrap_or(upper);
+        newton(start, Decimal256::zero(), upper, true, |y| {
+            Ok((
+                self.value(x, y, d, &self.terms(x, y, d)?)?,
+                self.partial(y, x, d)?,
+            ))
+        })
+    }
+
+    fn terms(&self, x: Decimal256, y: Decimal256, d: Decimal256) -> NeptuneResult<Terms> {
+        let k0 = div(
+            mul(mul(x, y)?, Decimal256::from_ratio(N_POW2, 1u8))?,
+            mul(d, d)?,
+        )?;
+        let gamma_plus_one = add(self.gamma, Decimal256::one())?;
+        let denominator = gamma_plus_one
+            .checked_sub(k0)
+            .map_err(|_| SwapError::InvalidPool)?;
+        let denominator_pow2 = mul(denominator, denominator)?;
+        let numerator = mul(self.leverage, mul(self.gamma, self.gamma)?)?;
+        Ok(Terms {
+            k0,
+            k: div(mul(numerator, k0)?, denominator_pow2)?,
+            dk: div(
+                mul(numerator, add(gamma_plus_one, k0)?)?,
+                mul(denominator_pow2, denominator)?,
+            )?,
+        })
+    }
+
+    /// `K * D * (x + y) + x * y - K * D^2 - D^2 / 4`.
+    fn value(
+        &self,
+        x: Decimal256,
+        y: Decimal256,
+        d: Decimal256,
+        terms: &Terms,
+    ) -> NeptuneResult<Signed> {
+        let kd = mul(terms.k, d)?;
+        let d_pow2 = mul(d, d)?;
+        Ok(Signed::difference(
+            add(mul(kd, add(x, y)?)?, mul(x, y)?)?,
+            add(
+                mul(kd, d)?,
+                div(d_pow2, Decimal256::from_ratio(N_POW2, 1u8))?,
+            )?,
+        ))
+    }
+
+    /// The derivative of the invariant with respect to `D`,
+    /// `K * (x + y - 2 * D) - D / 2 - 2 * K0 * dK / dK0 * (x + y - D)`.
+    fn derivative_d(
+        &self,
+        x: Decimal256,
+        y: Decimal256,
+        d: Decimal256,
+        terms: &Terms,
+    ) -> NeptuneResult<Signed> {
+        let two = Decimal256::from_ratio(2u8, 1u8);
+        let excess = Signed::difference(add(x, y)?, d);
+        let excess_term = mul(mul(mul(two, terms.k0)?, terms.dk)?, excess.abs)?;
+        let mut positive = mul(terms.k, add(x, y)?)?;
+        let mut negative = add(mul(mul(two, terms.k)?, d)?, div(d, two)?)?;
+        if excess.negative {
+            positive = add(positive, excess_term)?;
+        } else {
+            negative = add(negative, excess_term)?;
+        }
+        Ok(Signed::difference(positive, negative))
+    }
+
+    /// The partial derivative of the invariant with respect to the reserve `x`,
+    /// `dK / dK0 * 4 * y / D * (x + y - D) + K * D + y`.
+    fn partial(&self, x: Decimal256, y: Decimal256, d: Decimal256) -> NeptuneResult<Signed> {
+        let terms = self.terms(x, y, d)?;
+        let excess = Signed::difference(add(x, y)?, d);
+        let dk0 = div(mul(Decimal256::from_ratio(N_POW2, 1u8), y)?, d)?;
+        let excess_term = mul(mul(terms.dk, dk0)?, excess.abs)?;
+        let base = add(mul(terms.k, d)?, y)?;
+        Ok(if excess.negative {
+            Signed::difference(base, excess_term)
+        } else {
+            Signed::difference(add(base, excess_term)?, Decimal256::zero())
+        })
+    }
+}
+
+/// Returns the amount of the ask asset received when swapping until the marginal ratio
+/// of offer to ask reaches `max_ratio`. Fees are not taken into account.
+/// The result never exceeds the amount at `max_ratio`, if the search does not converge
+/// within its bound the largest amount found within the price is returned.
+pub fn ask_amount_at_price(
+    invariant: Invariant,
+    offer_reserve: Decimal256,
+    ask_reserve: Decimal256,
+    max_ratio: Decimal256,
+) -> NeptuneResult<Decimal256> {
+    if offer_reserve.is_zero() || ask_reserve.is_zero() {
+        return Ok(Decimal256::zero());
+    }
+    let d = invariant.compute_d(offer_reserve, ask_reserve)?;
+    // Each offer reserve is solved for starting from the previous one.
+    let mut offer_reserve_after = offer_reserve;
+    let mut excess = |ask_reserve_after: Decimal256| -> NeptuneResult<Signed> {
+        offer_reserve_after = invariant.solve_y(ask_reserve_after, d, Some(offer_reserve_after))?;
+        let ratio = invariant.marginal_ratio(d, offer_reserve_after, ask_reserve_after)?;
+        Ok(Signed::difference(ratio, max_ratio))
+    };
+    let mut within_excess = excess(ask_reserve)?;
+    if within_excess.is_positive() {
+        return Ok(Decimal256::zero());
+    }
+
+    // The marginal ratio is within the price at the ask reserve `within` and beyond it at `beyond`.
+    // Halve the ask reserve until the price is exceeded.
+    let half = Decimal256::percent(50);
+    let mut within = ask_reserve;
+    let mut beyond = ask_reserve;
+    let mut beyond_excess = within_excess;
+    let mut iterations = 0;
+    while !beyond_excess.is_positive() {
+        iterations += 1;
+        beyond = mul(beyond, half)?;
+        if iterations > MAX_PRICE_ITERATIONS || beyond.is_zero() {
+            return Ok(ask_reserve - within);
+        }
+        beyond_excess = excess(beyond)?;
+        if !beyond_excess.is_positive() {
+            (within, within_excess) = (beyond, beyond_excess);
+        }
+    }
+
+    // Narrow down the boundary by the Illinois variant of the false position method,
+    // which halves the excess of an end of the interval whenever the other end moves twice.
+    let mut last_moved_within = None;
+    while iterations < MAX_PRICE_ITERATIONS && !within_excess.abs.is_zero() {
+        iterations += 1;
+        let width = within - beyond;
+        if width <= mul(within, Decimal256::from_ratio(1u8, PRECISION))? {
+            break;
+        }
+        let weight = div(
+            beyond_excess.abs,
+            add(beyond_excess.abs, within_excess.abs)?,
+        )?;
+        let mut next = add(beyond, mul(width, weight)?)?;
+        if next <= beyond || next >= within {
+            next = beyond + width * half;
+        }
+        let next_excess = excess(next)?;
+        if next_excess.is_positive() {
+            (beyond, beyond_excess) = (next, next_excess);
+            if last_moved_within == Some(false) {
+                within_excess.abs = within_excess.abs * half;
+            }
+            last_moved_within = Some(false);
+        } else {
+            (within, within_excess) = (next, next_excess);
+            if last_moved_within == Some(true) {
+                beyond_excess.abs = beyond_excess.abs * half;
+            }
+            last_moved_within = Some(true);
+        }
+    }
+    Ok(ask_reserve - within)
+}
+
+/// A signed value, as the invariant and its derivatives are not always positive.
+#[derive(Clone, Copy, Debug)]
+struct Signed {
+    abs: Decimal256,
+    negative: bool,
+}
+
+impl Signed {
+    /// `lhs - rhs`.
+    fn difference(lhs: Decimal256, rhs: Decimal256) -> Self {
+        if lhs >= rhs {
+            Self {
+                abs: lhs - rhs,
+                negative: false,
+            }
+        } else {
+            Self {
+                abs: rhs - lhs,
+                negative: true,
+            }
+        }
+    }
+
+    fn is_positive(&self) -> bool {
+        !self.negative && !self.abs.is_zero()
+    }
+}
+
+/// Solves `f(v) = 0` by Newton's method from `start`, where `f` returns a value and its derivative.
+/// The root lies between `lower` and `upper`, `f` being increasing or decreasing between them.
+/// Steps leaving the interval are replaced by halving the distance to its bound.
+fn newton(
+    start: Decimal256,
+    lower: Decimal256,
+    upper: Decimal256,
+    increasing: bool,
+    f: impl Fn(Decimal256) -> NeptuneResult<(Signed, Signed)>,
+) -> NeptuneResult<Decimal256> {
+    let half = Decimal256::percent(50);
+    let precision = Decimal256::from_ratio(1u8, PRECISION);
+    let mut v = start;
+    for _ in 0..MAX_ITERATIONS {
+        let (value, derivative) = f(v)?;
+        if value.abs.is_zero() {
+            return Ok(v);
+        }
+        // The root is above `v` where an increasing function is negative.
+        let root_above = value.negative == increasing;
+        let step = if derivative.abs.is_zero() || derivative.negative == increasing {
+            None
+        } else {
+            value.abs.checked_div(derivative.abs).ok()
+        };
+        let next = match step {
+            Some(step) if root_above => v.checked_add(step).ok().filter(|next| *next < upper),
+            Some(step) => v.checked_sub(step).ok().filter(|next| *next > lower),
+            None => None,
+        };
+        let next = next.unwrap_or_else(|| {
+            if root_above {
+                v + (upper - v) * half
+            } else {
+                v - (v - lower) * half
+            }
+        });
+        let tolerance = (next * precision).max(Decimal256::new(Uint256::one()));
+        if next.max(v) - next.min(v) <= tolerance {
+            return Ok(next);
+        }
+        v = next;
+    }
+    Err(SwapError::InvalidPool.into())
+}
+
+fn add(lhs: Decimal256, rhs: Decimal256) -> NeptuneResult<Decimal256> {
+    lhs.checked_add(rhs)
+        .map_err(|_| SwapError::InvalidPool.into())
+}
+
+fn mul(lhs: Decimal256, rhs: Decimal256) -> NeptuneResult<Decimal256> {
+    lhs.checked_mul(rhs)
+        .map_err(|_| SwapError::InvalidPool.into())
+}
+
+fn div(lhs: Decimal256, rhs: Decimal256) -> NeptuneResult<Decimal256> {
+    lhs.checked_div(rhs)
+        .map_err(|_| SwapError::InvalidPool.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::error::NeptuneError;
+
+    fn invariant() -> Invariant {
+        Invariant::new(
+            Decimal256::from_ratio(10u8, 1u8),
+            Decimal256::from_str("0.000145").unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn is_close(a: Decimal256, b: Decimal256) -> bool {
+        a.max(b) - a.min(b) <= a.max(b) * Decimal256::from_ratio(1u8, 1_000_000_000u64)
+    }
+
+    #[test]
+    fn test_balanced_pool() {
+        let invariant = invariant();
+        let reserve = Decimal256::from_ratio(1_000_000u64, 1u8);
+        let d = invariant.compute_d(reserve, reserve).unwrap();
+        assert_eq!(d, reserve * Decimal256::from_ratio(2u8, 1u8));
+        assert!(is_close(invariant.compute_y(reserve, d).unwrap(), reserve));
+        assert!(is_close(
+            invariant.marginal_ratio(d, reserve, reserve).unwrap(),
+            Decimal256::one()
+        ));
+    }
+
+    #[test]
+    fn test_imbalanced_pool() {
+        let invariant = invariant();
+        let (x, y) = (
+            Decimal256::from_ratio(1_000u64, 1u8),
+            Decimal256::from_ratio(800u64, 1u8),
+        );
+        let d = invariant.compute_d(x, y).unwrap();
+        assert!(d > (x * y).sqrt() * Decimal256::from_ratio(2u8, 1u8) && d < x + y);
+        assert!(is_close(invariant.compute_y(x, d).unwrap(), y));
+        assert!(is_close(invariant.compute_y(y, d).unwrap(), x));
+
+        // The marginal ratio matches the ratio of a small swap.
+        let offer = Decimal256::from_ratio(1u8, 1_000u64);
+        let received = y - invariant.compute_y(x + offer, d).unwrap();
+        let ratio = invariant.marginal_ratio(d, x, y).unwrap();
+        assert!(ratio > Decimal256::one());
+        assert!(
+            (offer / received).max(ratio) - (offer / received).min(ratio)
+                < Decimal256::from_ratio(1u8, 10_000u64)
+        );
+
+        // Far from the price scale the pool behaves like a constant product.
+        let (x, y) = (
+            Decimal256::from_ratio(1_000_000u64, 1u8),
+            Decimal256::from_ratio(1_000u64, 1u8),
+        );
+        let d = invariant.compute_d(x, y).unwrap();
+        assert!(is_close(invariant.compute_y(x, d).unwrap(), y));
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let err = Err(NeptuneError::SwapError(SwapError::InvalidPool));
+        assert_eq!(Invariant::new(Decimal256::one(), Decimal256::zero()), err);
+        assert_eq!(Invariant::new(Decimal256::zero(), Decimal256::one()), err);
+
+        let invariant = invariant();
+        let reserve = Decimal256::from_ratio(1_000_000u64, 1u8);
+        assert_eq!(
+            invariant.compute_d(reserve, Decimal256::zero()),
+            Err(NeptuneError::SwapError(SwapError::InsufficientLiquidity))
+        );
+        assert_eq!(
+            invariant.compute_y(Decimal256::zero(), reserve),
+            Err(NeptuneError::SwapError(SwapError::InsufficientLiquidity))
+        );
+    }
+
+    #[test]
+    fn test_ask_amount_at_price() {
+        let invariant = invariant();
+        let reserve = Decimal256::from_ratio(1_000_000u64, 1u8);
+
+        assert_eq!(
+            ask_amount_at_price(invariant, reserve, reserve, Decimal256::percent(99)).unwrap(),
+            Decimal256::zero()
+        );
+
+        let max_ratio = Decimal256::percent(101);
+        let ask_amount = ask_amount_at_price(invariant, reserve, reserve, max_ratio).unwrap();
+        assert!(!ask_amount.is_zero() && ask_amount < reserve);
+
+        // The marginal ratio after the swap matches the price.
+        let d = invariant.compute_d(reserve, reserve).unwrap();
+        let offer_reserve = invariant.compute_y(reserve - ask_amount, d).unwrap();
+        let ratio = invariant
+            .marginal_ratio(d, offer_reserve, reserve - ask_amount)
+            .unwrap();
+        assert!(ratio <= max_ratio);
+        assert!(is_close(ratio, max_ratio) || max_ratio - ratio < Decimal256::permille(1));
+
+        // A price far beyond the pool's is still searched within the bound.
+        let max_ratio = Decimal256::from_ratio(1_000_000u64, 1u8);
+        let ask_amount = ask_amount_at_price(invariant, reserve, reserve, max_ratio).unwrap();
+        assert!(ask_amount > reserve * Decimal256::percent(99) && ask_amount < reserve);
+    }
+}
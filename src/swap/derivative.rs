@@ -0,0 +1,437 @@
+use crate::{
+    error::NeptuneResult,
+    injective::{into_fp_decimal, try_into_decimal_256},
+    msg_wrapper::MsgWrapper,
+    pool::GetPoolRef,
+    query_wrapper::QueryWrapper,
+};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{CosmosMsg, Decimal256, Deps, Env, QueryRequest, StdError};
+use injective_cosmwasm::{
+    exchange::{response::QueryOrderbookResponse, types::PriceLevel},
+    DerivativeMarket, DerivativeMarketResponse, DerivativeOrder, FullDerivativeMarket,
+    InjectiveMsg, InjectiveQuery, InjectiveRoute, MarketId, OrderInfo, OrderType, SubaccountId,
+};
+use injective_math::FPDecimal;
+
+use super::{
+    error::SwapError,
+    order_book_math::{price_impact, tick_round_down, tick_round_up},
+};
+
+/// The parameters of a derivative market which determine how market orders are filled.
+/// Prices and margins are in the smallest units of the quote asset,
+/// quantities are in whole contracts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivativeParams {
+    pub min_price_tick_size: FPDecimal,
+    pub min_quantity_tick_size: FPDecimal,
+    /// The fee rate paid by market orders.
+    pub fee_rate: FPDecimal,
+    pub initial_margin_ratio: FPDecimal,
+}
+
+impl DerivativeParams {
+    /// Derives the parameters of a derivative market, market orders pay the taker fee.
+    /// The relayer's share of the fee is returned to the contract as it is its own fee recipient.
+    pub fn new(market: &DerivativeMarket) -> Self {
+        Self {
+            min_price_tick_size: market.min_price_tick_size,
+            min_quantity_tick_size: market.min_quantity_tick_size,
+            fee_rate: market.taker_fee_rate * (FPDecimal::ONE - market.relayer_fee_share_rate),
+            initial_margin_ratio: market.initial_margin_ratio,
+        }
+    }
+}
+
+/// A market order on a derivative market, placed from a subaccount of the contract.
+#[cw_serde]
+pub struct MarketOrder {
+    pub market_id: MarketId,
+    /// The side of the order, a short position is closed by a buy.
+    pub is_buy: bool,
+    /// Rounded down to the quantity tick.
+    pub quantity: FPDecimal,
+    /// The fraction by which the order may be filled at worse than the simulated worst price.
+    pub slippage: Decimal256,
+}
+
+/// The simulated fill of a derivative market order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivativeFill {
+    pub quantity: FPDecimal,
+    /// The value of the filled quantity at the prices of the crossed levels.
+    pub notional: FPDecimal,
+    /// The fees paid on the notional.
+    pub fee: FPDecimal,
+    /// The margin posted with the order, zero for reduce-only orders.
+    pub margin: FPDecimal,
+    /// The notional divided by the filled quantity.
+    pub average_price: FPDecimal,
+    /// The limit price of the order, the price of the last level crossed moved by the slippage.
+    pub worst_price: FPDecimal,
+    /// The deviation of the average price from the best price.
+    pub price_impact: Decimal256,
+}
+
+/// A derivative market order along with its simulated fill.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivativePlan {
+    pub msg: CosmosMsg<MsgWrapper>,
+    pub fill: DerivativeFill,
+}
+
+/// Simulates a market order against the levels of the opposite side of the book,
+/// sells from the highest price and buys from the lowest.
+/// Opening orders post the margin required at `leverage`, reduce-only orders post none.
+/// Errors on insufficient liquidity or when the leverage exceeds the market's maximum.
+pub fn simulate_fill(
+    params: &DerivativeParams,
+    levels: &[PriceLevel],
+    order: &MarketOrder,
+    leverage: Option<FPDecimal>,
+) -> NeptuneResult<DerivativeFill> {
+    let quantity = tick_round_down(order.quantity, params.min_quantity_tick_size);
+    if quantity.is_zero() {
+        return Err(SwapError::OrderTooSmall.into());
+    }
+    let mut notional = FPDecimal::ZERO;
+    let mut remaining_quantity = quantity;
+    let mut last_price = None;
+    for level in levels {
+        if remaining_quantity.is_zero() {
+            break;
+        }
+        let fill_quantity = level.q.min(remaining_quantity);
+        notional += fill_quantity * level.p;
+        remaining_quantity -= fill_quantity;
+        last_price = Some(level.p);
+    }
+    let (Some(best), Some(last_price)) = (levels.first(), last_price) else {
+        return Err(SwapError::InsufficientLiquidity.into());
+    };
+    if !remaining_quantity.is_zero() {
+        return Err(SwapError::InsufficientLiquidity.into());
+    }
+    let slippage = into_fp_decimal(order.slippage);
+    let worst_price = if order.is_buy {
+        tick_round_down(
+            last_price * (FPDecimal::ONE + slippage),
+            params.min_price_tick_size,
+        )
+        .max(last_price)
+    } else {
+        tick_round_up(
+            last_price * (FPDecimal::ONE - slippage),
+            params.min_price_tick_size,
+        )
+        .min(last_price)
+    };
+    let margin = match leverage {
+        Some(leverage) => {
+            if leverage.is_zero() || leverage * params.initial_margin_ratio > FPDecimal::ONE {
+                return Err(SwapError::LeverageExceeded.into());
+            }
+            tick_round_up(worst_price * quantity / leverage, FPDecimal::ONE)
+        }
+        None => FPDecimal::ZERO,
+    };
+    let average_price = notional / quantity;
+    Ok(DerivativeFill {
+        quantity,
+        notional,
+        fee: notional * params.fee_rate,
+        margin,
+        average_price,
+        worst_price,
+        price_impact: price_impact(best.p, average_price, order.is_buy)?,
+    })
+}
+
+/// Plans a market order which opens or increases a position,
+/// posting margin at `leverage` from the subaccount's deposits.
+pub fn market_open(
+    deps: Deps<QueryWrapper>,
+    env: &Env,
+    subaccount_id: SubaccountId,
+    order: MarketOrder,
+    leverage: FPDecimal,
+) -> NeptuneResult<DerivativePlan> {
+    market_order(deps, env, subaccount_id, order, Some(leverage))
+}
+
+/// Plans a reduce-only market order which closes or decreases a position.
+pub fn market_close(
+    deps: Deps<QueryWrapper>,
+    env: &Env,
+    subaccount_id: SubaccountId,
+    order: MarketOrder,
+) -> NeptuneResult<DerivativePlan> {
+    market_order(deps, env, subaccount_id, order, None)
+}
+
+/// The quantity of contracts whose short delta-hedges the balance of a pool,
+/// rounded down to the quantity tick. `decimals` are those of the pool's asset.
+pub fn hedge_quantity(
+    pool: &dyn GetPoolRef,
+    decimals: u8,
+    params: &DerivativeParams,
+) -> NeptuneResult<FPDecimal> {
+    let balance = *pool.get_pool_ref().balance;
+    let balance = Decimal256::from_atomics(balance, decimals.into())
+        .map_err(|_| SwapError::InvalidAmount(balance.to_string()))?;
+    let tick_size = try_into_decimal_256(params.min_quantity_tick_size)?.atomics();
+    let ticks = balance
+        .atomics()
+        .checked_div(tick_size)
+        .map_err(StdError::from)?;
+    Ok(into_fp_decimal(Decimal256::new(ticks * tick_size)))
+}
+
+fn market_order(
+    deps: Deps<QueryWrapper>,
+    env: &Env,
+    subaccount_id: SubaccountId,
+    order: MarketOrder,
+    leverage: Option<FPDecimal>,
+) -> NeptuneResult<DerivativePlan> {
+    let market = query_derivative_market(deps, order.market_id.clone())?;
+    let derivative_market = market.market.ok_or(SwapError::DerivativeMarketNotFound)?;
+    let params = DerivativeParams::new(&derivative_market);
+    let order_book = query_derivative_market_order_book(deps, order.market_id.clone(), 0)?;
+    let levels = if order.is_buy {
+        order_book.sells_price_level
+    } else {
+        order_book.buys_price_level
+    };
+    let fill = simulate_fill(&params, &levels, &order, leverage)?;
+
+    let order_info = OrderInfo {
+        subaccount_id,
+        fee_recipient: Some(env.contract.address.clone()),
+        price: fill.worst_price,
+        quantity: fill.quantity,
+        cid: None,
+    };
+
+    let order = DerivativeOrder {
+        market_id: order.market_id,
+        order_info,
+        order_type: if order.is_buy {
+            OrderType::Buy
+        } else {
+            OrderType::Sell
+        },
+        margin: fill.margin,
+        trigger_price: None,
+    };
+
+    let wrapper = MsgWrapper {
+        route: InjectiveRoute::Exchange,
+        msg_data: InjectiveMsg::CreateDerivativeMarketOrder {
+            sender: env.contract.address.clone(),
+            order,
+        },
+    };
+
+    Ok(DerivativePlan {
+        msg: CosmosMsg::Custom(wrapper),
+        fill,
+    })
+}
+
+pub fn query_derivative_market(
+    deps: Deps<QueryWrapper>,
+    market_id: MarketId,
+) -> NeptuneResult<FullDerivativeMarket> {
+    let wrapper = QueryWrapper {
+        route: InjectiveRoute::Exchange,
+        query_data: InjectiveQuery::DerivativeMarket { market_id },
+    };
+
+    let query_request = QueryRequest::Custom(wrapper);
+
+    let res: DerivativeMarketResponse = deps.querier.query(&query_request)?;
+
+    Ok(res.market)
+}
+
+/// Queries both sides of a derivative market's order book, a limit of zero returns every level.
+pub fn query_derivative_market_order_book(
+    deps: Deps<QueryWrapper>,
+    market_id: MarketId,
+    limit: u64,
+) -> NeptuneResult<QueryOrderbookResponse> {
+    let wrapper = QueryWrapper {
+        route: InjectiveRoute::Exchange,
+        query_data: InjectiveQuery::DerivativeOrderbook {
+            market_id,
+            limit,
+            limit_cumulative_notional: None,
+        },
+    };
+
+    let query_request = QueryRequest::Custom(wrapper);
+
+    Ok(deps.querier.query(&query_request)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::Uint256;
+    use injective_cosmwasm::{MarketStatus, OracleType};
+
+    use super::*;
+    use crate::{
+        error::NeptuneError,
+        injective::subaccount::subaccount_id,
+        pool::Pool,
+        swap::mock_exchange::{
+            fp, mock_dependencies_with_exchange, MockDerivativeMarket, MockExchange,
+        },
+        testing::mock_env,
+    };
+
+    const MARKET_ID: &str = "0x9b9980167ecc3645ff1a5517886652d94a0825e54a77d2057cbbe3ebee015963";
+
+    fn level(p: &str, q: &str) -> PriceLevel {
+        PriceLevel { p: fp(p), q: fp(q) }
+    }
+
+    /// A perpetual quoted in a 6 decimal asset with a maximum leverage of 20.
+    fn exchange() -> MockExchange {
+        MockExchange::default().with_derivative_market(MockDerivativeMarket {
+            market: DerivativeMarket {
+                ticker: "INJ/USDT PERP".to_string(),
+                oracle_base: "inj".to_string(),
+                oracle_quote: "usdt".to_string(),
+                oracle_type: OracleType::Pyth,
+                oracle_scale_factor: 6,
+                quote_denom: "usdt".to_string(),
+                market_id: MarketId::new(MARKET_ID).unwrap(),
+                initial_margin_ratio: fp("0.05"),
+                maintenance_margin_ratio: fp("0.02"),
+                maker_fee_rate: fp("0.0005"),
+                taker_fee_rate: fp("0.001"),
+                relayer_fee_share_rate: fp("0.4"),
+                isPerpetual: true,
+                status: MarketStatus::Active,
+                min_price_tick_size: fp("1000"),
+                min_quantity_tick_size: fp("0.001"),
+            },
+            mark_price: fp("9950000"),
+            buys: vec![level("9900000", "1"), level("9800000", "2")],
+            sells: vec![level("10000000", "1"), level("10100000", "2")],
+        })
+    }
+
+    fn order(is_buy: bool, quantity: &str) -> MarketOrder {
+        MarketOrder {
+            market_id: MarketId::new(MARKET_ID).unwrap(),
+            is_buy,
+            quantity: fp(quantity),
+            slippage: Decimal256::percent(1),
+        }
+    }
+
+    #[test]
+    fn test_market_open() {
+        let deps = mock_dependencies_with_exchange(exchange());
+        let env = mock_env();
+        let subaccount = subaccount_id(&env.contract.address, 1);
+        let mut exchange = exchange();
+
+        let plan = market_open(
+            deps.as_ref(),
+            &env,
+            subaccount.clone(),
+            order(true, "2"),
+            fp("2"),
+        )
+        .unwrap();
+        assert_eq!(plan.fill.notional, fp("20100000"));
+        assert_eq!(plan.fill.average_price, fp("10050000"));
+        // The taker fee net of the relayer's share.
+        assert_eq!(plan.fill.fee, fp("12060"));
+        // The last level crossed moved up by the slippage.
+        assert_eq!(plan.fill.worst_price, fp("10201000"));
+        assert_eq!(plan.fill.margin, fp("10201000"));
+        assert!(plan.fill.price_impact > Decimal256::zero());
+
+        exchange.execute(&[plan.msg]);
+        let placed = &exchange.derivative_orders[0];
+        assert_eq!(placed.order_type, OrderType::Buy);
+        assert_eq!(placed.margin, fp("10201000"));
+        assert_eq!(placed.order_info.subaccount_id, subaccount);
+
+        let err = market_open(
+            deps.as_ref(),
+            &env,
+            subaccount.clone(),
+            order(true, "2"),
+            fp("25"),
+        )
+        .unwrap_err();
+        assert_eq!(err, NeptuneError::from(SwapError::LeverageExceeded));
+
+        let err =
+            market_open(deps.as_ref(), &env, subaccount, order(true, "4"), fp("2")).unwrap_err();
+        assert_eq!(err, NeptuneError::from(SwapError::InsufficientLiquidity));
+    }
+
+    #[test]
+    fn test_market_close() {
+        let deps = mock_dependencies_with_exchange(exchange());
+        let env = mock_env();
+        let mut exchange = exchange();
+
+        // Closes part of a long position, the quantity is rounded down to the tick.
+        let plan = market_close(
+            deps.as_ref(),
+            &env,
+            subaccount_id(&env.contract.address, 1),
+            order(false, "1.0005"),
+        )
+        .unwrap();
+        assert_eq!(plan.fill.quantity, fp("1"));
+        assert_eq!(plan.fill.worst_price, fp("9801000"));
+        exchange.execute(&[plan.msg]);
+        let placed = &exchange.derivative_orders[0];
+        assert_eq!(placed.order_type, OrderType::Sell);
+        // Reduce-only orders post no margin.
+        assert_eq!(placed.margin, FPDecimal::ZERO);
+
+        let err = market_close(
+            deps.as_ref(),
+            &env,
+            subaccount_id(&env.contract.address, 1),
+            order(false, "0.0005"),
+        )
+        .unwrap_err();
+        assert_eq!(err, NeptuneError::from(SwapError::OrderTooSmall));
+    }
+
+    #[test]
+    fn test_hedge_quantity() {
+        let market = exchange().derivative_markets[MARKET_ID].market.clone();
+        let pool = Pool {
+            balance: Uint256::from(1_234_567_890_000_000_000u128),
+            shares: Uint256::from(1_000u64),
+        };
+        let params = DerivativeParams::new(&market);
+        assert_eq!(hedge_quantity(&pool, 18, &params), Ok(fp("1.234")));
+        // Decimals beyond the range of a power of ten leave nothing to hedge.
+        assert_eq!(hedge_quantity(&pool, 80, &params), Ok(FPDecimal::ZERO));
+
+        let pool = Pool {
+            balance: Uint256::MAX,
+            shares: Uint256::from(1_000u64),
+        };
+        assert_eq!(
+            hedge_quantity(&pool, 0, &params),
+            Err(NeptuneError::from(SwapError::InvalidAmount(
+                Uint256::MAX.to_string()
+            )))
+        );
+    }
+}
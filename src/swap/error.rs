@@ -1,4 +1,5 @@
 use crate::asset::AssetInfo;
+use cosmwasm_std::{Decimal256, Uint256};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -6,6 +7,15 @@ pub enum SwapError {
     #[error("liquidity pool not found {0:?}")]
     PoolNotFound([AssetInfo; 2]),
 
+    #[error("exchange already registered {0:?}")]
+    ExchangeAlreadyRegistered([AssetInfo; 2]),
+
+    #[error("exchange does not trade {0:?}")]
+    ExchangeMismatch([AssetInfo; 2]),
+
+    #[error("no swap route found {0:?}")]
+    RouteNotFound([AssetInfo; 2]),
+
     #[error("Insufficient liquidity to execute swap")]
     InsufficientLiquidity,
 
@@ -18,6 +28,36 @@ pub enum SwapError {
     #[error("Spot market not found")]
     SpotMarketNotFound,
 
+    #[error("atomic execution fee multiplier not found for market {0}")]
+    FeeMultiplierNotFound(String),
+
+    #[error("Derivative market not found")]
+    DerivativeMarketNotFound,
+
+    #[error("leverage exceeds the maximum of the market")]
+    LeverageExceeded,
+
+    #[error("order book price not crossed within {0} levels")]
+    OrderBookDepthExceeded(u64),
+
+    #[error("order quantity is below the minimum quantity tick")]
+    OrderTooSmall,
+
+    #[error("order price is below the minimum price tick")]
+    InvalidOrderPrice,
+
+    #[error("invalid price {0}")]
+    InvalidPrice(Decimal256),
+
+    #[error("invalid amount {0}")]
+    InvalidAmount(String),
+
     #[error("Invalid offer asset")]
     InvalidOfferAsset,
+
+    #[error("max spread {0} exceeds the maximum of 0.5")]
+    MaxSpreadTooLarge(Decimal256),
+
+    #[error("Slippage exceeded: expected at least {minimum}, received {actual}")]
+    SlippageExceeded { minimum: Uint256, actual: Uint256 },
 }
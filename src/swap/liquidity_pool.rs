@@ -1,18 +1,33 @@
 use crate::{
-    asset::{AssetAmount, AssetInfo},
+    asset::{AssetAmount, AssetInfo, AssetMap},
     error::NeptuneResult,
+    math::div_or_zero,
     msg_wrapper::MsgWrapper,
+    querier::query_token_info,
     query_wrapper::QueryWrapper,
     send_asset::{send_assets, SendFundsMsg},
 };
-use astroport::pair::{PoolResponse, ReverseSimulationResponse, SimulationResponse};
+use astroport::{
+    asset::PairInfo,
+    factory::PairType,
+    pair::{PoolResponse, ReverseSimulationResponse, SimulationResponse},
+    router::{SimulateSwapOperationsResponse, SwapOperation},
+};
 use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{
-    to_json_binary, Addr, CosmosMsg, Decimal, Decimal256, Deps, Env, Fraction, Isqrt,
-    QuerierWrapper, QueryRequest, StdResult, Uint128, Uint256, WasmQuery,
+    from_json, to_json_binary, Addr, Binary, CosmosMsg, Decimal, Decimal256, Deps, Env, Fraction,
+    Isqrt, QuerierWrapper, StdError, StdResult, Uint128, Uint256,
 };
+use serde::{Deserialize, Serialize};
 
-use super::{error::SwapError, Swap};
+use super::{
+    concentrated::{self, Invariant},
+    error::SwapError,
+    plan::SwapPlan,
+    search_ask_amount_at_price_by_sim,
+    slippage::Slippage,
+    stable_swap, Swap,
+};
 
 #[cw_serde]
 pub struct LiquidityPool {
@@ -25,15 +40,56 @@ impl Swap for LiquidityPool {
         deps: Deps<QueryWrapper>,
         _env: &Env,
         offer_asset: &AssetInfo,
-        _ask_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
         offer_amount: Uint256,
-    ) -> NeptuneResult<Vec<CosmosMsg<MsgWrapper>>> {
-        let return_amount =
-            query_sim_pool(deps, self.addr.clone(), offer_asset.clone(), offer_amount)?;
-        if return_amount == Uint256::zero() {
-            return Ok(vec![]);
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        if offer_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
+        }
+        let sim = simulate(
+            &deps.querier,
+            self.addr.clone(),
+            &AssetAmount {
+                info: offer_asset.clone(),
+                amount: offer_amount,
+            }
+            .try_into()?,
+        )?;
+        if sim.return_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
+        }
+        self.plan(offer_asset, ask_asset, offer_amount, &sim, slippage)
+    }
+
+    /// Swaps the smallest offer amount whose return covers the ask amount,
+    /// so at least `ask_amount` is always received.
+    fn swap_ask(
+        &self,
+        deps: Deps<QueryWrapper>,
+        _env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        if ask_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
         }
-        msg_to_dex(self.addr.clone(), offer_asset.clone(), offer_amount)
+        let (offer_amount, sim) =
+            self.exact_offer_amount(deps, offer_asset, ask_asset, ask_amount)?;
+        let min_receive = slippage
+            .assert(offer_amount, sim.return_amount.into())?
+            .max(ask_amount);
+        let mut plan = self.plan(
+            offer_asset,
+            ask_asset,
+            offer_amount,
+            &sim,
+            &Slippage::MinimumReceive(min_receive),
+        )?;
+        plan.surplus = plan.ask.amount - ask_amount;
+        Ok(plan)
     }
 
     /// sends a query for a swap simulation
@@ -63,28 +119,559 @@ impl Swap for LiquidityPool {
     fn query_reverse_sim(
         &self,
         deps: Deps<QueryWrapper>,
-        _offer_asset: &AssetInfo,
+        offer_asset: &AssetInfo,
         ask_asset: &AssetInfo,
         ask_amount: Uint256,
     ) -> NeptuneResult<Uint256> {
         if ask_amount.is_zero() {
             return Ok(Uint256::zero());
         }
-        let offer_amount = reverse_simulate(
+        Ok(self
+            .exact_offer_amount(deps, offer_asset, ask_asset, ask_amount)?
+            .0)
+    }
+
+    /// Uses the invariant of the pair type, falling back to a search
+    /// over simulations for other custom pair types.
+    fn query_ask_amount_at_price(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        max_ratio: Decimal256,
+    ) -> NeptuneResult<Uint256> {
+        let pair: PairInfo = deps
+            .querier
+            .query_wasm_smart(&self.addr, &astroport::pair::QueryMsg::Pair {})?;
+        match pair.pair_type {
+            PairType::Xyk {} => {
+                let (offer_balance, ask_balance) = self.reserves(deps, offer_asset, ask_asset)?;
+                let inv_max_ratio = max_ratio.inv().ok_or(SwapError::InvalidPrice(max_ratio))?;
+                let sqrt = (offer_balance.full_mul(ask_balance) * inv_max_ratio).isqrt();
+                Ok(Uint256::from(ask_balance).saturating_sub(sqrt))
+            }
+            // The invariant is computed on reserves scaled to the greater precision of the assets.
+            PairType::Stable {} => {
+                let config = self.config(deps)?;
+                let params: StablePoolParams =
+                    from_json(config.params.ok_or(SwapError::InvalidPool)?.as_slice())?;
+                let offer_precision = precision(deps, &config.factory_addr, offer_asset)?;
+                let ask_precision = precision(deps, &config.factory_addr, ask_asset)?;
+                let precision = offer_precision.max(ask_precision);
+                let (offer_balance, ask_balance) = self.reserves(deps, offer_asset, ask_asset)?;
+                let ask_amount = stable_swap::ask_amount_at_price(
+                    stable_swap::leverage(params.amp.into()),
+                    Uint256::from(offer_balance)
+                        .checked_mul(pow10(precision - offer_precision)?)
+                        .map_err(StdError::from)?,
+                    Uint256::from(ask_balance)
+                        .checked_mul(pow10(precision - ask_precision)?)
+                        .map_err(StdError::from)?,
+                    scale_ratio(max_ratio, offer_precision, ask_precision)?,
+                )?;
+                Ok(ask_amount / pow10(precision - ask_precision)?)
+            }
+            // The invariant is computed on reserves in whole units,
+            // the second asset of the pair priced in the first by the price scale.
+            PairType::Custom(pair_type) if pair_type == CONCENTRATED_PAIR_TYPE => {
+                let config = self.config(deps)?;
+                let params: ConcentratedPoolParams =
+                    from_json(config.params.ok_or(SwapError::InvalidPool)?.as_slice())?;
+                let offer_precision = precision(deps, &config.factory_addr, offer_asset)?;
+                let ask_precision = precision(deps, &config.factory_addr, ask_asset)?;
+                let (offer_balance, ask_balance) = self.reserves(deps, offer_asset, ask_asset)?;
+                let scale = |asset: &AssetInfo| match pair.asset_infos.get(1) {
+                    Some(info) if &Into::<AssetInfo>::into(info.clone()) == asset => {
+                        params.price_scale.into()
+                    }
+                    _ => Decimal256::one(),
+                };
+                let (offer_scale, ask_scale) = (scale(offer_asset), scale(ask_asset));
+                let ask_amount = concentrated::ask_amount_at_price(
+                    Invariant::new(params.amp.into(), params.gamma.into())?,
+                    Decimal256::checked_from_ratio(offer_balance, pow10(offer_precision)?)?
+                        * offer_scale,
+                    Decimal256::checked_from_ratio(ask_balance, pow10(ask_precision)?)? * ask_scale,
+                    (scale_ratio(max_ratio, offer_precision, ask_precision)? * offer_scale)
+                        .checked_div(ask_scale)?,
+                )?
+                .checked_div(ask_scale)?;
+                Ok(ask_amount
+                    .atomics()
+                    .multiply_ratio(pow10(ask_precision)?, Decimal256::one().atomics()))
+            }
+            PairType::Custom(_) => {
+                search_ask_amount_at_price_by_sim(self, deps, offer_asset, ask_asset, max_ratio)
+            }
+        }
+    }
+}
+
+/// Upper bound on the number of simulations spent by [`search_exact_offer_amount`],
+/// which runs for every reverse simulation.
+const MAX_EXACT_OUTPUT_SIMULATIONS: u32 = 32;
+
+/// The custom pair type of Astroport concentrated liquidity pairs.
+pub(crate) const CONCENTRATED_PAIR_TYPE: &str = "concentrated";
+
+/// The configuration of an Astroport pair, other fields are ignored.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PairConfig {
+    pub params: Option<Binary>,
+    pub factory_addr: Addr,
+}
+
+/// The parameters of an Astroport stableswap pair, other fields are ignored.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct StablePoolParams {
+    pub amp: Decimal,
+}
+
+/// The parameters of an Astroport concentrated liquidity pair, other fields are ignored.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ConcentratedPoolParams {
+    pub amp: Decimal,
+    pub gamma: Decimal,
+    pub price_scale: Decimal,
+}
+
+/// The configuration of an Astroport factory, other fields are ignored.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct FactoryConfig {
+    pub coin_registry_address: Addr,
+}
+
+/// A coin of the Astroport native coin registry, other fields are ignored.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct NativeCoin {
+    pub decimals: u8,
+}
+
+/// Returns the precision of an asset the way Astroport pairs read it,
+/// from the native coin registry of the factory or from the token's info.
+fn precision(
+    deps: Deps<QueryWrapper>,
+    factory_addr: &Addr,
+    asset: &AssetInfo,
+) -> NeptuneResult<u8> {
+    match asset {
+        AssetInfo::Token { contract_addr } => {
+            Ok(query_token_info(deps.querier, contract_addr)?.decimals)
+        }
+        AssetInfo::NativeToken { denom } => {
+            let factory: FactoryConfig = deps
+                .querier
+                .query_wasm_smart(factory_addr, &astroport::factory::QueryMsg::Config {})?;
+            let coin: NativeCoin = deps.querier.query_wasm_smart(
+                factory.coin_registry_address,
+                &astroport::native_coin_registry::QueryMsg::NativeToken {
+                    denom: denom.clone(),
+                },
+            )?;
+            Ok(coin.decimals)
+        }
+    }
+}
+
+fn pow10(exponent: u8) -> NeptuneResult<Uint256> {
+    Ok(Uint256::from(10u8)
+        .checked_pow(exponent.into())
+        .map_err(StdError::from)?)
+}
+
+/// Converts a ratio of offer to ask in their smallest units to a ratio in whole units.
+fn scale_ratio(
+    ratio: Decimal256,
+    offer_precision: u8,
+    ask_precision: u8,
+) -> NeptuneResult<Decimal256> {
+    if ask_precision >= offer_precision {
+        let scale = Decimal256::checked_from_ratio(pow10(ask_precision - offer_precision)?, 1u8)?;
+        Ok(ratio.checked_mul(scale).map_err(StdError::from)?)
+    } else {
+        let scale = Decimal256::checked_from_ratio(pow10(offer_precision - ask_precision)?, 1u8)?;
+        Ok(ratio.checked_div(scale)?)
+    }
+}
+
+impl LiquidityPool {
+    fn config(&self, deps: Deps<QueryWrapper>) -> NeptuneResult<PairConfig> {
+        Ok(deps
+            .querier
+            .query_wasm_smart(&self.addr, &astroport::pair::QueryMsg::Config {})?)
+    }
+
+    /// Returns the reserves of the offer and the ask asset.
+    fn reserves(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+    ) -> NeptuneResult<(Uint128, Uint128)> {
+        let res: PoolResponse = deps
+            .querier
+            .query_wasm_smart(&self.addr, &astroport::pair::QueryMsg::Pool {})?;
+        let balance = |asset: &AssetInfo| {
+            res.assets
+                .iter()
+                .find(|x| &Into::<AssetInfo>::into(x.info.clone()) == asset)
+                .map(|x| x.amount)
+                .ok_or(SwapError::InvalidPool)
+        };
+        Ok((balance(offer_asset)?, balance(ask_asset)?))
+    }
+
+    /// Creates the plan of a swap from its simulation.
+    fn plan(
+        &self,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+        sim: &SimulationResponse,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let return_amount = Uint256::from(sim.return_amount);
+        let min_receive = slippage.assert(offer_amount, return_amount)?;
+        let (belief_price, max_spread) = slippage.to_astroport(offer_amount, return_amount)?;
+        let msgs = msg_to_dex(
+            self.addr.clone(),
+            offer_asset.clone(),
+            offer_amount,
+            belief_price,
+            max_spread,
+        )?;
+        Ok(SwapPlan {
+            msgs,
+            offer: AssetAmount {
+                info: offer_asset.clone(),
+                amount: offer_amount,
+            },
+            ask: AssetAmount {
+                info: ask_asset.clone(),
+                amount: return_amount,
+            },
+            commission: (ask_asset.clone(), Uint256::from(sim.commission_amount)).into(),
+            price_impact: price_impact(sim),
+            min_receive,
+            surplus: Uint256::zero(),
+            venue: None,
+            legs: vec![],
+        })
+    }
+
+    /// Finds the smallest offer amount whose simulated return is at least `ask_amount`.
+    /// The pool's reverse simulation is only used as a starting point since it rounds
+    /// in either direction.
+    fn exact_offer_amount(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<(Uint256, SimulationResponse)> {
+        let estimate = reverse_simulate(
             &deps.querier,
             self.addr.clone(),
             &AssetAmount {
                 info: ask_asset.clone(),
-                amount: ask_amount + Uint256::one(),
+                amount: ask_amount,
             }
             .try_into()?,
         )?
-        .offer_amount
-            + Uint128::one(); // We always add 1 here to avoid rounding errors
-        Ok(offer_amount.into())
+        .offer_amount;
+        search_exact_offer_amount(estimate.into(), ask_amount, |offer_amount| {
+            Ok(simulate(
+                &deps.querier,
+                self.addr.clone(),
+                &AssetAmount {
+                    info: offer_asset.clone(),
+                    amount: offer_amount,
+                }
+                .try_into()?,
+            )?)
+        })
+    }
+}
+
+/// Searches for the smallest offer amount whose simulated return is at least `ask_amount`,
+/// starting from an estimate. The surplus received is therefore less than the return
+/// of a single extra unit of the offer asset. Once [`MAX_EXACT_OUTPUT_SIMULATIONS`]
+/// are spent, the smallest offer amount found to cover the ask amount is returned instead.
+pub(crate) fn search_exact_offer_amount(
+    estimate: Uint256,
+    ask_amount: Uint256,
+    sim: impl Fn(Uint256) -> NeptuneResult<SimulationResponse>,
+) -> NeptuneResult<(Uint256, SimulationResponse)> {
+    let covers = |sim: &SimulationResponse| Uint256::from(sim.return_amount) >= ask_amount;
+
+    // `lower` never covers the ask amount, `upper` always does once found.
+    let mut lower = Uint256::zero();
+    let mut upper = estimate.max(Uint256::one());
+    let mut upper_sim = sim(upper)?;
+    let mut simulations = 1;
+
+    // Step up from the estimate until the ask amount is covered.
+    let mut step = Uint256::one();
+    while !covers(&upper_sim) {
+        if simulations == MAX_EXACT_OUTPUT_SIMULATIONS {
+            return Err(SwapError::InsufficientLiquidity.into());
+        }
+        lower = upper;
+        upper += step;
+        upper_sim = sim(upper)?;
+        simulations += 1;
+        step *= Uint256::from(2u8);
+    }
+
+    // Otherwise step down from the estimate until it is no longer covered.
+    if simulations == 1 {
+        step = Uint256::one();
+        while simulations < MAX_EXACT_OUTPUT_SIMULATIONS {
+            let candidate = upper.saturating_sub(step);
+            if candidate.is_zero() {
+                break;
+            }
+            let candidate_sim = sim(candidate)?;
+            simulations += 1;
+            if !covers(&candidate_sim) {
+                lower = candidate;
+                break;
+            }
+            upper = candidate;
+            upper_sim = candidate_sim;
+            step *= Uint256::from(2u8);
+        }
+    }
+
+    // Bisect down to the smallest offer amount that covers the ask amount.
+    while upper - lower > Uint256::one() && simulations < MAX_EXACT_OUTPUT_SIMULATIONS {
+        let mid = lower + (upper - lower) / Uint256::from(2u8);
+        let mid_sim = sim(mid)?;
+        simulations += 1;
+        if covers(&mid_sim) {
+            upper = mid;
+            upper_sim = mid_sim;
+        } else {
+            lower = mid;
+        }
+    }
+
+    Ok((upper, upper_sim))
+}
+
+/// A route through several Astroport pairs, executed in a single message by the Astroport router.
+#[cw_serde]
+pub struct Router {
+    pub addr: Addr,
+    /// The assets along the route, from one end of the pair to the other.
+    /// The route can be traded in both directions.
+    pub hops: Vec<AssetInfo>,
+}
+
+impl Router {
+    /// Returns the assets along the route in the direction of the swap.
+    fn route(
+        &self,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+    ) -> NeptuneResult<Vec<AssetInfo>> {
+        match (self.hops.first(), self.hops.last()) {
+            (Some(first), Some(last)) if first == offer_asset && last == ask_asset => {
+                Ok(self.hops.clone())
+            }
+            (Some(first), Some(last)) if first == ask_asset && last == offer_asset => {
+                Ok(self.hops.iter().rev().cloned().collect())
+            }
+            _ => Err(SwapError::InvalidAsset.into()),
+        }
+    }
+
+    fn operations(
+        &self,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+    ) -> NeptuneResult<Vec<SwapOperation>> {
+        Ok(self
+            .route(offer_asset, ask_asset)?
+            .windows(2)
+            .map(|hop| SwapOperation::AstroSwap {
+                offer_asset_info: hop[0].clone().into(),
+                ask_asset_info: hop[1].clone().into(),
+            })
+            .collect())
+    }
+
+    /// Returns the pair of every hop along the route, as registered in the router's factory.
+    fn pools(
+        &self,
+        deps: Deps<QueryWrapper>,
+        route: &[AssetInfo],
+    ) -> NeptuneResult<Vec<LiquidityPool>> {
+        let config: astroport::router::ConfigResponse = deps
+            .querier
+            .query_wasm_smart(&self.addr, &astroport::router::QueryMsg::Config {})?;
+        route
+            .windows(2)
+            .map(|hop| -> NeptuneResult<LiquidityPool> {
+                let pair: PairInfo = deps.querier.query_wasm_smart(
+                    &config.astroport_factory,
+                    &astroport::factory::QueryMsg::Pair {
+                        asset_infos: vec![hop[0].clone().into(), hop[1].clone().into()],
+                    },
+                )?;
+                Ok(LiquidityPool {
+                    addr: pair.contract_addr,
+                })
+            })
+            .collect()
+    }
+
+    /// Creates the plan of a swap through the router from its simulated return.
+    fn plan(
+        &self,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+        return_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let min_receive = slippage.assert(offer_amount, return_amount)?;
+        let operations = self.operations(offer_asset, ask_asset)?;
+        let minimum_receive = Some(min_receive.try_into()?);
+        let max_spread = Some(slippage.to_astroport_router()?);
+        let swap_msg = match offer_asset {
+            AssetInfo::NativeToken { .. } => {
+                to_json_binary(&astroport::router::ExecuteMsg::ExecuteSwapOperations {
+                    operations,
+                    minimum_receive,
+                    to: None,
+                    max_spread,
+                })?
+            }
+            AssetInfo::Token { .. } => {
+                to_json_binary(&astroport::router::Cw20HookMsg::ExecuteSwapOperations {
+                    operations,
+                    minimum_receive,
+                    to: None,
+                    max_spread,
+                })?
+            }
+        };
+        let msg = send_assets(&self.addr, offer_amount, offer_asset.clone(), swap_msg)?;
+        // The router does not report the commission or the spread of its hops.
+        Ok(SwapPlan {
+            msgs: vec![msg],
+            offer: AssetAmount {
+                info: offer_asset.clone(),
+                amount: offer_amount,
+            },
+            ask: AssetAmount {
+                info: ask_asset.clone(),
+                amount: return_amount,
+            },
+            commission: AssetMap::new(),
+            price_impact: Decimal256::zero(),
+            min_receive,
+            surplus: Uint256::zero(),
+            venue: None,
+            legs: vec![],
+        })
+    }
+}
+
+impl Swap for Router {
+    fn swap(
+        &self,
+        deps: Deps<QueryWrapper>,
+        _env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let return_amount = self.query_sim(deps, offer_asset, ask_asset, offer_amount)?;
+        if return_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
+        }
+        self.plan(
+            offer_asset,
+            ask_asset,
+            offer_amount,
+            return_amount,
+            slippage,
+        )
+    }
+
+    /// Offers the amount required by each pair to return at least what the next pair requires,
+    /// so at least `ask_amount` is always received.
+    fn swap_ask(
+        &self,
+        deps: Deps<QueryWrapper>,
+        _env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        if ask_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
+        }
+        let offer_amount = self.query_reverse_sim(deps, offer_asset, ask_asset, ask_amount)?;
+        let return_amount = self.query_sim(deps, offer_asset, ask_asset, offer_amount)?;
+        let min_receive = slippage
+            .assert(offer_amount, return_amount)?
+            .max(ask_amount);
+        let mut plan = self.plan(
+            offer_asset,
+            ask_asset,
+            offer_amount,
+            return_amount,
+            &Slippage::MinimumReceive(min_receive),
+        )?;
+        plan.surplus = return_amount.saturating_sub(ask_amount);
+        Ok(plan)
+    }
+
+    fn query_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        if offer_amount.is_zero() {
+            return Ok(Uint256::zero());
+        }
+        let res: SimulateSwapOperationsResponse = deps.querier.query_wasm_smart(
+            &self.addr,
+            &astroport::router::QueryMsg::SimulateSwapOperations {
+                offer_amount: offer_amount.try_into()?,
+                operations: self.operations(offer_asset, ask_asset)?,
+            },
+        )?;
+        Ok(res.amount.into())
+    }
+
+    /// The router has no reverse simulation, the pairs along the route are simulated in reverse instead.
+    fn query_reverse_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        if ask_amount.is_zero() {
+            return Ok(Uint256::zero());
+        }
+        let route = self.route(offer_asset, ask_asset)?;
+        let pools = self.pools(deps, &route)?;
+        let mut amount = ask_amount;
+        for (hop, pool) in route.windows(2).zip(pools).rev() {
+            amount = pool.query_reverse_sim(deps, &hop[0], &hop[1], amount)?;
+        }
+        Ok(amount)
     }
 
-    /// This function assumes constant product
     fn query_ask_amount_at_price(
         &self,
         deps: Deps<QueryWrapper>,
@@ -92,25 +679,7 @@ impl Swap for LiquidityPool {
         ask_asset: &AssetInfo,
         max_ratio: Decimal256,
     ) -> NeptuneResult<Uint256> {
-        let res: PoolResponse = deps
-            .querier
-            .query_wasm_smart(&self.addr, &astroport::pair::QueryMsg::Pool {})?;
-        let offer_balance = res
-            .assets
-            .iter()
-            .find(|x| &Into::<AssetInfo>::into(x.info.clone()) == offer_asset)
-            .ok_or(SwapError::InvalidPool)?
-            .amount;
-        let ask_balance = res
-            .assets
-            .iter()
-            .find(|x| &Into::<AssetInfo>::into(x.info.clone()) == ask_asset)
-            .ok_or(SwapError::InvalidPool)?
-            .amount;
-        let mul = offer_balance.full_mul(ask_balance);
-        let frac = mul * max_ratio.inv().unwrap();
-        let sqrt = frac.isqrt();
-        Ok(Uint256::from(ask_balance).saturating_sub(sqrt))
+        search_ask_amount_at_price_by_sim(self, deps, offer_asset, ask_asset, max_ratio)
     }
 }
 
@@ -147,6 +716,8 @@ fn msg_to_dex(
     swap_pool: Addr,
     offer_asset: SendFundsMsg,
     offer_amount: Uint256,
+    belief_price: Option<Decimal>,
+    max_spread: Option<Decimal>,
 ) -> NeptuneResult<Vec<CosmosMsg<MsgWrapper>>> {
     let swap_msg = to_json_binary(&astroport::pair::ExecuteMsg::Swap {
         offer_asset: AssetAmount {
@@ -154,8 +725,8 @@ fn msg_to_dex(
             amount: offer_amount,
         }
         .try_into()?,
-        belief_price: None,
-        max_spread: Some(Decimal::percent(50)),
+        belief_price,
+        max_spread,
         to: None,
         ask_asset_info: None,
     })?;
@@ -163,27 +734,218 @@ fn msg_to_dex(
     Ok(vec![msg])
 }
 
-/// queries a pool and simulates a swap.
-fn query_sim_pool(
-    deps: Deps<QueryWrapper>,
-    pool_addr: Addr,
-    offer_asset: AssetInfo,
-    offer_amount: Uint256,
-) -> NeptuneResult<Uint256> {
-    if offer_amount.is_zero() {
-        return Ok(Uint256::zero());
+/// The fraction of the return lost to the spread, before commission.
+pub(crate) fn price_impact(sim: &SimulationResponse) -> Decimal256 {
+    let total = Uint256::from(sim.return_amount)
+        + Uint256::from(sim.spread_amount)
+        + Uint256::from(sim.commission_amount);
+    div_or_zero(sim.spread_amount.into(), total)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::swap::{
+        mock_querier::{mock_dependencies_with_pools, MockAstroport},
+        simulated_pool::{Curve, SimulatedPool},
+    };
+
+    fn native(denom: &str) -> AssetInfo {
+        AssetInfo::NativeToken {
+            denom: denom.to_string(),
+        }
     }
 
-    let res: SimulationResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-        contract_addr: pool_addr.to_string(),
-        msg: to_json_binary(&astroport::pair::QueryMsg::Simulation {
-            offer_asset: astroport::asset::Asset {
-                info: offer_asset.into(),
-                amount: offer_amount.try_into()?,
-            },
-            ask_asset_info: None,
-        })?,
-    }))?;
+    #[test]
+    fn test_router_route() {
+        let router = Router {
+            addr: Addr::unchecked("router"),
+            hops: vec![native("atom"), native("usdc"), native("inj")],
+        };
+        assert_eq!(
+            router.route(&native("atom"), &native("inj")).unwrap(),
+            router.hops
+        );
+        assert_eq!(
+            router.route(&native("inj"), &native("atom")).unwrap(),
+            vec![native("inj"), native("usdc"), native("atom")]
+        );
+        assert!(router.route(&native("atom"), &native("usdc")).is_err());
 
-    Ok(res.return_amount.into())
+        let operations = router.operations(&native("inj"), &native("atom")).unwrap();
+        assert_eq!(
+            operations,
+            vec![
+                SwapOperation::AstroSwap {
+                    offer_asset_info: native("inj").into(),
+                    ask_asset_info: native("usdc").into(),
+                },
+                SwapOperation::AstroSwap {
+                    offer_asset_info: native("usdc").into(),
+                    ask_asset_info: native("atom").into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query_ask_amount_at_price() {
+        let (offer_asset, ask_asset) = (native("uatom"), native("uinj"));
+        let pool = |reserves: [u128; 2], curve: Curve| SimulatedPool {
+            assets: [offer_asset.clone(), ask_asset.clone()],
+            reserves: reserves.map(Uint128::from),
+            commission_rate: Decimal::permille(3),
+            curve,
+        };
+        let amp = Decimal::from_ratio(100u64, 1u64);
+        let concentrated = |price_scale: Decimal| Curve::Concentrated {
+            amp: Decimal::from_ratio(10u64, 1u64),
+            gamma: Decimal::from_ratio(145u64, 1_000_000u64),
+            price_scale,
+        };
+        let deps = mock_dependencies_with_pools(
+            MockAstroport::default()
+                .with_decimals("uinj", 8)
+                .with_pool("xyk", pool([1_000_000, 1_000_000], Curve::ConstantProduct))
+                .with_pool(
+                    "stable",
+                    pool([1_000_000_000, 100_000_000_000], Curve::StableSwap { amp }),
+                )
+                .with_pool(
+                    "pcl",
+                    pool(
+                        [1_000_000_000_000, 100_000_000_000_000],
+                        concentrated(Decimal::one()),
+                    ),
+                )
+                .with_pool(
+                    "scaled_pcl",
+                    pool(
+                        [1_000_000_000_000, 50_000_000_000_000],
+                        concentrated(Decimal::from_ratio(2u8, 1u8)),
+                    ),
+                ),
+        );
+        let query = |addr: &str, max_ratio: Decimal256| {
+            LiquidityPool {
+                addr: Addr::unchecked(addr),
+            }
+            .query_ask_amount_at_price(
+                deps.as_ref(),
+                &offer_asset,
+                &ask_asset,
+                max_ratio,
+            )
+        };
+
+        assert_eq!(
+            query("xyk", Decimal256::zero()),
+            Err(SwapError::InvalidPrice(Decimal256::zero()).into())
+        );
+
+        // The offer asset has 6 decimals and the ask asset 8, so whole units trade one to one
+        // at a ratio of a hundredth. The stableswap invariant is computed on 8 decimals.
+        assert_eq!(
+            query("stable", Decimal256::from_ratio(101u64, 10_000u64)).unwrap(),
+            stable_swap::ask_amount_at_price(
+                stable_swap::leverage(amp.into()),
+                100_000_000_000u64.into(),
+                100_000_000_000u64.into(),
+                Decimal256::percent(101),
+            )
+            .unwrap()
+        );
+
+        // The concentrated liquidity invariant is computed on whole units,
+        // matching a simulated pool whose reserves are a million whole units.
+        let ask_amount = query("pcl", Decimal256::from_ratio(101u64, 10_000u64)).unwrap();
+        let expected = pool(
+            [1_000_000_000_000, 1_000_000_000_000],
+            concentrated(Decimal::one()),
+        )
+        .query_ask_amount_at_price(
+            deps.as_ref(),
+            &offer_asset,
+            &ask_asset,
+            Decimal256::percent(101),
+        )
+        .unwrap()
+            * Uint256::from(100u8);
+        assert!(!ask_amount.is_zero());
+        assert!(
+            ask_amount.max(expected) - ask_amount.min(expected)
+                <= expected / Uint256::from(1_000_000u64)
+        );
+
+        // A whole unit of the ask asset is worth two of the offer asset.
+        assert!(
+            query("scaled_pcl", Decimal256::from_ratio(198u64, 10_000u64))
+                .unwrap()
+                .is_zero()
+        );
+        assert!(
+            !query("scaled_pcl", Decimal256::from_ratio(202u64, 10_000u64))
+                .unwrap()
+                .is_zero()
+        );
+    }
+
+    #[test]
+    fn test_exact_offer_amount() {
+        let (offer_asset, ask_asset) = (native("uatom"), native("uusdc"));
+        let pool = |curve: Curve| SimulatedPool {
+            assets: [offer_asset.clone(), ask_asset.clone()],
+            reserves: [1_000_000_000u128.into(), 3_000_000_000u128.into()],
+            commission_rate: Decimal::permille(3),
+            curve,
+        };
+        let xyk = pool(Curve::ConstantProduct);
+        let stable = pool(Curve::StableSwap {
+            amp: Decimal::from_ratio(50u64, 1u64),
+        });
+        let deps = mock_dependencies_with_pools(
+            MockAstroport::default()
+                .with_pool("xyk", xyk.clone())
+                .with_pool("stable", stable.clone()),
+        );
+
+        for (addr, pool) in [("xyk", &xyk), ("stable", &stable)] {
+            let liquidity_pool = LiquidityPool {
+                addr: Addr::unchecked(addr),
+            };
+            let ask_amount = Uint256::from(12_345_678u64);
+            let offer_amount = liquidity_pool
+                .query_reverse_sim(deps.as_ref(), &offer_asset, &ask_asset, ask_amount)
+                .unwrap();
+            // The offer amount is the smallest whose return covers the ask amount.
+            let return_amount = |offer_amount: Uint256| {
+                Uint256::from(
+                    pool.simulate(&offer_asset, offer_amount.try_into().unwrap())
+                        .unwrap()
+                        .return_amount,
+                )
+            };
+            assert!(return_amount(offer_amount) >= ask_amount);
+            assert!(return_amount(offer_amount - Uint256::one()) < ask_amount);
+        }
+
+        // A poor estimate spends the whole budget, settling for an offer amount which covers.
+        let simulations = Cell::new(0);
+        let ask_amount = Uint256::from(1_000_000u64);
+        let (offer_amount, sim) =
+            search_exact_offer_amount(Uint256::one(), ask_amount, |offer_amount| {
+                simulations.set(simulations.get() + 1);
+                xyk.simulate(&offer_asset, offer_amount.try_into()?)
+            })
+            .unwrap();
+        assert_eq!(simulations.get(), MAX_EXACT_OUTPUT_SIMULATIONS);
+        assert!(Uint256::from(sim.return_amount) >= ask_amount);
+        assert_eq!(
+            sim,
+            xyk.simulate(&offer_asset, offer_amount.try_into().unwrap())
+                .unwrap()
+        );
+    }
 }
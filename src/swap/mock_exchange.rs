@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{
+    testing::{MockApi, MockQuerier, MockStorage},
+    to_json_binary, Binary, ContractResult, CosmosMsg, OwnedDeps, QuerierResult, StdError,
+    SystemError, SystemResult,
+};
+use injective_cosmwasm::{
+    exchange::{response::QueryOrderbookResponse, types::PriceLevel},
+    DerivativeMarket, DerivativeMarketResponse, DerivativeOrder, FullDerivativeMarket,
+    InjectiveMsg, InjectiveQuery, MarketId, OrderSide, OrderType,
+    QueryMarketAtomicExecutionFeeMultiplierResponse, SpotMarket, SpotMarketResponse, SpotOrder,
+};
+use injective_math::FPDecimal;
+use serde::Serialize;
+
+use crate::{
+    error::NeptuneResult, msg_wrapper::MsgWrapper, query_wrapper::QueryWrapper,
+    testing::mock_dependencies,
+};
+
+/// Mock dependencies whose exchange queries are answered by the given spot markets.
+pub fn mock_dependencies_with_exchange(
+    exchange: MockExchange,
+) -> OwnedDeps<MockStorage, MockApi, MockQuerier<QueryWrapper>, QueryWrapper> {
+    let mut deps = mock_dependencies();
+    deps.querier = deps
+        .querier
+        .with_custom_handler(move |query| exchange.handle(query));
+    deps
+}
+
+/// Parses a decimal, panicking if it is invalid.
+pub fn fp(value: &str) -> FPDecimal {
+    FPDecimal::must_from_str(value)
+}
+
+/// A spot market along with the price levels of its order book.
+#[derive(Clone, Debug)]
+pub struct MockSpotMarket {
+    pub market: SpotMarket,
+    /// Buy orders, from the highest price to the lowest.
+    pub buys: Vec<PriceLevel>,
+    /// Sell orders, from the lowest price to the highest.
+    pub sells: Vec<PriceLevel>,
+    pub atomic_execution_fee_multiplier: FPDecimal,
+    /// Resting orders, the hash of each order is its index in hex.
+    pub open_orders: Vec<SpotOrder>,
+}
+
+/// A derivative market along with its mark price and the price levels of its order book.
+#[derive(Clone, Debug)]
+pub struct MockDerivativeMarket {
+    pub market: DerivativeMarket,
+    pub mark_price: FPDecimal,
+    /// Buy orders, from the highest price to the lowest.
+    pub buys: Vec<PriceLevel>,
+    /// Sell orders, from the lowest price to the highest.
+    pub sells: Vec<PriceLevel>,
+}
+
+/// Answers the `SpotMarket`, `SpotOrderbook`, `MarketAtomicExecutionFeeMultiplier`,
+/// `TraderSpotOrders`, `DerivativeMarket` and `DerivativeOrderbook` queries
+/// of the Injective exchange module, keyed by market id.
+/// Orders created and cancelled by executed messages are recorded in `orders`,
+/// `derivative_orders` and `cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct MockExchange {
+    pub markets: BTreeMap<String, MockSpotMarket>,
+    pub derivative_markets: BTreeMap<String, MockDerivativeMarket>,
+    pub orders: Vec<SpotOrder>,
+    pub derivative_orders: Vec<DerivativeOrder>,
+    /// The hashes of the cancelled orders.
+    pub cancelled: Vec<String>,
+}
+
+/// The order book's representation of a resting order.
+#[derive(Serialize)]
+struct TrimmedOrder {
+    price: FPDecimal,
+    quantity: FPDecimal,
+    fillable: FPDecimal,
+    #[serde(rename = "isBuy")]
+    is_buy: bool,
+    order_hash: String,
+}
+
+#[derive(Serialize)]
+struct TraderOrders {
+    orders: Vec<TrimmedOrder>,
+}
+
+/// The hash of the resting order at the given index.
+pub fn order_hash(index: usize) -> String {
+    format!("0x{index:064x}")
+}
+
+impl MockExchange {
+    pub fn with_market(mut self, market: MockSpotMarket) -> Self {
+        self.markets
+            .insert(market.market.market_id.as_str().to_string(), market);
+        self
+    }
+
+    pub fn with_derivative_market(mut self, market: MockDerivativeMarket) -> Self {
+        self.derivative_markets
+            .insert(market.market.market_id.as_str().to_string(), market);
+        self
+    }
+
+    /// Records the orders created and cancelled by the given messages, ignoring any others.
+    pub fn execute(&mut self, msgs: &[CosmosMsg<MsgWrapper>]) {
+        for msg in msgs {
+            let CosmosMsg::Custom(MsgWrapper { msg_data, .. }) = msg else {
+                continue;
+            };
+            match msg_data {
+                InjectiveMsg::CreateSpotMarketOrder { order, .. }
+                | InjectiveMsg::CreateSpotLimitOrder { order, .. } => {
+                    self.orders.push(order.clone())
+                }
+                InjectiveMsg::CreateDerivativeMarketOrder { order, .. } => {
+                    self.derivative_orders.push(order.clone())
+                }
+                InjectiveMsg::CancelSpotOrder { order_hash, .. } => {
+                    self.cancelled.push(order_hash.clone())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn handle(&self, query: &QueryWrapper) -> QuerierResult {
+        let res: NeptuneResult<Binary> = match &query.query_data {
+            InjectiveQuery::SpotMarket { market_id } => to_json_binary(&SpotMarketResponse {
+                market: self
+                    .markets
+                    .get(market_id.as_str())
+                    .map(|market| market.market.clone()),
+            })
+            .map_err(Into::into),
+            InjectiveQuery::SpotOrderbook {
+                market_id,
+                limit,
+                order_side,
+                limit_cumulative_quantity,
+                limit_cumulative_notional,
+            } => self.market(market_id).and_then(|market| {
+                let levels = |levels: &[PriceLevel]| {
+                    book_levels(
+                        levels,
+                        *limit,
+                        *limit_cumulative_quantity,
+                        *limit_cumulative_notional,
+                    )
+                };
+                let (buys_price_level, sells_price_level) = match order_side {
+                    OrderSide::Buy => (levels(&market.buys), vec![]),
+                    OrderSide::Sell => (vec![], levels(&market.sells)),
+                    _ => (levels(&market.buys), levels(&market.sells)),
+                };
+                Ok(to_json_binary(&QueryOrderbookResponse {
+                    buys_price_level,
+                    sells_price_level,
+                })?)
+            }),
+            InjectiveQuery::MarketAtomicExecutionFeeMultiplier { market_id } => {
+                self.market(market_id).and_then(|market| {
+                    Ok(to_json_binary(
+                        &QueryMarketAtomicExecutionFeeMultiplierResponse {
+                            multiplier: market.atomic_execution_fee_multiplier,
+                        },
+                    )?)
+                })
+            }
+            InjectiveQuery::TraderSpotOrders {
+                market_id,
+                subaccount_id,
+            } => self.market(market_id).and_then(|market| {
+                let orders = market
+                    .open_orders
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, order)| &order.order_info.subaccount_id == subaccount_id)
+                    .map(|(index, order)| TrimmedOrder {
+                        price: order.order_info.price,
+                        quantity: order.order_info.quantity,
+                        fillable: order.order_info.quantity,
+                        is_buy: matches!(
+                            order.order_type,
+                            OrderType::Buy | OrderType::BuyPo | OrderType::BuyAtomic
+                        ),
+                        order_hash: order_hash(index),
+                    })
+                    .collect();
+                Ok(to_json_binary(&TraderOrders { orders })?)
+            }),
+            InjectiveQuery::DerivativeMarket { market_id } => {
+                let market = self.derivative_markets.get(market_id.as_str());
+                to_json_binary(&DerivativeMarketResponse {
+                    market: FullDerivativeMarket {
+                        market: market.map(|market| market.market.clone()),
+                        info: None,
+                        mark_price: market.map_or(FPDecimal::ZERO, |market| market.mark_price),
+                    },
+                })
+                .map_err(Into::into)
+            }
+            InjectiveQuery::DerivativeOrderbook {
+                market_id,
+                limit,
+                limit_cumulative_notional,
+            } => self.derivative_market(market_id).and_then(|market| {
+                let levels = |levels: &[PriceLevel]| {
+                    book_levels(levels, *limit, None, *limit_cumulative_notional)
+                };
+                Ok(to_json_binary(&QueryOrderbookResponse {
+                    buys_price_level: levels(&market.buys),
+                    sells_price_level: levels(&market.sells),
+                })?)
+            }),
+            _ => {
+                return SystemResult::Err(SystemError::UnsupportedRequest {
+                    kind: "injective query".to_string(),
+                })
+            }
+        };
+        SystemResult::Ok(match res {
+            Ok(binary) => ContractResult::Ok(binary),
+            Err(err) => ContractResult::Err(err.to_string()),
+        })
+    }
+
+    fn market(&self, market_id: &MarketId) -> NeptuneResult<&MockSpotMarket> {
+        Ok(self
+            .markets
+            .get(market_id.as_str())
+            .ok_or_else(|| StdError::not_found(market_id.as_str()))?)
+    }
+
+    fn derivative_market(&self, market_id: &MarketId) -> NeptuneResult<&MockDerivativeMarket> {
+        Ok(self
+            .derivative_markets
+            .get(market_id.as_str())
+            .ok_or_else(|| StdError::not_found(market_id.as_str()))?)
+    }
+}
+
+/// Returns the best price levels up to the first one at which any of the limits is reached,
+/// a limit of zero returns every level.
+fn book_levels(
+    levels: &[PriceLevel],
+    limit: u64,
+    limit_cumulative_quantity: Option<FPDecimal>,
+    limit_cumulative_notional: Option<FPDecimal>,
+) -> Vec<PriceLevel> {
+    let mut cumulative_quantity = FPDecimal::ZERO;
+    let mut cumulative_notional = FPDecimal::ZERO;
+    let mut book = vec![];
+    for level in levels {
+        if (limit != 0 && book.len() as u64 >= limit)
+            || limit_cumulative_quantity.is_some_and(|limit| cumulative_quantity >= limit)
+            || limit_cumulative_notional.is_some_and(|limit| cumulative_notional >= limit)
+        {
+            break;
+        }
+        cumulative_quantity += level.q;
+        cumulative_notional += level.q * level.p;
+        book.push(level.clone());
+    }
+    book
+}
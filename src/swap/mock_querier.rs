@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+
+use astroport::{
+    asset::{Asset, PairInfo},
+    factory::PairType,
+    native_coin_registry::QueryMsg as CoinRegistryQueryMsg,
+    pair::{PoolResponse, QueryMsg},
+};
+use cosmwasm_std::{
+    from_json,
+    testing::{MockApi, MockQuerier, MockStorage},
+    to_json_binary, Addr, Binary, ContractResult, OwnedDeps, QuerierResult, StdError, SystemError,
+    SystemResult, Uint128, WasmQuery,
+};
+
+use crate::{error::NeptuneResult, query_wrapper::QueryWrapper, testing::mock_dependencies};
+
+use super::{
+    liquidity_pool::{
+        ConcentratedPoolParams, FactoryConfig, NativeCoin, PairConfig, StablePoolParams,
+        CONCENTRATED_PAIR_TYPE,
+    },
+    simulated_pool::{Curve, SimulatedPool},
+};
+
+/// Mock dependencies whose wasm queries are answered by the given Astroport pools.
+pub fn mock_dependencies_with_pools(
+    astroport: MockAstroport,
+) -> OwnedDeps<MockStorage, MockApi, MockQuerier<QueryWrapper>, QueryWrapper> {
+    let mut deps = mock_dependencies();
+    deps.querier
+        .update_wasm(move |query| astroport.handle(query));
+    deps
+}
+
+/// The address of the factory of the mock Astroport pairs.
+pub const MOCK_FACTORY: &str = "astroport_factory";
+
+/// The address of the native coin registry of the mock Astroport factory.
+pub const MOCK_COIN_REGISTRY: &str = "astroport_coin_registry";
+
+/// The precision of native coins missing from the mock coin registry.
+const DEFAULT_DECIMALS: u8 = 6;
+
+/// Answers the `Pair`, `Pool`, `Config`, `Simulation` and `ReverseSimulation` queries
+/// of Astroport pairs from simulated pools, keyed by the address of the pair,
+/// along with the queries of their factory's native coin registry.
+#[derive(Clone, Debug, Default)]
+pub struct MockAstroport {
+    pub pools: BTreeMap<String, SimulatedPool>,
+    /// The precisions of native coins, keyed by denom.
+    pub decimals: BTreeMap<String, u8>,
+}
+
+impl MockAstroport {
+    pub fn with_pool(mut self, addr: &str, pool: SimulatedPool) -> Self {
+        self.pools.insert(addr.to_string(), pool);
+        self
+    }
+
+    pub fn with_decimals(mut self, denom: &str, decimals: u8) -> Self {
+        self.decimals.insert(denom.to_string(), decimals);
+        self
+    }
+
+    pub fn handle(&self, query: &WasmQuery) -> QuerierResult {
+        let WasmQuery::Smart { contract_addr, msg } = query else {
+            return SystemResult::Err(SystemError::UnsupportedRequest {
+                kind: "raw wasm query".to_string(),
+            });
+        };
+        let res: NeptuneResult<Binary> = match contract_addr.as_str() {
+            MOCK_FACTORY => to_json_binary(&FactoryConfig {
+                coin_registry_address: Addr::unchecked(MOCK_COIN_REGISTRY),
+            })
+            .map_err(Into::into),
+            MOCK_COIN_REGISTRY => from_json(msg.as_slice())
+                .map_err(Into::into)
+                .and_then(|msg| self.query_coin_registry(msg)),
+            _ => {
+                let Some(pool) = self.pools.get(contract_addr) else {
+                    return SystemResult::Err(SystemError::NoSuchContract {
+                        addr: contract_addr.clone(),
+                    });
+                };
+                from_json(msg.as_slice())
+                    .map_err(Into::into)
+                    .and_then(|msg| query_pool(contract_addr, pool, msg))
+            }
+        };
+        SystemResult::Ok(match res {
+            Ok(binary) => ContractResult::Ok(binary),
+            Err(err) => ContractResult::Err(err.to_string()),
+        })
+    }
+}
+
+impl MockAstroport {
+    fn query_coin_registry(&self, msg: CoinRegistryQueryMsg) -> NeptuneResult<Binary> {
+        match msg {
+            CoinRegistryQueryMsg::NativeToken { denom } => Ok(to_json_binary(&NativeCoin {
+                decimals: self
+                    .decimals
+                    .get(&denom)
+                    .copied()
+                    .unwrap_or(DEFAULT_DECIMALS),
+            })?),
+            _ => Err(StdError::generic_err("query not supported by the mock coin registry").into()),
+        }
+    }
+}
+
+fn query_pool(addr: &str, pool: &SimulatedPool, msg: QueryMsg) -> NeptuneResult<Binary> {
+    Ok(match msg {
+        QueryMsg::Pair {} => to_json_binary(&PairInfo {
+            asset_infos: pool.assets.iter().cloned().map(Into::into).collect(),
+            contract_addr: Addr::unchecked(addr),
+            liquidity_token: Addr::unchecked(format!("{addr}_lp")),
+            pair_type: match pool.curve {
+                Curve::ConstantProduct => PairType::Xyk {},
+                Curve::StableSwap { .. } => PairType::Stable {},
+                Curve::Concentrated { .. } => PairType::Custom(CONCENTRATED_PAIR_TYPE.to_string()),
+            },
+        })?,
+        QueryMsg::Config {} => to_json_binary(&PairConfig {
+            params: match &pool.curve {
+                Curve::ConstantProduct => None,
+                Curve::StableSwap { amp } => Some(to_json_binary(&StablePoolParams { amp: *amp })?),
+                Curve::Concentrated {
+                    amp,
+                    gamma,
+                    price_scale,
+                } => Some(to_json_binary(&ConcentratedPoolParams {
+                    amp: *amp,
+                    gamma: *gamma,
+                    price_scale: *price_scale,
+                })?),
+            },
+            factory_addr: Addr::unchecked(MOCK_FACTORY),
+        })?,
+        QueryMsg::Pool {} => to_json_binary(&PoolResponse {
+            assets: pool
+                .assets
+                .iter()
+                .zip(pool.reserves)
+                .map(|(info, amount)| Asset {
+                    info: info.clone().into(),
+                    amount,
+                })
+                .collect(),
+            total_share: Uint128::zero(),
+        })?,
+        QueryMsg::Simulation { offer_asset, .. } => {
+            to_json_binary(&pool.simulate(&offer_asset.info.into(), offer_asset.amount)?)?
+        }
+        QueryMsg::ReverseSimulation { ask_asset, .. } => {
+            to_json_binary(&pool.reverse_simulate(&ask_asset.info.into(), ask_asset.amount)?)?
+        }
+        _ => return Err(StdError::generic_err("query not supported by the mock pair").into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{testing::mock_env, Decimal, Decimal256, Uint256};
+
+    use super::*;
+    use crate::{
+        asset::AssetInfo,
+        swap::{error::SwapError, liquidity_pool::LiquidityPool, slippage::Slippage, Swap},
+    };
+
+    #[test]
+    fn test_liquidity_pool() {
+        let pool = SimulatedPool {
+            assets: [
+                AssetInfo::NativeToken {
+                    denom: "uatom".to_string(),
+                },
+                AssetInfo::NativeToken {
+                    denom: "uusdc".to_string(),
+                },
+            ],
+            reserves: [1_000_000u128.into(), 1_000_000u128.into()],
+            commission_rate: Decimal::permille(3),
+            curve: Curve::ConstantProduct,
+        };
+        let deps =
+            mock_dependencies_with_pools(MockAstroport::default().with_pool("pool", pool.clone()));
+        let liquidity_pool = LiquidityPool {
+            addr: Addr::unchecked("pool"),
+        };
+        let [offer_asset, ask_asset] = &pool.assets;
+
+        let return_amount = liquidity_pool
+            .query_sim(deps.as_ref(), offer_asset, ask_asset, 1_000u64.into())
+            .unwrap();
+        assert_eq!(return_amount, Uint256::from(997u64));
+
+        // The pair's reverse simulation undershoots, the exact offer amount is found instead.
+        let offer_amount = liquidity_pool
+            .query_reverse_sim(deps.as_ref(), offer_asset, ask_asset, 997u64.into())
+            .unwrap();
+        assert_eq!(offer_amount, Uint256::from(1_000u64));
+
+        let plan = liquidity_pool
+            .swap_ask(
+                deps.as_ref(),
+                &mock_env(),
+                offer_asset,
+                ask_asset,
+                997u64.into(),
+                &Slippage::Tolerance(Decimal256::percent(1)),
+            )
+            .unwrap();
+        assert_eq!(plan.msgs.len(), 1);
+        assert_eq!(plan.offer.amount, Uint256::from(1_000u64));
+        assert_eq!(plan.min_receive, Uint256::from(997u64));
+        assert_eq!(plan.surplus, Uint256::zero());
+
+        let ask_amount = liquidity_pool
+            .query_ask_amount_at_price(
+                deps.as_ref(),
+                offer_asset,
+                ask_asset,
+                Decimal256::percent(110),
+            )
+            .unwrap();
+        assert_eq!(
+            ask_amount,
+            pool.query_ask_amount_at_price(
+                deps.as_ref(),
+                offer_asset,
+                ask_asset,
+                Decimal256::percent(110)
+            )
+            .unwrap()
+        );
+        assert!(!ask_amount.is_zero());
+
+        // Both reject a zero ratio, which no swap can reach.
+        let err = Err(SwapError::InvalidPrice(Decimal256::zero()).into());
+        assert_eq!(
+            liquidity_pool.query_ask_amount_at_price(
+                deps.as_ref(),
+                offer_asset,
+                ask_asset,
+                Decimal256::zero()
+            ),
+            err
+        );
+        assert_eq!(
+            pool.query_ask_amount_at_price(
+                deps.as_ref(),
+                offer_asset,
+                ask_asset,
+                Decimal256::zero()
+            ),
+            err
+        );
+    }
+}
@@ -1,16 +1,41 @@
+pub mod adapter;
+pub mod concentrated;
+#[cfg(feature = "injective")]
+pub mod derivative;
 pub mod error;
 pub mod liquidity_pool;
+#[cfg(all(feature = "injective", not(target_arch = "wasm32")))]
+pub mod mock_exchange;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mock_querier;
+pub mod multi_hop;
 #[cfg(feature = "injective")]
 pub mod order_book;
+#[cfg(feature = "injective")]
+pub mod order_book_math;
+#[cfg(feature = "osmosis")]
+pub mod osmosis;
+pub mod plan;
+pub mod registry;
+pub mod simulated_pool;
+pub mod slippage;
+pub mod split;
+pub mod stable_swap;
+pub mod verify;
 
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{CosmosMsg, Decimal256, Deps, Env, Uint256};
+use cosmwasm_std::{Decimal256, Deps, Env, Uint256};
 
-use crate::{
-    asset::AssetInfo, error::NeptuneResult, msg_wrapper::MsgWrapper, query_wrapper::QueryWrapper,
-};
+use crate::{asset::AssetInfo, error::NeptuneResult, query_wrapper::QueryWrapper};
 
-use self::{error::SwapError, liquidity_pool::LiquidityPool};
+use self::{
+    adapter::Adapter,
+    error::SwapError,
+    liquidity_pool::{LiquidityPool, Router},
+    plan::SwapPlan,
+    slippage::Slippage,
+    split::Split,
+};
 
 pub const EXCHANGES: cw_storage_plus::Map<(&AssetInfo, &AssetInfo), Exchange> =
     cw_storage_plus::Map::new("exchanges");
@@ -18,8 +43,17 @@ pub const EXCHANGES: cw_storage_plus::Map<(&AssetInfo, &AssetInfo), Exchange> =
 #[cw_serde]
 pub enum Exchange {
     LiquidityPool(LiquidityPool),
+    /// A route through several Astroport pairs, executed in a single message.
+    Router(Router),
     #[cfg(feature = "injective")]
     OrderBook(order_book::OrderBook),
+    /// A route through Osmosis poolmanager pools.
+    #[cfg(feature = "osmosis")]
+    Osmosis(osmosis::OsmosisRoute),
+    /// A venue implemented by an external contract supporting the swap adapter interface.
+    Adapter(Adapter),
+    /// Several venues for the same pair, swaps are split across them.
+    Split(Split),
 }
 
 fn get_exchange_type(
@@ -33,8 +67,106 @@ fn get_exchange_type(
         .ok_or_else(|| SwapError::PoolNotFound([assets[0].clone(), assets[1].clone()]))?)
 }
 
+/// Relative precision at which the generic price searches stop bisecting.
+const SEARCH_PRECISION: u64 = 1_000;
+
+/// Upper bound on the number of iterations spent in each phase of the search.
+const MAX_SEARCH_STEPS: u32 = 128;
+
+/// Generic implementation of `query_ask_amount_at_price` that only relies on `query_reverse_sim`.
+/// Searches for the largest ask amount at which the marginal ratio of offer to ask
+/// is still at most `max_ratio`, to a relative precision of 0.1%.
+pub fn search_ask_amount_at_price<S: Swap + ?Sized>(
+    exchange: &S,
+    deps: Deps<QueryWrapper>,
+    offer_asset: &AssetInfo,
+    ask_asset: &AssetInfo,
+    max_ratio: Decimal256,
+) -> NeptuneResult<Uint256> {
+    Ok(search_largest(|ask_amount| {
+        let step = (ask_amount / Uint256::from(SEARCH_PRECISION)).max(Uint256::one());
+        let Ok(next_ask_amount) = ask_amount.checked_add(step) else {
+            return false;
+        };
+        let (Ok(offer_amount), Ok(next_offer_amount)) = (
+            exchange.query_reverse_sim(deps, offer_asset, ask_asset, ask_amount),
+            exchange.query_reverse_sim(deps, offer_asset, ask_asset, next_ask_amount),
+        ) else {
+            // Reverse simulations fail once the liquidity is exhausted.
+            return false;
+        };
+        next_offer_amount.saturating_sub(offer_amount) <= step * max_ratio
+    }))
+}
+
+/// Generic implementation of `query_ask_amount_at_price` that only relies on `query_sim`.
+/// Searches for the largest offer amount at which the marginal ratio of offer to ask
+/// is still at most `max_ratio` and returns the amount it simulates to.
+/// A unit of rounding in the simulated return is tolerated.
+pub fn search_ask_amount_at_price_by_sim<S: Swap + ?Sized>(
+    exchange: &S,
+    deps: Deps<QueryWrapper>,
+    offer_asset: &AssetInfo,
+    ask_asset: &AssetInfo,
+    max_ratio: Decimal256,
+) -> NeptuneResult<Uint256> {
+    let offer_amount = search_largest(|offer_amount| {
+        let Ok(ask_amount) = exchange.query_sim(deps, offer_asset, ask_asset, offer_amount) else {
+            return false;
+        };
+        // The marginal ratio cannot be observed through rounding, use the average ratio instead.
+        if ask_amount < Uint256::from(SEARCH_PRECISION) {
+            return offer_amount <= (ask_amount + Uint256::one()) * max_ratio;
+        }
+        let step = (offer_amount / Uint256::from(SEARCH_PRECISION)).max(Uint256::one());
+        let Ok(next_offer_amount) = offer_amount.checked_add(step) else {
+            return false;
+        };
+        let Ok(next_ask_amount) =
+            exchange.query_sim(deps, offer_asset, ask_asset, next_offer_amount)
+        else {
+            return false;
+        };
+        step <= (next_ask_amount.saturating_sub(ask_amount) + Uint256::one()) * max_ratio
+    });
+    exchange.query_sim(deps, offer_asset, ask_asset, offer_amount)
+}
+
+/// Returns the largest amount for which `is_within_price` holds,
+/// to a relative precision of [`SEARCH_PRECISION`].
+fn search_largest(is_within_price: impl Fn(Uint256) -> bool) -> Uint256 {
+    // Exponential search for an upper bound.
+    let mut lower = Uint256::zero();
+    let mut upper = Uint256::one();
+    let mut steps = 0;
+    while is_within_price(upper) {
+        lower = upper;
+        upper = match upper.checked_mul(Uint256::from(2u8)) {
+            Ok(upper) if steps < MAX_SEARCH_STEPS => upper,
+            _ => return lower,
+        };
+        steps += 1;
+    }
+
+    // Bisect until the bounds are within the required precision.
+    steps = 0;
+    while upper - lower > (lower / Uint256::from(SEARCH_PRECISION)).max(Uint256::one())
+        && steps < MAX_SEARCH_STEPS
+    {
+        let mid = lower + (upper - lower) / Uint256::from(2u8);
+        if is_within_price(mid) {
+            lower = mid;
+        } else {
+            upper = mid;
+        }
+        steps += 1;
+    }
+
+    lower
+}
+
 pub trait Swap {
-    /// Creates a message to swap assets
+    /// Plans a swap of the offer amount, protected by the given slippage policy
     fn swap(
         &self,
         deps: Deps<QueryWrapper>,
@@ -42,9 +174,10 @@ pub trait Swap {
         offer_asset: &AssetInfo,
         ask_asset: &AssetInfo,
         offer_amount: Uint256,
-    ) -> NeptuneResult<Vec<CosmosMsg<MsgWrapper>>>;
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan>;
 
-    /// Creates a message to swap assets
+    /// Plans a swap for the ask amount, protected by the given slippage policy
     fn swap_ask(
         &self,
         deps: Deps<QueryWrapper>,
@@ -52,9 +185,10 @@ pub trait Swap {
         offer_asset: &AssetInfo,
         ask_asset: &AssetInfo,
         ask_amount: Uint256,
-    ) -> NeptuneResult<Vec<CosmosMsg<MsgWrapper>>> {
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
         let offer_amount = self.query_reverse_sim(deps, offer_asset, ask_asset, ask_amount)?;
-        self.swap(deps, env, offer_asset, ask_asset, offer_amount)
+        self.swap(deps, env, offer_asset, ask_asset, offer_amount, slippage)
     }
 
     fn query_sim(
@@ -107,7 +241,7 @@ pub trait Swap {
     }
 }
 
-impl Swap for cw_storage_plus::Map<'static, (&AssetInfo, &AssetInfo), Exchange> {
+impl Swap for Exchange {
     fn swap(
         &self,
         deps: Deps<QueryWrapper>,
@@ -115,16 +249,31 @@ impl Swap for cw_storage_plus::Map<'static, (&AssetInfo, &AssetInfo), Exchange>
         offer_asset: &AssetInfo,
         ask_asset: &AssetInfo,
         offer_amount: Uint256,
-    ) -> NeptuneResult<Vec<CosmosMsg<MsgWrapper>>> {
-        match get_exchange_type(deps, self, [offer_asset, ask_asset])? {
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let plan = match self {
             Exchange::LiquidityPool(liquidity_pool) => {
-                liquidity_pool.swap(deps, env, offer_asset, ask_asset, offer_amount)
+                liquidity_pool.swap(deps, env, offer_asset, ask_asset, offer_amount, slippage)
+            }
+            Exchange::Router(router) => {
+                router.swap(deps, env, offer_asset, ask_asset, offer_amount, slippage)
             }
             #[cfg(feature = "injective")]
             Exchange::OrderBook(order_book) => {
-                order_book.swap(deps, env, offer_asset, ask_asset, offer_amount)
+                order_book.swap(deps, env, offer_asset, ask_asset, offer_amount, slippage)
             }
-        }
+            #[cfg(feature = "osmosis")]
+            Exchange::Osmosis(osmosis_route) => {
+                osmosis_route.swap(deps, env, offer_asset, ask_asset, offer_amount, slippage)
+            }
+            Exchange::Adapter(adapter) => {
+                adapter.swap(deps, env, offer_asset, ask_asset, offer_amount, slippage)
+            }
+            Exchange::Split(split) => {
+                split.swap(deps, env, offer_asset, ask_asset, offer_amount, slippage)
+            }
+        }?;
+        Ok(plan.with_venue(self))
     }
 
     fn swap_ask(
@@ -134,16 +283,31 @@ impl Swap for cw_storage_plus::Map<'static, (&AssetInfo, &AssetInfo), Exchange>
         offer_asset: &AssetInfo,
         ask_asset: &AssetInfo,
         ask_amount: Uint256,
-    ) -> NeptuneResult<Vec<CosmosMsg<MsgWrapper>>> {
-        match get_exchange_type(deps, self, [offer_asset, ask_asset])? {
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let plan = match self {
             Exchange::LiquidityPool(liquidity_pool) => {
-                liquidity_pool.swap_ask(deps, env, offer_asset, ask_asset, ask_amount)
+                liquidity_pool.swap_ask(deps, env, offer_asset, ask_asset, ask_amount, slippage)
+            }
+            Exchange::Router(router) => {
+                router.swap_ask(deps, env, offer_asset, ask_asset, ask_amount, slippage)
             }
             #[cfg(feature = "injective")]
             Exchange::OrderBook(order_book) => {
-                order_book.swap_ask(deps, env, offer_asset, ask_asset, ask_amount)
+                order_book.swap_ask(deps, env, offer_asset, ask_asset, ask_amount, slippage)
             }
-        }
+            #[cfg(feature = "osmosis")]
+            Exchange::Osmosis(osmosis_route) => {
+                osmosis_route.swap_ask(deps, env, offer_asset, ask_asset, ask_amount, slippage)
+            }
+            Exchange::Adapter(adapter) => {
+                adapter.swap_ask(deps, env, offer_asset, ask_asset, ask_amount, slippage)
+            }
+            Exchange::Split(split) => {
+                split.swap_ask(deps, env, offer_asset, ask_asset, ask_amount, slippage)
+            }
+        }?;
+        Ok(plan.with_venue(self))
     }
 
     fn query_sim(
@@ -153,14 +317,25 @@ impl Swap for cw_storage_plus::Map<'static, (&AssetInfo, &AssetInfo), Exchange>
         ask_asset: &AssetInfo,
         offer_amount: Uint256,
     ) -> NeptuneResult<Uint256> {
-        match get_exchange_type(deps, self, [offer_asset, ask_asset])? {
+        match self {
             Exchange::LiquidityPool(liquidity_pool) => {
                 liquidity_pool.query_sim(deps, offer_asset, ask_asset, offer_amount)
             }
+            Exchange::Router(router) => {
+                router.query_sim(deps, offer_asset, ask_asset, offer_amount)
+            }
             #[cfg(feature = "injective")]
             Exchange::OrderBook(order_book) => {
                 order_book.query_sim(deps, offer_asset, ask_asset, offer_amount)
             }
+            #[cfg(feature = "osmosis")]
+            Exchange::Osmosis(osmosis_route) => {
+                osmosis_route.query_sim(deps, offer_asset, ask_asset, offer_amount)
+            }
+            Exchange::Adapter(adapter) => {
+                adapter.query_sim(deps, offer_asset, ask_asset, offer_amount)
+            }
+            Exchange::Split(split) => split.query_sim(deps, offer_asset, ask_asset, offer_amount),
         }
     }
 
@@ -171,14 +346,27 @@ impl Swap for cw_storage_plus::Map<'static, (&AssetInfo, &AssetInfo), Exchange>
         ask_asset: &AssetInfo,
         ask_amount: Uint256,
     ) -> NeptuneResult<Uint256> {
-        match get_exchange_type(deps, self, [offer_asset, ask_asset])? {
+        match self {
             Exchange::LiquidityPool(liquidity_pool) => {
                 liquidity_pool.query_reverse_sim(deps, offer_asset, ask_asset, ask_amount)
             }
+            Exchange::Router(router) => {
+                router.query_reverse_sim(deps, offer_asset, ask_asset, ask_amount)
+            }
             #[cfg(feature = "injective")]
             Exchange::OrderBook(order_book) => {
                 order_book.query_reverse_sim(deps, offer_asset, ask_asset, ask_amount)
             }
+            #[cfg(feature = "osmosis")]
+            Exchange::Osmosis(osmosis_route) => {
+                osmosis_route.query_reverse_sim(deps, offer_asset, ask_asset, ask_amount)
+            }
+            Exchange::Adapter(adapter) => {
+                adapter.query_reverse_sim(deps, offer_asset, ask_asset, ask_amount)
+            }
+            Exchange::Split(split) => {
+                split.query_reverse_sim(deps, offer_asset, ask_asset, ask_amount)
+            }
         }
     }
 
@@ -189,14 +377,27 @@ impl Swap for cw_storage_plus::Map<'static, (&AssetInfo, &AssetInfo), Exchange>
         ask_asset: &AssetInfo,
         max_ratio: Decimal256,
     ) -> NeptuneResult<Uint256> {
-        match get_exchange_type(deps, self, [offer_asset, ask_asset])? {
+        match self {
             Exchange::LiquidityPool(liquidity_pool) => {
                 liquidity_pool.query_ask_amount_at_price(deps, offer_asset, ask_asset, max_ratio)
             }
+            Exchange::Router(router) => {
+                router.query_ask_amount_at_price(deps, offer_asset, ask_asset, max_ratio)
+            }
             #[cfg(feature = "injective")]
             Exchange::OrderBook(order_book) => {
                 order_book.query_ask_amount_at_price(deps, offer_asset, ask_asset, max_ratio)
             }
+            #[cfg(feature = "osmosis")]
+            Exchange::Osmosis(osmosis_route) => {
+                osmosis_route.query_ask_amount_at_price(deps, offer_asset, ask_asset, max_ratio)
+            }
+            Exchange::Adapter(adapter) => {
+                adapter.query_ask_amount_at_price(deps, offer_asset, ask_asset, max_ratio)
+            }
+            Exchange::Split(split) => {
+                split.query_ask_amount_at_price(deps, offer_asset, ask_asset, max_ratio)
+            }
         }
     }
 
@@ -207,14 +408,27 @@ impl Swap for cw_storage_plus::Map<'static, (&AssetInfo, &AssetInfo), Exchange>
         ask_asset: &AssetInfo,
         offer_amount: Uint256,
     ) -> NeptuneResult<Decimal256> {
-        match get_exchange_type(deps, self, [offer_asset, ask_asset])? {
+        match self {
             Exchange::LiquidityPool(liquidity_pool) => {
                 liquidity_pool.query_swap_ratio(deps, offer_asset, ask_asset, offer_amount)
             }
+            Exchange::Router(router) => {
+                router.query_swap_ratio(deps, offer_asset, ask_asset, offer_amount)
+            }
             #[cfg(feature = "injective")]
             Exchange::OrderBook(order_book) => {
                 order_book.query_swap_ratio(deps, offer_asset, ask_asset, offer_amount)
             }
+            #[cfg(feature = "osmosis")]
+            Exchange::Osmosis(osmosis_route) => {
+                osmosis_route.query_swap_ratio(deps, offer_asset, ask_asset, offer_amount)
+            }
+            Exchange::Adapter(adapter) => {
+                adapter.query_swap_ratio(deps, offer_asset, ask_asset, offer_amount)
+            }
+            Exchange::Split(split) => {
+                split.query_swap_ratio(deps, offer_asset, ask_asset, offer_amount)
+            }
         }
     }
 
@@ -225,14 +439,142 @@ impl Swap for cw_storage_plus::Map<'static, (&AssetInfo, &AssetInfo), Exchange>
         ask_asset: &AssetInfo,
         ask_amount: Uint256,
     ) -> NeptuneResult<Decimal256> {
-        match get_exchange_type(deps, self, [offer_asset, ask_asset])? {
+        match self {
             Exchange::LiquidityPool(liquidity_pool) => {
                 liquidity_pool.query_reverse_swap_ratio(deps, offer_asset, ask_asset, ask_amount)
             }
+            Exchange::Router(router) => {
+                router.query_reverse_swap_ratio(deps, offer_asset, ask_asset, ask_amount)
+            }
             #[cfg(feature = "injective")]
             Exchange::OrderBook(order_book) => {
                 order_book.query_reverse_swap_ratio(deps, offer_asset, ask_asset, ask_amount)
             }
+            #[cfg(feature = "osmosis")]
+            Exchange::Osmosis(osmosis_route) => {
+                osmosis_route.query_reverse_swap_ratio(deps, offer_asset, ask_asset, ask_amount)
+            }
+            Exchange::Adapter(adapter) => {
+                adapter.query_reverse_swap_ratio(deps, offer_asset, ask_asset, ask_amount)
+            }
+            Exchange::Split(split) => {
+                split.query_reverse_swap_ratio(deps, offer_asset, ask_asset, ask_amount)
+            }
         }
     }
 }
+
+impl Swap for cw_storage_plus::Map<'_, (&AssetInfo, &AssetInfo), Exchange> {
+    fn swap(
+        &self,
+        deps: Deps<QueryWrapper>,
+        env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        get_exchange_type(deps, self, [offer_asset, ask_asset])?.swap(
+            deps,
+            env,
+            offer_asset,
+            ask_asset,
+            offer_amount,
+            slippage,
+        )
+    }
+
+    fn swap_ask(
+        &self,
+        deps: Deps<QueryWrapper>,
+        env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        get_exchange_type(deps, self, [offer_asset, ask_asset])?.swap_ask(
+            deps,
+            env,
+            offer_asset,
+            ask_asset,
+            ask_amount,
+            slippage,
+        )
+    }
+
+    fn query_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        get_exchange_type(deps, self, [offer_asset, ask_asset])?.query_sim(
+            deps,
+            offer_asset,
+            ask_asset,
+            offer_amount,
+        )
+    }
+
+    fn query_reverse_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        get_exchange_type(deps, self, [offer_asset, ask_asset])?.query_reverse_sim(
+            deps,
+            offer_asset,
+            ask_asset,
+            ask_amount,
+        )
+    }
+
+    fn query_ask_amount_at_price(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        max_ratio: Decimal256,
+    ) -> NeptuneResult<Uint256> {
+        get_exchange_type(deps, self, [offer_asset, ask_asset])?.query_ask_amount_at_price(
+            deps,
+            offer_asset,
+            ask_asset,
+            max_ratio,
+        )
+    }
+
+    fn query_swap_ratio(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+    ) -> NeptuneResult<Decimal256> {
+        get_exchange_type(deps, self, [offer_asset, ask_asset])?.query_swap_ratio(
+            deps,
+            offer_asset,
+            ask_asset,
+            offer_amount,
+        )
+    }
+
+    fn query_reverse_swap_ratio(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<Decimal256> {
+        get_exchange_type(deps, self, [offer_asset, ask_asset])?.query_reverse_swap_ratio(
+            deps,
+            offer_asset,
+            ask_asset,
+            ask_amount,
+        )
+    }
+}
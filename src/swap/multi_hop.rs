@@ -0,0 +1,434 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use cosmwasm_std::{Decimal256, Deps, Env, Order, Storage, Uint256};
+use cw_storage_plus::Map;
+
+use crate::{
+    asset::AssetInfo, error::NeptuneResult, math::div_or_zero, query_wrapper::QueryWrapper,
+};
+
+use super::{
+    error::SwapError, plan::SwapPlan, search_ask_amount_at_price, slippage::Slippage, Exchange,
+    Swap,
+};
+
+/// The default maximum number of swaps in a single route.
+pub const DEFAULT_MAX_HOPS: usize = 3;
+
+/// Routes swaps through intermediate assets when two assets do not share an exchange.
+/// The exchange registry is treated as an undirected graph where each asset is a node
+/// and each registered exchange is an edge.
+pub struct MultiHop<'a> {
+    pub exchanges: Map<'a, (&'a AssetInfo, &'a AssetInfo), Exchange>,
+    pub max_hops: usize,
+}
+
+impl<'a> MultiHop<'a> {
+    pub const fn new(exchanges: Map<'a, (&'a AssetInfo, &'a AssetInfo), Exchange>) -> Self {
+        Self {
+            exchanges,
+            max_hops: DEFAULT_MAX_HOPS,
+        }
+    }
+
+    /// Finds the route with the fewest hops between the offer and the ask asset.
+    /// The returned path starts with the offer asset and ends with the ask asset.
+    pub fn find_route(
+        &self,
+        storage: &dyn Storage,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+    ) -> NeptuneResult<Vec<AssetInfo>> {
+        let not_found = || SwapError::RouteNotFound([offer_asset.clone(), ask_asset.clone()]);
+        if offer_asset == ask_asset {
+            return Err(not_found().into());
+        }
+
+        let mut graph: BTreeMap<AssetInfo, Vec<AssetInfo>> = BTreeMap::new();
+        for key in self.exchanges.keys(storage, None, None, Order::Ascending) {
+            let (asset_a, asset_b) = key?;
+            graph
+                .entry(asset_a.clone())
+                .or_default()
+                .push(asset_b.clone());
+            graph.entry(asset_b).or_default().push(asset_a);
+        }
+
+        // Breadth first search, remembering the asset each visited asset was reached from.
+        let mut previous: BTreeMap<&AssetInfo, &AssetInfo> = BTreeMap::new();
+        let mut queue = VecDeque::from([(offer_asset, 0usize)]);
+        while let Some((asset, hops)) = queue.pop_front() {
+            if asset == ask_asset {
+                let mut route = vec![asset.clone()];
+                let mut current = asset;
+                while let Some(prev) = previous.get(current) {
+                    route.push((*prev).clone());
+                    current = *prev;
+                }
+                route.reverse();
+                return Ok(route);
+            }
+            if hops == self.max_hops {
+                continue;
+            }
+            for next in graph.get(asset).into_iter().flatten() {
+                if next != offer_asset && !previous.contains_key(next) {
+                    previous.insert(next, asset);
+                    queue.push_back((next, hops + 1));
+                }
+            }
+        }
+
+        Err(not_found().into())
+    }
+
+    /// Returns the simulated amount of each asset along the route when offering `offer_amount`.
+    fn forward_amounts(
+        &self,
+        deps: Deps<QueryWrapper>,
+        route: &[AssetInfo],
+        offer_amount: Uint256,
+    ) -> NeptuneResult<Vec<Uint256>> {
+        let mut amounts = vec![offer_amount];
+        let mut amount = offer_amount;
+        for hop in route.windows(2) {
+            amount = self.exchanges.query_sim(deps, &hop[0], &hop[1], amount)?;
+            amounts.push(amount);
+        }
+        Ok(amounts)
+    }
+
+    /// Returns the amount of each asset along the route required to receive `ask_amount`.
+    fn reverse_amounts(
+        &self,
+        deps: Deps<QueryWrapper>,
+        route: &[AssetInfo],
+        ask_amount: Uint256,
+    ) -> NeptuneResult<Vec<Uint256>> {
+        let mut amounts = vec![ask_amount];
+        let mut amount = ask_amount;
+        for hop in route.windows(2).rev() {
+            amount = self
+                .exchanges
+                .query_reverse_sim(deps, &hop[0], &hop[1], amount)?;
+            amounts.push(amount);
+        }
+        amounts.reverse();
+        Ok(amounts)
+    }
+
+    /// Plans every hop given the amount of each asset along the route.
+    /// The tolerance implied by `min_receive` is spread evenly across the hops,
+    /// the last hop must still receive `min_receive`.
+    ///
+    /// Exact-offer hops offer only the minimum the previous hop is guaranteed to return,
+    /// so slippage within the tolerance never leaves a hop short of funds.
+    /// Each hop simulates its own return from that minimum.
+    /// Exact-output hops ask for exactly what the next hop offers and receive at least that.
+    fn plan_hops(
+        &self,
+        deps: Deps<QueryWrapper>,
+        env: &Env,
+        route: &[AssetInfo],
+        amounts: &[Uint256],
+        min_receive: Uint256,
+        is_ask: bool,
+    ) -> NeptuneResult<SwapPlan> {
+        let hops = route.len() - 1;
+        let tolerance = Decimal256::one().saturating_sub(div_or_zero(min_receive, amounts[hops]));
+        let hop_tolerance = tolerance / Decimal256::from_ratio(hops as u64, 1u8);
+        let mut legs = vec![];
+        let mut offer_amount = amounts[0];
+        for (index, hop) in route.windows(2).enumerate() {
+            let slippage = if index == hops - 1 {
+                Slippage::MinimumReceive(min_receive)
+            } else {
+                Slippage::Tolerance(hop_tolerance)
+            };
+            let leg = if is_ask {
+                self.exchanges.swap_ask(
+                    deps,
+                    env,
+                    &hop[0],
+                    &hop[1],
+                    amounts[index + 1],
+                    &slippage,
+                )?
+            } else {
+                self.exchanges
+                    .swap(deps, env, &hop[0], &hop[1], offer_amount, &slippage)?
+            };
+            offer_amount = leg.min_receive;
+            legs.push(leg);
+        }
+        Ok(SwapPlan::sequence(
+            &route[0],
+            &route[route.len() - 1],
+            legs,
+            min_receive,
+        ))
+    }
+}
+
+impl Swap for MultiHop<'_> {
+    /// Each hop offers the minimum return of the previous hop. Intermediate hops share
+    /// the tolerance of the slippage policy, which applies to the return of the whole route.
+    fn swap(
+        &self,
+        deps: Deps<QueryWrapper>,
+        env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let route = self.find_route(deps.storage, offer_asset, ask_asset)?;
+        let amounts = self.forward_amounts(deps, &route, offer_amount)?;
+        let min_receive = slippage.assert(offer_amount, amounts[amounts.len() - 1])?;
+        self.plan_hops(deps, env, &route, &amounts, min_receive, false)
+    }
+
+    /// Each hop asks for exactly the amount the next hop requires.
+    fn swap_ask(
+        &self,
+        deps: Deps<QueryWrapper>,
+        env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let route = self.find_route(deps.storage, offer_asset, ask_asset)?;
+        let amounts = self.reverse_amounts(deps, &route, ask_amount)?;
+        let min_receive = slippage.assert(amounts[0], ask_amount)?;
+        self.plan_hops(deps, env, &route, &amounts, min_receive, true)
+    }
+
+    fn query_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        let route = self.find_route(deps.storage, offer_asset, ask_asset)?;
+        let amounts = self.forward_amounts(deps, &route, offer_amount)?;
+        Ok(amounts[amounts.len() - 1])
+    }
+
+    fn query_reverse_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        let route = self.find_route(deps.storage, offer_asset, ask_asset)?;
+        Ok(self.reverse_amounts(deps, &route, ask_amount)?[0])
+    }
+
+    /// Delegates to the exchange for direct routes and otherwise searches the whole route.
+    fn query_ask_amount_at_price(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        max_ratio: Decimal256,
+    ) -> NeptuneResult<Uint256> {
+        let route = self.find_route(deps.storage, offer_asset, ask_asset)?;
+        if route.len() == 2 {
+            return self.exchanges.query_ask_amount_at_price(
+                deps,
+                offer_asset,
+                ask_asset,
+                max_ratio,
+            );
+        }
+        search_ask_amount_at_price(self, deps, offer_asset, ask_asset, max_ratio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{
+        testing::{mock_dependencies, mock_env},
+        Addr, Coin, CosmosMsg, Decimal, Uint128, WasmMsg,
+    };
+
+    use super::*;
+    use crate::{
+        error::NeptuneError,
+        swap::{
+            liquidity_pool::LiquidityPool,
+            mock_querier::{mock_dependencies_with_pools, MockAstroport},
+            simulated_pool::{Curve, SimulatedPool},
+        },
+    };
+
+    fn native(denom: &str) -> AssetInfo {
+        AssetInfo::NativeToken {
+            denom: denom.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_find_route() {
+        let mut owned_deps = mock_dependencies();
+        let deps = owned_deps.as_mut();
+        const EXCHANGES: Map<(&AssetInfo, &AssetInfo), Exchange> = Map::new("exchanges");
+
+        let pairs = [
+            (native("atom"), native("usdc")),
+            (native("inj"), native("usdc")),
+            (native("inj"), native("weth")),
+        ];
+        for (asset_a, asset_b) in &pairs {
+            let mut assets = [asset_a, asset_b];
+            assets.sort_unstable();
+            let exchange = Exchange::LiquidityPool(LiquidityPool {
+                addr: Addr::unchecked(format!("{asset_a}{asset_b}")),
+            });
+            EXCHANGES
+                .save(deps.storage, (assets[0], assets[1]), &exchange)
+                .unwrap();
+        }
+
+        let mut multi_hop = MultiHop::new(EXCHANGES);
+        let route = multi_hop
+            .find_route(deps.storage, &native("atom"), &native("inj"))
+            .unwrap();
+        assert_eq!(route, vec![native("atom"), native("usdc"), native("inj")]);
+
+        let route = multi_hop
+            .find_route(deps.storage, &native("weth"), &native("atom"))
+            .unwrap();
+        assert_eq!(
+            route,
+            vec![
+                native("weth"),
+                native("inj"),
+                native("usdc"),
+                native("atom")
+            ]
+        );
+
+        multi_hop.max_hops = 2;
+        let res = multi_hop.find_route(deps.storage, &native("weth"), &native("atom"));
+        assert_eq!(
+            res,
+            Err(NeptuneError::SwapError(SwapError::RouteNotFound([
+                native("weth"),
+                native("atom")
+            ])))
+        );
+    }
+
+    #[test]
+    fn test_multi_hop_swap() {
+        let pool = |assets: [&str; 2], reserves: [u128; 2]| SimulatedPool {
+            assets: assets.map(native),
+            reserves: reserves.map(Uint128::new),
+            commission_rate: Decimal::permille(3),
+            curve: Curve::ConstantProduct,
+        };
+        let atom_usdc = pool(["atom", "usdc"], [1_000_000, 10_000_000]);
+        let inj_usdc = pool(["inj", "usdc"], [2_000_000, 40_000_000]);
+        let mut deps = mock_dependencies_with_pools(
+            MockAstroport::default()
+                .with_pool("atom_usdc", atom_usdc.clone())
+                .with_pool("inj_usdc", inj_usdc.clone()),
+        );
+        const EXCHANGES: Map<(&AssetInfo, &AssetInfo), Exchange> = Map::new("exchanges");
+        for addr in ["atom_usdc", "inj_usdc"] {
+            let (asset_a, asset_b) = addr.split_once('_').unwrap();
+            let mut assets = [native(asset_a), native(asset_b)];
+            assets.sort_unstable();
+            let exchange = Exchange::LiquidityPool(LiquidityPool {
+                addr: Addr::unchecked(addr),
+            });
+            EXCHANGES
+                .save(&mut deps.storage, (&assets[0], &assets[1]), &exchange)
+                .unwrap();
+        }
+        let multi_hop = MultiHop::new(EXCHANGES);
+        let (atom, usdc, inj) = (native("atom"), native("usdc"), native("inj"));
+
+        // Each hop offers the return of the previous one.
+        let usdc_amount = atom_usdc.simulate(&atom, 10_000u128.into()).unwrap();
+        let inj_amount = inj_usdc.simulate(&usdc, usdc_amount.return_amount).unwrap();
+        let return_amount = multi_hop
+            .query_sim(deps.as_ref(), &atom, &inj, 10_000u64.into())
+            .unwrap();
+        assert_eq!(return_amount, Uint256::from(inj_amount.return_amount));
+
+        // In reverse each hop asks for what the next one requires.
+        let usdc_amount = LiquidityPool {
+            addr: Addr::unchecked("inj_usdc"),
+        }
+        .query_reverse_sim(deps.as_ref(), &usdc, &inj, 5_000u64.into())
+        .unwrap();
+        let atom_amount = LiquidityPool {
+            addr: Addr::unchecked("atom_usdc"),
+        }
+        .query_reverse_sim(deps.as_ref(), &atom, &usdc, usdc_amount)
+        .unwrap();
+        assert_eq!(
+            multi_hop
+                .query_reverse_sim(deps.as_ref(), &atom, &inj, 5_000u64.into())
+                .unwrap(),
+            atom_amount
+        );
+
+        let plan = multi_hop
+            .swap(
+                deps.as_ref(),
+                &mock_env(),
+                &atom,
+                &inj,
+                10_000u64.into(),
+                &Slippage::Tolerance(Decimal256::percent(1)),
+            )
+            .unwrap();
+        assert_eq!(plan.min_receive, return_amount * Decimal256::percent(99));
+        assert_eq!(plan.legs.len(), 2);
+        let sent: Vec<_> = plan
+            .msgs
+            .iter()
+            .map(|msg| match msg {
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr,
+                    funds,
+                    ..
+                }) => (contract_addr.as_str(), funds.clone()),
+                _ => panic!("unexpected message {msg:?}"),
+            })
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                ("atom_usdc", vec![Coin::new(10_000, "atom")]),
+                (
+                    "inj_usdc",
+                    vec![Coin::new(
+                        Uint128::try_from(plan.legs[0].min_receive).unwrap().u128(),
+                        "usdc"
+                    )]
+                ),
+            ]
+        );
+        // The second hop only offers what the first is guaranteed to return,
+        // and its return is simulated from that amount.
+        assert_eq!(plan.legs[1].offer.amount, plan.legs[0].min_receive);
+        let inj_amount = inj_usdc
+            .simulate(&usdc, Uint128::try_from(plan.legs[0].min_receive).unwrap())
+            .unwrap();
+        assert_eq!(
+            plan.legs[1].ask.amount,
+            Uint256::from(inj_amount.return_amount)
+        );
+        assert!(plan.ask.amount < return_amount && plan.ask.amount >= plan.min_receive);
+        // The intermediate hop is given half of the route's tolerance.
+        assert!(plan.legs[0].min_receive < plan.legs[0].ask.amount);
+        assert!(plan.legs[0].min_receive >= plan.legs[0].ask.amount * Decimal256::permille(994));
+    }
+}
@@ -1,25 +1,215 @@
 use crate::{
-    asset::AssetInfo,
+    asset::{AssetAmount, AssetInfo},
     error::NeptuneResult,
-    injective::{into_decimal_256, into_fp_decimal, into_uint_256},
+    injective::{
+        into_fp_decimal, subaccount::subaccount_id, try_into_decimal_256, try_into_uint_256,
+    },
     msg_wrapper::MsgWrapper,
     query_wrapper::QueryWrapper,
+    send_asset::send_assets,
 };
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{CosmosMsg, Decimal256, Deps, Env, Fraction, QueryRequest, Uint256};
+use cosmwasm_std::{
+    to_json_binary, Addr, Binary, Coin, CosmosMsg, Decimal256, Deps, Env, Fraction, QueryRequest,
+    Uint256, WasmMsg,
+};
 use injective_cosmwasm::{
-    exchange::response::QueryOrderbookResponse, get_default_subaccount_id_for_checked_address,
+    cancel_spot_order_msg,
+    exchange::{response::QueryOrderbookResponse, types::PriceLevel},
     InjectiveMsg, InjectiveQuery, InjectiveRoute, MarketId, MarketMidPriceAndTOBResponse,
     OrderInfo, OrderSide, OrderType, QueryMarketAtomicExecutionFeeMultiplierResponse, SpotMarket,
-    SpotMarketResponse, SpotOrder,
+    SpotMarketResponse, SpotOrder, SubaccountId, TraderSpotOrdersResponse, TrimmedSpotLimitOrder,
 };
 use injective_math::FPDecimal;
 
-use super::{error::SwapError, Swap};
+use super::{
+    error::SwapError,
+    order_book_math::{
+        buy_quantity, buy_quantity_at_price, buy_with_quote, sell_amount_at_price, sell_for_quote,
+        sell_quantity, Fill, MarketDecimals, MarketParams,
+    },
+    plan::SwapPlan,
+    slippage::Slippage,
+    Swap,
+};
+
+pub use super::order_book_math::{tick_round_down, tick_round_up};
+
+/// Number of price levels fetched by the first page of a walk through the order book.
+const INITIAL_BOOK_LEVELS: u64 = 16;
+
+/// Default bound on the number of price levels fetched by a walk through the order book.
+pub const DEFAULT_MAX_BOOK_LEVELS: u64 = 256;
 
 #[cw_serde]
 pub struct OrderBook {
     pub market_id: MarketId,
+    /// Bound on the number of price levels fetched when walking the book up to a price,
+    /// defaults to `DEFAULT_MAX_BOOK_LEVELS`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_book_levels: Option<u64>,
+    /// The decimals of the base and quote assets, which the market does not report,
+    /// to convert its prices to and from whole assets. Swaps do not depend on them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals: Option<MarketDecimals>,
+    /// Injective's cw20-adapter, through which CW20 assets are traded
+    /// as their `factory/{adapter}/{cw20}` denoms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cw20_adapter: Option<Addr>,
+    /// The nonce of the contract's subaccount which swaps are placed from, defaults to zero.
+    /// Swaps from other subaccounts are funded from its deposits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subaccount_nonce: Option<u32>,
+}
+
+/// The execute message of the cw20-adapter which burns its denom to return the CW20.
+/// CW20s sent to the adapter are minted as its denom to the sender.
+#[cw_serde]
+enum Cw20AdapterExecuteMsg {
+    RedeemAndTransfer { recipient: Option<String> },
+}
+
+/// The amount of the ask asset available at a price.
+#[cw_serde]
+pub struct AskAmountAtPrice {
+    pub ask_amount: Uint256,
+    /// Set when the maximum number of levels was fetched before the price was crossed,
+    /// the ask amount is then only a lower bound.
+    pub partial: bool,
+}
+
+impl OrderBook {
+    pub fn max_book_levels(&self) -> u64 {
+        self.max_book_levels.unwrap_or(DEFAULT_MAX_BOOK_LEVELS)
+    }
+
+    /// The subaccount of the contract which swaps are placed from.
+    pub fn subaccount_id(&self, env: &Env) -> SubaccountId {
+        subaccount_id(
+            &env.contract.address,
+            self.subaccount_nonce.unwrap_or_default(),
+        )
+    }
+
+    /// The denom traded on the exchange for an asset,
+    /// CW20s are only supported through the cw20-adapter.
+    pub fn denom(&self, asset: &AssetInfo) -> NeptuneResult<String> {
+        match (asset, &self.cw20_adapter) {
+            (AssetInfo::NativeToken { denom }, _) => Ok(denom.clone()),
+            (AssetInfo::Token { contract_addr }, Some(cw20_adapter)) => {
+                Ok(format!("factory/{cw20_adapter}/{contract_addr}"))
+            }
+            (AssetInfo::Token { .. }, None) => Err(SwapError::InvalidAsset.into()),
+        }
+    }
+
+    fn exchange_asset(&self, asset: &AssetInfo) -> NeptuneResult<AssetInfo> {
+        Ok(AssetInfo::NativeToken {
+            denom: self.denom(asset)?,
+        })
+    }
+
+    /// Converts a plan trading the exchange's denoms into one trading the given assets.
+    /// CW20 offers are first sent to the cw20-adapter. The quantity bought of a CW20 ask
+    /// is redeemed from it, while a sell for a CW20 only redeems and reports its minimum
+    /// received since its proceeds are not known in advance. Any excess remains
+    /// as the adapter's denom.
+    fn wrap_cw20(
+        &self,
+        deps: Deps<QueryWrapper>,
+        mut plan: SwapPlan,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+    ) -> NeptuneResult<SwapPlan> {
+        let Some(cw20_adapter) = &self.cw20_adapter else {
+            return Ok(plan);
+        };
+        let (exchange_offer, exchange_ask) = (plan.offer.info.clone(), plan.ask.info.clone());
+        let mut msgs = vec![];
+        if matches!(offer_asset, AssetInfo::Token { .. }) && !plan.offer.amount.is_zero() {
+            msgs.push(send_assets(
+                cw20_adapter,
+                plan.offer.amount,
+                offer_asset.clone(),
+                Binary::default(),
+            )?);
+        }
+        let has_order = !plan.msgs.is_empty();
+        msgs.append(&mut plan.msgs);
+        if matches!(ask_asset, AssetInfo::Token { .. }) && has_order {
+            let spot_market = query_spot_market(deps, self.market_id.clone())?;
+            let ask_denom = self.denom(ask_asset)?;
+            if ask_denom != spot_market.base_denom {
+                plan.ask.amount = plan.min_receive;
+            }
+            if !plan.ask.amount.is_zero() {
+                msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: cw20_adapter.to_string(),
+                    msg: to_json_binary(&Cw20AdapterExecuteMsg::RedeemAndTransfer {
+                        recipient: None,
+                    })?,
+                    funds: vec![Coin {
+                        denom: ask_denom,
+                        amount: plan.ask.amount.try_into()?,
+                    }],
+                }));
+            }
+        }
+        plan.msgs = msgs;
+        plan.offer.info = offer_asset.clone();
+        plan.ask.info = ask_asset.clone();
+        for (info, _) in plan.commission.iter_mut() {
+            if *info == exchange_offer {
+                *info = offer_asset.clone();
+            } else if *info == exchange_ask {
+                *info = ask_asset.clone();
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Returns the amount of the ask asset which can be received while the ratio
+    /// of offer to ask stays at most `max_ratio`, walking at most `max_book_levels` levels.
+    pub fn ask_amount_at_price(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        max_ratio: Decimal256,
+    ) -> NeptuneResult<AskAmountAtPrice> {
+        let offer_denom = &self.denom(offer_asset)?;
+        let spot_market = query_spot_market(deps, self.market_id.clone())?;
+
+        let params = query_market_params(deps, &spot_market)?;
+
+        let (ask_amount, partial) = if offer_denom == &spot_market.quote_denom {
+            let price = into_fp_decimal(max_ratio);
+            let (sells, partial) = query_levels_to_price(
+                deps,
+                self.market_id.clone(),
+                false,
+                price,
+                self.max_book_levels(),
+            )?;
+            (buy_quantity_at_price(&params, &sells, price), partial)
+        } else if offer_denom == &spot_market.base_denom {
+            let price = into_fp_decimal(max_ratio.inv().ok_or(SwapError::InvalidPrice(max_ratio))?);
+            let (buys, partial) = query_levels_to_price(
+                deps,
+                self.market_id.clone(),
+                true,
+                price,
+                self.max_book_levels(),
+            )?;
+            (sell_amount_at_price(&params, &buys, price), partial)
+        } else {
+            return Err(SwapError::InvalidAsset.into());
+        };
+
+        Ok(AskAmountAtPrice {
+            ask_amount: into_amount(ask_amount)?,
+            partial,
+        })
+    }
 }
 
 impl Swap for OrderBook {
@@ -28,16 +218,20 @@ impl Swap for OrderBook {
         deps: Deps<QueryWrapper>,
         env: &Env,
         offer_asset: &AssetInfo,
-        _ask_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
         offer_amount: Uint256,
-    ) -> NeptuneResult<Vec<CosmosMsg<MsgWrapper>>> {
-        if let Some(msg) =
-            market_order_offer(deps, env, self.market_id.clone(), offer_asset, offer_amount)?
-        {
-            Ok(vec![msg])
-        } else {
-            Ok(vec![])
-        }
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let plan = market_order_offer(
+            deps,
+            env,
+            self,
+            &self.exchange_asset(offer_asset)?,
+            &self.exchange_asset(ask_asset)?,
+            offer_amount,
+            slippage,
+        )?;
+        self.wrap_cw20(deps, plan, offer_asset, ask_asset)
     }
 
     /// Override the default impl for more accuracy
@@ -45,17 +239,21 @@ impl Swap for OrderBook {
         &self,
         deps: Deps<QueryWrapper>,
         env: &Env,
-        _offer_asset: &AssetInfo,
+        offer_asset: &AssetInfo,
         ask_asset: &AssetInfo,
         ask_amount: Uint256,
-    ) -> NeptuneResult<Vec<CosmosMsg<MsgWrapper>>> {
-        if let Some(msg) =
-            market_order_ask(deps, env, self.market_id.clone(), ask_asset, ask_amount)?
-        {
-            Ok(vec![msg])
-        } else {
-            Ok(vec![])
-        }
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let plan = market_order_ask(
+            deps,
+            env,
+            self,
+            &self.exchange_asset(offer_asset)?,
+            &self.exchange_asset(ask_asset)?,
+            ask_amount,
+            slippage,
+        )?;
+        self.wrap_cw20(deps, plan, offer_asset, ask_asset)
     }
 
     /// sends a query for a swap simulation
@@ -69,12 +267,10 @@ impl Swap for OrderBook {
         if offer_amount.is_zero() {
             return Ok(Uint256::zero());
         }
-        let AssetInfo::NativeToken { denom: offer_denom } = offer_asset else {
-            return Err(SwapError::InvalidAsset.into());
-        };
+        let offer_denom = &self.denom(offer_asset)?;
         let spot_market = query_spot_market(deps, self.market_id.clone())?;
 
-        let fee_rate = query_total_fees(deps, &spot_market);
+        let params = query_market_params(deps, &spot_market)?;
         let offer_amount = offer_amount.into();
 
         let ask_amount = if offer_denom == &spot_market.quote_denom {
@@ -86,7 +282,7 @@ impl Swap for OrderBook {
                 None,
                 Some(offer_amount),
             )?;
-            get_buy_quantity(&spot_market, fee_rate, &order_book, offer_amount)?.quantity
+            buy_with_quote(&params, &order_book.sells_price_level, offer_amount)?.quantity
         } else if offer_denom == &spot_market.base_denom {
             let order_book = query_spot_market_order_book(
                 deps,
@@ -96,12 +292,12 @@ impl Swap for OrderBook {
                 Some(offer_amount),
                 None,
             )?;
-            get_sell_ask_amount(&spot_market, fee_rate, &order_book, offer_amount)?
+            sell_quantity(&params, &order_book.buys_price_level, offer_amount)?.quote_amount
         } else {
             return Err(SwapError::InvalidOfferAsset.into());
         };
 
-        Ok(into_uint_256(ask_amount.int()))
+        into_amount(ask_amount)
     }
 
     fn query_swap_ratio(
@@ -111,11 +307,9 @@ impl Swap for OrderBook {
         _ask_asset: &AssetInfo,
         offer_amount: Uint256,
     ) -> NeptuneResult<Decimal256> {
-        let AssetInfo::NativeToken { denom: offer_denom } = offer_asset else {
-            return Err(SwapError::InvalidAsset.into());
-        };
+        let offer_denom = &self.denom(offer_asset)?;
         let spot_market = query_spot_market(deps, self.market_id.clone())?;
-        let fee_rate = query_total_fees(deps, &spot_market);
+        let params = query_market_params(deps, &spot_market)?;
         let mut offer_amount = offer_amount.into();
 
         let ask_amount = if offer_denom == &spot_market.quote_denom {
@@ -127,12 +321,12 @@ impl Swap for OrderBook {
                 None,
                 Some(offer_amount),
             )?;
-            let buy_quantity = get_buy_quantity(&spot_market, fee_rate, &order_book, offer_amount)?
+            let sells = &order_book.sells_price_level;
+            let quantity = buy_with_quote(&params, sells, offer_amount)?
                 .quantity
-                .max(spot_market.min_quantity_tick_size);
-            offer_amount = get_buy_offer_amount(&spot_market, fee_rate, &order_book, buy_quantity)?
-                .offer_amount;
-            buy_quantity
+                .max(params.min_quantity_tick_size);
+            offer_amount = buy_quantity(&params, sells, quantity)?.quote_amount;
+            quantity
         } else if offer_denom == &spot_market.base_denom {
             let order_book = query_spot_market_order_book(
                 deps,
@@ -142,9 +336,9 @@ impl Swap for OrderBook {
                 Some(offer_amount),
                 None,
             )?;
-            offer_amount = tick_round_down(offer_amount, spot_market.min_quantity_tick_size)
-                .max(spot_market.min_quantity_tick_size);
-            get_sell_ask_amount(&spot_market, fee_rate, &order_book, offer_amount)?
+            offer_amount = tick_round_down(offer_amount, params.min_quantity_tick_size)
+                .max(params.min_quantity_tick_size);
+            sell_quantity(&params, &order_book.buys_price_level, offer_amount)?.quote_amount
         } else {
             return Err(SwapError::InvalidAsset.into());
         };
@@ -155,7 +349,8 @@ impl Swap for OrderBook {
 
         let swap_ratio = offer_amount / ask_amount;
 
-        Ok(into_decimal_256(swap_ratio))
+        Ok(try_into_decimal_256(swap_ratio)
+            .map_err(|_| SwapError::InvalidAmount(swap_ratio.to_string()))?)
     }
 
     /// Returns the of the offer asset required to receive the given amount of the ask asset, rounded up.
@@ -170,12 +365,10 @@ impl Swap for OrderBook {
         if ask_amount.is_zero() {
             return Ok(Uint256::zero());
         }
-        let AssetInfo::NativeToken { denom: ask_denom } = ask_asset else {
-            return Err(SwapError::InvalidAsset.into());
-        };
+        let ask_denom = &self.denom(ask_asset)?;
         let spot_market = query_spot_market(deps, self.market_id.clone())?;
 
-        let fee_rate = query_total_fees(deps, &spot_market);
+        let params = query_market_params(deps, &spot_market)?;
 
         let ask_amount = ask_amount.into();
 
@@ -188,7 +381,7 @@ impl Swap for OrderBook {
                 Some(ask_amount),
                 None,
             )?;
-            get_buy_offer_amount(&spot_market, fee_rate, &order_book, ask_amount)?.offer_amount
+            buy_quantity(&params, &order_book.sells_price_level, ask_amount)?.quote_amount
         } else if ask_denom == &spot_market.quote_denom {
             let order_book = query_spot_market_order_book(
                 deps,
@@ -198,14 +391,16 @@ impl Swap for OrderBook {
                 None,
                 Some(ask_amount),
             )?;
-            get_sell_quantity(&spot_market, fee_rate, &order_book, ask_amount)?
+            sell_for_quote(&params, &order_book.buys_price_level, ask_amount)?.quantity
         } else {
             return Err(SwapError::InvalidAsset.into());
         };
 
-        Ok(into_uint_256(offer_amount.int()))
+        into_amount(offer_amount)
     }
 
+    /// Errors if the price is not crossed within the maximum number of levels,
+    /// use `OrderBook::ask_amount_at_price` to accept a partial result.
     fn query_ask_amount_at_price(
         &self,
         deps: Deps<QueryWrapper>,
@@ -213,42 +408,11 @@ impl Swap for OrderBook {
         _ask_asset: &AssetInfo,
         max_ratio: Decimal256,
     ) -> NeptuneResult<Uint256> {
-        let AssetInfo::NativeToken { denom: offer_denom } = offer_asset else {
-            return Err(SwapError::InvalidAsset.into());
-        };
-        let spot_market = query_spot_market(deps, self.market_id.clone())?;
-
-        let fee_rate = query_total_fees(deps, &spot_market);
-
-        let ask_amount = if offer_denom == &spot_market.quote_denom {
-            let price = into_fp_decimal(max_ratio);
-            // TODO: This quey is problematic as we cannot effectively limit
-            // TODO: our query.
-            let order_book = query_spot_market_order_book(
-                deps,
-                self.market_id.clone(),
-                0,
-                OrderSide::Sell,
-                None,
-                None,
-            )?;
-            get_buy_quantity_at_price(&spot_market, &order_book, price)?
-        } else if offer_denom == &spot_market.base_denom {
-            let price = into_fp_decimal(max_ratio.inv().unwrap());
-            let order_book = query_spot_market_order_book(
-                deps,
-                self.market_id.clone(),
-                0,
-                OrderSide::Buy,
-                None,
-                None,
-            )?;
-            get_sell_ask_amount_at_price(&spot_market, fee_rate, &order_book, price)?
-        } else {
-            return Err(SwapError::InvalidAsset.into());
-        };
-
-        Ok(into_uint_256(ask_amount.int()))
+        let res = self.ask_amount_at_price(deps, offer_asset, max_ratio)?;
+        if res.partial {
+            return Err(SwapError::OrderBookDepthExceeded(self.max_book_levels()).into());
+        }
+        Ok(res.ask_amount)
     }
 
     /// Uses a swap simulation to calculate the ratio of offer to ask.
@@ -267,23 +431,29 @@ impl Swap for OrderBook {
 pub fn market_order_offer(
     deps: Deps<QueryWrapper>,
     env: &Env,
-    market_id: MarketId,
+    order_book: &OrderBook,
     offer_asset: &AssetInfo,
+    ask_asset: &AssetInfo,
     offer_amount: Uint256,
-) -> NeptuneResult<Option<CosmosMsg<MsgWrapper>>> {
+    slippage: &Slippage,
+) -> NeptuneResult<SwapPlan> {
     let offer_amount = FPDecimal::from(offer_amount);
 
     if offer_amount.is_zero() {
-        return Ok(None);
+        return Ok(SwapPlan::empty(offer_asset, ask_asset));
     }
 
     let AssetInfo::NativeToken { denom: offer_denom } = offer_asset else {
         return Err(SwapError::InvalidAsset.into());
     };
 
+    let market_id = order_book.market_id.clone();
+
     let spot_market = query_spot_market(deps, market_id.clone())?;
 
-    let fee_rate = query_total_fees(deps, &spot_market);
+    let params = query_market_params(deps, &spot_market)?;
+
+    let subaccount_id = order_book.subaccount_id(env);
 
     if &spot_market.quote_denom == offer_denom {
         let order_book = query_spot_market_order_book(
@@ -294,40 +464,75 @@ pub fn market_order_offer(
             None,
             Some(offer_amount),
         )?;
-        let buy_quantity = get_buy_quantity(&spot_market, fee_rate, &order_book, offer_amount)?;
-        buy(
+        let fill = buy_with_quote(&params, &order_book.sells_price_level, offer_amount)?;
+        let min_receive = assert_slippage(slippage, offer_amount, fill.quantity)?;
+        let msg = buy(
             env,
             &spot_market,
-            buy_quantity.worst_order_price,
-            buy_quantity.quantity,
+            subaccount_id,
+            fill.worst_price,
+            fill.quantity,
+        )?;
+        order_plan(
+            msg,
+            (offer_asset, offer_amount),
+            (ask_asset, fill.quantity),
+            (offer_asset, fill.fee),
+            fill.price_impact,
+            min_receive,
         )
     } else if &spot_market.base_denom == offer_denom {
-        sell(env, &spot_market, offer_amount)
+        let order_book = query_spot_market_order_book(
+            deps,
+            market_id,
+            0,
+            OrderSide::Buy,
+            Some(offer_amount),
+            None,
+        )?;
+        let fill = sell_quantity(&params, &order_book.buys_price_level, offer_amount)?;
+        let min_receive = assert_slippage(slippage, offer_amount, fill.quote_amount)?;
+        let price = sell_limit_price(&fill, min_receive)?;
+        let msg = sell(env, &spot_market, subaccount_id, offer_amount, price)?;
+        order_plan(
+            msg,
+            (offer_asset, offer_amount),
+            (ask_asset, fill.quote_amount),
+            (ask_asset, fill.fee),
+            fill.price_impact,
+            min_receive,
+        )
     } else {
-        return Err(SwapError::InvalidAsset.into());
+        Err(SwapError::InvalidAsset.into())
     }
 }
 
 pub fn market_order_ask(
     deps: Deps<QueryWrapper>,
     env: &Env,
-    market_id: MarketId,
+    order_book: &OrderBook,
+    offer_asset: &AssetInfo,
     ask_asset: &AssetInfo,
     ask_amount: Uint256,
-) -> NeptuneResult<Option<CosmosMsg<MsgWrapper>>> {
+    slippage: &Slippage,
+) -> NeptuneResult<SwapPlan> {
     let ask_amount = FPDecimal::from(ask_amount);
 
     if ask_amount.is_zero() {
-        return Ok(None);
+        return Ok(SwapPlan::empty(offer_asset, ask_asset));
     }
 
     let AssetInfo::NativeToken { denom: ask_denom } = ask_asset else {
         return Err(SwapError::InvalidAsset.into());
     };
 
+    let market_id = order_book.market_id.clone();
+
     let spot_market = query_spot_market(deps, market_id.clone())?;
 
-    let fee_rate = query_total_fees(deps, &spot_market);
+    let params = query_market_params(deps, &spot_market)?;
+
+    let subaccount_id = order_book.subaccount_id(env);
 
     if &spot_market.base_denom == ask_denom {
         let order_book = query_spot_market_order_book(
@@ -338,10 +543,23 @@ pub fn market_order_ask(
             Some(ask_amount),
             None,
         )?;
-        let worst_order_price =
-            get_buy_offer_amount(&spot_market, fee_rate, &order_book, ask_amount)?
-                .worst_order_price;
-        buy(env, &spot_market, worst_order_price, ask_amount)
+        let fill = buy_quantity(&params, &order_book.sells_price_level, ask_amount)?;
+        let min_receive = assert_slippage(slippage, fill.quote_amount, ask_amount)?;
+        let msg = buy(
+            env,
+            &spot_market,
+            subaccount_id,
+            fill.worst_price,
+            ask_amount,
+        )?;
+        order_plan(
+            msg,
+            (offer_asset, fill.quote_amount),
+            (ask_asset, ask_amount),
+            (offer_asset, fill.fee),
+            fill.price_impact,
+            min_receive,
+        )
     } else if &spot_market.quote_denom == ask_denom {
         let order_book = query_spot_market_order_book(
             deps,
@@ -351,197 +569,75 @@ pub fn market_order_ask(
             None,
             Some(ask_amount),
         )?;
-        let quantity = get_sell_quantity(&spot_market, fee_rate, &order_book, ask_amount)?.int();
-        sell(env, &spot_market, quantity)
+        let fill = sell_for_quote(&params, &order_book.buys_price_level, ask_amount)?;
+        let min_receive = assert_slippage(slippage, fill.quantity, fill.quote_amount)?;
+        let price = sell_limit_price(&fill, min_receive)?;
+        let msg = sell(env, &spot_market, subaccount_id, fill.quantity, price)?;
+        order_plan(
+            msg,
+            (offer_asset, fill.quantity),
+            (ask_asset, fill.quote_amount),
+            (ask_asset, fill.fee),
+            fill.price_impact,
+            min_receive,
+        )
     } else {
-        return Err(SwapError::InvalidAsset.into());
+        Err(SwapError::InvalidAsset.into())
     }
 }
 
-struct GetBuyQuantity {
-    quantity: FPDecimal,
-    worst_order_price: Option<FPDecimal>,
-}
-
-/// returns the quantity of the ask asset (rounded down)
-/// that can be bought with the given offer amount
-fn get_buy_quantity(
-    spot_market: &SpotMarket,
-    fee_rate: FPDecimal,
-    order_book: &QueryOrderbookResponse,
-    offer_amount: FPDecimal, // quote
-) -> NeptuneResult<GetBuyQuantity> {
-    let mut remaining_offer_amount = offer_amount; // quote
-    let mut quantity = FPDecimal::ZERO; // base
-    let mut worst_order_price = None;
-    for sell_order in &order_book.sells_price_level {
-        let sell_order_quantity = sell_order.q;
-        let sell_order_price = sell_order.p;
-        worst_order_price = Some(sell_order_price);
-        let sell_order_base_amount = apply_fee(sell_order_quantity * sell_order_price, fee_rate);
-        if remaining_offer_amount > sell_order_base_amount {
-            quantity += sell_order_quantity;
-            remaining_offer_amount -= sell_order_base_amount;
-        } else {
-            // `sell_order_price` cannot be zero, no need to check.
-            quantity +=
-                (remaining_offer_amount / ((FPDecimal::ONE + fee_rate) * sell_order_price)).int();
-            break;
-        }
-    }
-    quantity = tick_round_down(quantity, spot_market.min_quantity_tick_size);
-    Ok(GetBuyQuantity {
-        quantity,
-        worst_order_price,
+/// Creates the plan of a market order from its simulated amounts.
+fn order_plan(
+    msg: Option<CosmosMsg<MsgWrapper>>,
+    offer: (&AssetInfo, FPDecimal),
+    ask: (&AssetInfo, FPDecimal),
+    commission: (&AssetInfo, FPDecimal),
+    price_impact: Decimal256,
+    min_receive: FPDecimal,
+) -> NeptuneResult<SwapPlan> {
+    Ok(SwapPlan {
+        msgs: msg.into_iter().collect(),
+        offer: AssetAmount {
+            info: offer.0.clone(),
+            amount: into_amount(offer.1)?,
+        },
+        ask: AssetAmount {
+            info: ask.0.clone(),
+            amount: into_amount(ask.1)?,
+        },
+        commission: (commission.0.clone(), into_amount(commission.1)?).into(),
+        price_impact,
+        min_receive: into_amount(min_receive)?,
+        surplus: Uint256::zero(),
+        venue: None,
+        legs: vec![],
     })
 }
 
-/// returns the quantity of the ask asset (rounded down)
-/// that can be bought for less that `price`.
-fn get_buy_quantity_at_price(
-    spot_market: &SpotMarket,
-    order_book: &QueryOrderbookResponse,
-    price: FPDecimal, // quote
-) -> NeptuneResult<FPDecimal> {
-    let mut quantity = FPDecimal::ZERO; // base
-    for sell_order in &order_book.sells_price_level {
-        let sell_order_quantity = sell_order.q;
-        let sell_order_price = sell_order.p;
-        if sell_order_price > price {
-            break;
-        }
-        quantity += sell_order_quantity;
-    }
-    quantity = tick_round_down(quantity, spot_market.min_quantity_tick_size);
-    Ok(quantity)
-}
-
-/// Returns the quantity of the offer asset (rounded up)
-/// that can be sold for more than `price`.
-fn get_sell_ask_amount_at_price(
-    spot_market: &SpotMarket,
-    fee_rate: FPDecimal,
-    order_book: &QueryOrderbookResponse,
-    price: FPDecimal, // quote
-) -> NeptuneResult<FPDecimal> {
-    let mut quantity = FPDecimal::ZERO; // base
-    for buy_order in &order_book.buys_price_level {
-        let buy_order_quantity = buy_order.q;
-        let buy_order_price = buy_order.p;
-        if buy_order_price < price {
-            break;
-        }
-        let buy_order_base_amount = apply_fee(buy_order_quantity * buy_order_price, fee_rate);
-        quantity += buy_order_base_amount;
-    }
-    let quantity = tick_round_down(quantity, spot_market.min_quantity_tick_size);
-    Ok(quantity)
-}
-
-/// Returns the quantity of the offer asset (rounded up)
-/// that is required to receive the ask amount.
-/// Will throw an error on insufficient liquidity.
-fn get_sell_quantity(
-    spot_market: &SpotMarket,
-    fee_rate: FPDecimal,
-    order_book: &QueryOrderbookResponse,
-    ask_amount: FPDecimal, // quote
+/// Returns the minimum amount to be received under the slippage policy,
+/// erroring if the simulated return is already below it.
+fn assert_slippage(
+    slippage: &Slippage,
+    offer_amount: FPDecimal,
+    return_amount: FPDecimal,
 ) -> NeptuneResult<FPDecimal> {
-    let mut remaining_ask_amount = ask_amount; // quote
-    let mut quantity = FPDecimal::ZERO; // base
-    for buy_order in &order_book.buys_price_level {
-        let buy_order_quantity = buy_order.q;
-        let buy_order_price = buy_order.p;
-        let buy_order_quote_amount =
-            ((buy_order_quantity * buy_order_price) * (FPDecimal::ONE - fee_rate)).int();
-        if remaining_ask_amount > buy_order_quote_amount {
-            quantity += buy_order_quantity;
-            remaining_ask_amount -= buy_order_quote_amount;
-        } else {
-            // `buy_order_price` cannot be zero, no need to check.
-            quantity += tick_round_up(
-                apply_fee(remaining_ask_amount / buy_order_price, fee_rate),
-                spot_market.min_quantity_tick_size,
-            );
-            remaining_ask_amount = FPDecimal::ZERO;
-            break;
-        }
-    }
-    if !remaining_ask_amount.is_zero() {
-        return Err(SwapError::InsufficientLiquidity.into());
-    }
-    quantity *= FPDecimal::must_from_str("1.00001");
-    let quantity = tick_round_up(quantity, spot_market.min_quantity_tick_size);
-    Ok(quantity)
+    let min_receive = slippage.assert(into_amount(offer_amount)?, into_amount(return_amount)?)?;
+    Ok(min_receive.into())
 }
 
-struct GetBuyOfferAmount {
-    offer_amount: FPDecimal,
-    worst_order_price: Option<FPDecimal>,
+/// Rounds a simulated amount down to an integer amount, erroring if it is negative.
+fn into_amount(value: FPDecimal) -> NeptuneResult<Uint256> {
+    Ok(try_into_uint_256(value.int()).map_err(|_| SwapError::InvalidAmount(value.to_string()))?)
 }
 
-/// returns the offer amount amount_required to purchase
-/// a given quantity of the ask asset
-fn get_buy_offer_amount(
-    spot_market: &SpotMarket,
-    fee_rate: FPDecimal,
-    order_book: &QueryOrderbookResponse,
-    quantity: FPDecimal, // quote
-) -> NeptuneResult<GetBuyOfferAmount> {
-    let quantity = tick_round_up(quantity, spot_market.min_quantity_tick_size);
-    let mut offer_amount = FPDecimal::ZERO;
-    let mut worst_order_price = None;
-    let mut remaining_quantity = quantity;
-    for sell_order in &order_book.sells_price_level {
-        let sell_order_quantity = sell_order.q;
-        let sell_order_price = sell_order.p;
-        worst_order_price = Some(sell_order_price);
-        if sell_order_quantity > remaining_quantity {
-            offer_amount += apply_fee(remaining_quantity * sell_order_price, fee_rate);
-            remaining_quantity = FPDecimal::ZERO;
-            break;
-        } else {
-            offer_amount += apply_fee(sell_order_quantity * sell_order_price, fee_rate);
-            remaining_quantity -= sell_order_quantity;
-        }
-    }
-    if !remaining_quantity.is_zero() {
+/// The worst price of a sell scaled down by the fraction of the simulated
+/// return which must be received. Fills below this price are rejected by the exchange.
+fn sell_limit_price(fill: &Fill, min_receive: FPDecimal) -> NeptuneResult<FPDecimal> {
+    let worst_price = fill.worst_price.ok_or(SwapError::InsufficientLiquidity)?;
+    if fill.quote_amount.is_zero() {
         return Err(SwapError::InsufficientLiquidity.into());
     }
-    Ok(GetBuyOfferAmount {
-        offer_amount,
-        worst_order_price,
-    })
-}
-
-/// returns the ask amount received from selling
-/// a given quantity of the offer asset
-fn get_sell_ask_amount(
-    spot_market: &SpotMarket,
-    fee_rate: FPDecimal,
-    order_book: &QueryOrderbookResponse,
-    quantity: FPDecimal, // quote
-) -> NeptuneResult<FPDecimal> {
-    let quantity = tick_round_down(quantity, spot_market.min_quantity_tick_size);
-    let mut ask_amount = FPDecimal::ZERO;
-    let mut remaining_quantity = quantity;
-    for buy_order in &order_book.buys_price_level {
-        let buy_order_quantity = buy_order.q;
-        let buy_order_price = buy_order.p;
-        if buy_order_quantity > remaining_quantity {
-            ask_amount +=
-                ((remaining_quantity * buy_order_price) / (FPDecimal::ONE + fee_rate)).int();
-            break;
-        } else {
-            ask_amount +=
-                ((buy_order_quantity * buy_order_price) / (FPDecimal::ONE + fee_rate)).int();
-            remaining_quantity -= buy_order_quantity;
-        }
-    }
-    // TODO: why does this work?
-    ask_amount = tick_round_down(ask_amount, FPDecimal::from(10_000_000_000_000u128));
-
-    Ok(ask_amount)
+    Ok(worst_price * min_receive / fill.quote_amount)
 }
 
 /// Buys the given quantity rounded up, erroring on insufficient funds
@@ -551,6 +647,7 @@ fn get_sell_ask_amount(
 fn buy(
     env: &Env,
     spot_market: &SpotMarket,
+    subaccount_id: SubaccountId,
     worst_order_price: Option<FPDecimal>,
     quantity: FPDecimal,
 ) -> NeptuneResult<Option<CosmosMsg<MsgWrapper>>> {
@@ -560,14 +657,12 @@ fn buy(
     }
     let worst_order_price = worst_order_price.ok_or(SwapError::InsufficientLiquidity)?;
     let price = tick_round_down(worst_order_price, spot_market.min_price_tick_size);
-    let subaccount_id = get_default_subaccount_id_for_checked_address(&env.contract.address);
 
     let order_info = OrderInfo {
         subaccount_id,
         fee_recipient: Some(env.contract.address.clone()),
         price,
         quantity,
-        // TODO
         cid: None,
     };
 
@@ -590,17 +685,20 @@ fn buy(
 }
 
 /// Sells the given quantity rounded down, erroring on insufficient funds
+/// `worst_order_price` is the lowest price that can be accepted
 fn sell(
     env: &Env,
     spot_market: &SpotMarket,
+    subaccount_id: SubaccountId,
     quantity: FPDecimal,
+    worst_order_price: FPDecimal,
 ) -> NeptuneResult<Option<CosmosMsg<MsgWrapper>>> {
     let quantity = tick_round_down(quantity, spot_market.min_quantity_tick_size);
     if quantity.is_zero() {
         return Ok(None);
     }
-    let price = spot_market.min_price_tick_size;
-    let subaccount_id = get_default_subaccount_id_for_checked_address(&env.contract.address);
+    let price = tick_round_down(worst_order_price, spot_market.min_price_tick_size)
+        .max(spot_market.min_price_tick_size);
 
     let order_info = OrderInfo {
         subaccount_id,
@@ -628,6 +726,127 @@ fn sell(
     Ok(Some(CosmosMsg::Custom(wrapper)))
 }
 
+/// A resting order on a spot market, placed from one of the contract's subaccounts.
+#[cw_serde]
+pub struct LimitOrder {
+    pub market_id: MarketId,
+    pub is_buy: bool,
+    /// Rounded to the price tick, down for buys and up for sells.
+    pub price: FPDecimal,
+    /// Rounded down to the quantity tick.
+    pub quantity: FPDecimal,
+    /// Post-only orders are rejected by the exchange instead of crossing the spread.
+    pub post_only: bool,
+    /// A client order id, echoed back by the exchange.
+    pub cid: Option<String>,
+}
+
+/// Places a resting limit order. The funds are taken from the contract's subaccount
+/// with the given nonce, the default subaccount is credited from its bank balance
+/// when it is insufficient.
+pub fn limit_order(
+    env: &Env,
+    spot_market: &SpotMarket,
+    subaccount_nonce: u32,
+    order: LimitOrder,
+) -> NeptuneResult<CosmosMsg<MsgWrapper>> {
+    let quantity = tick_round_down(order.quantity, spot_market.min_quantity_tick_size);
+    if quantity.is_zero() {
+        return Err(SwapError::OrderTooSmall.into());
+    }
+    let price = if order.is_buy {
+        tick_round_down(order.price, spot_market.min_price_tick_size)
+    } else {
+        tick_round_up(order.price, spot_market.min_price_tick_size)
+    };
+    if price.is_zero() {
+        return Err(SwapError::InvalidOrderPrice.into());
+    }
+    let order_type = match (order.is_buy, order.post_only) {
+        (true, false) => OrderType::Buy,
+        (true, true) => OrderType::BuyPo,
+        (false, false) => OrderType::Sell,
+        (false, true) => OrderType::SellPo,
+    };
+
+    let order_info = OrderInfo {
+        subaccount_id: subaccount_id(&env.contract.address, subaccount_nonce),
+        fee_recipient: Some(env.contract.address.clone()),
+        price,
+        quantity,
+        cid: order.cid,
+    };
+
+    let order = SpotOrder {
+        market_id: order.market_id,
+        order_info,
+        order_type,
+        trigger_price: None,
+    };
+
+    let wrapper = MsgWrapper {
+        route: InjectiveRoute::Exchange,
+        msg_data: InjectiveMsg::CreateSpotLimitOrder {
+            sender: env.contract.address.clone(),
+            order,
+        },
+    };
+
+    Ok(CosmosMsg::Custom(wrapper))
+}
+
+/// Cancels one of the resting orders of the contract's subaccount with the given nonce.
+pub fn cancel_order(
+    env: &Env,
+    market_id: MarketId,
+    subaccount_nonce: u32,
+    order_hash: String,
+) -> CosmosMsg<MsgWrapper> {
+    cancel_spot_order_msg(
+        env.contract.address.clone(),
+        market_id,
+        subaccount_id(&env.contract.address, subaccount_nonce),
+        order_hash,
+    )
+}
+
+/// Cancels every resting order of the contract's subaccount with the given nonce on a market.
+pub fn cancel_all_orders(
+    deps: Deps<QueryWrapper>,
+    env: &Env,
+    market_id: MarketId,
+    subaccount_nonce: u32,
+) -> NeptuneResult<Vec<CosmosMsg<MsgWrapper>>> {
+    Ok(
+        query_open_orders(deps, env, market_id.clone(), subaccount_nonce)?
+            .into_iter()
+            .map(|order| cancel_order(env, market_id.clone(), subaccount_nonce, order.order_hash))
+            .collect(),
+    )
+}
+
+/// Returns the resting orders of the contract's subaccount with the given nonce on a market.
+pub fn query_open_orders(
+    deps: Deps<QueryWrapper>,
+    env: &Env,
+    market_id: MarketId,
+    subaccount_nonce: u32,
+) -> NeptuneResult<Vec<TrimmedSpotLimitOrder>> {
+    let wrapper = QueryWrapper {
+        route: InjectiveRoute::Exchange,
+        query_data: InjectiveQuery::TraderSpotOrders {
+            market_id,
+            subaccount_id: subaccount_id(&env.contract.address, subaccount_nonce),
+        },
+    };
+
+    let query_request = QueryRequest::Custom(wrapper);
+
+    let res: TraderSpotOrdersResponse = deps.querier.query(&query_request)?;
+
+    Ok(res.orders.unwrap_or_default())
+}
+
 pub fn query_spot_market_mid_price_and_tob(
     deps: Deps<QueryWrapper>,
     market_id: MarketId,
@@ -684,6 +903,45 @@ fn query_spot_market_order_book(
     Ok(deps.querier.query(&query_request)?)
 }
 
+/// Fetches the bids or asks until a level is past `price`, doubling
+/// the page size up to `max_levels`. The exchange has no offset on order book queries,
+/// so each page extends the previous one. Also returns whether the walk stopped
+/// at `max_levels` before the price was crossed.
+fn query_levels_to_price(
+    deps: Deps<QueryWrapper>,
+    market_id: MarketId,
+    is_buy: bool,
+    price: FPDecimal,
+    max_levels: u64,
+) -> NeptuneResult<(Vec<PriceLevel>, bool)> {
+    let mut limit = INITIAL_BOOK_LEVELS.min(max_levels).max(1);
+    loop {
+        let order_side = if is_buy {
+            OrderSide::Buy
+        } else {
+            OrderSide::Sell
+        };
+        let order_book =
+            query_spot_market_order_book(deps, market_id.clone(), limit, order_side, None, None)?;
+        let (levels, crossed) = if is_buy {
+            let levels = order_book.buys_price_level;
+            let crossed = levels.last().is_some_and(|level| level.p < price);
+            (levels, crossed)
+        } else {
+            let levels = order_book.sells_price_level;
+            let crossed = levels.last().is_some_and(|level| level.p > price);
+            (levels, crossed)
+        };
+        if crossed || (levels.len() as u64) < limit {
+            return Ok((levels, false));
+        }
+        if limit >= max_levels {
+            return Ok((levels, true));
+        }
+        limit = (limit * 2).min(max_levels);
+    }
+}
+
 fn query_atomic_fee_execution_multiplier(
     deps: Deps<QueryWrapper>,
     market_id: MarketId,
@@ -701,32 +959,537 @@ fn query_atomic_fee_execution_multiplier(
     Ok(res.multiplier)
 }
 
-fn query_total_fees(deps: Deps<QueryWrapper>, spot_market: &SpotMarket) -> FPDecimal {
-    let multiplier =
-        query_atomic_fee_execution_multiplier(deps, spot_market.market_id.clone()).unwrap();
-    multiplier * spot_market.taker_fee_rate * (FPDecimal::ONE - spot_market.relayer_fee_share_rate)
+fn query_market_params(
+    deps: Deps<QueryWrapper>,
+    spot_market: &SpotMarket,
+) -> NeptuneResult<MarketParams> {
+    let multiplier = query_atomic_fee_execution_multiplier(deps, spot_market.market_id.clone())
+        .map_err(|_| {
+            SwapError::FeeMultiplierNotFound(spot_market.market_id.as_str().to_string())
+        })?;
+    Ok(MarketParams::new(spot_market, multiplier))
 }
 
-fn apply_fee(value: FPDecimal, fee: FPDecimal) -> FPDecimal {
-    let res = value.int() * (FPDecimal::ONE + fee);
-    if res.is_int() {
-        res
-    } else {
-        (res + FPDecimal::ONE).int()
-    }
-}
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{from_json, SystemError, SystemResult, Uint128};
+    use injective_cosmwasm::{
+        exchange::types::PriceLevel, get_default_subaccount_id_for_checked_address, MarketStatus,
+    };
 
-pub fn tick_round_up(value: FPDecimal, tick_size: FPDecimal) -> FPDecimal {
-    let tick_num = value / tick_size;
-    let tick_num = if tick_num.is_int() {
-        tick_num
-    } else {
-        (tick_num + FPDecimal::ONE).int() // no ceiling function
+    use super::*;
+    use crate::{
+        error::NeptuneError,
+        swap::mock_exchange::{
+            self, fp, mock_dependencies_with_exchange, MockExchange, MockSpotMarket,
+        },
+        testing::mock_env,
     };
-    tick_num * tick_size
-}
 
-pub fn tick_round_down(value: FPDecimal, tick_size: FPDecimal) -> FPDecimal {
-    let tick_num = (value / tick_size).int();
-    tick_num * tick_size
+    const MARKET_ID: &str = "0x0611780ba69656949525013d947713300f56c37b6175e02f26bffa495c3208fe";
+
+    fn level(p: &str, q: &str) -> PriceLevel {
+        PriceLevel { p: fp(p), q: fp(q) }
+    }
+
+    fn denom(denom: &str) -> AssetInfo {
+        AssetInfo::NativeToken {
+            denom: denom.to_string(),
+        }
+    }
+
+    /// A market between two 18 decimal assets with a total fee rate of 0.2%.
+    fn exchange() -> MockExchange {
+        MockExchange::default().with_market(MockSpotMarket {
+            market: SpotMarket {
+                ticker: "BASE/QUOTE".to_string(),
+                base_denom: "base".to_string(),
+                quote_denom: "quote".to_string(),
+                maker_fee_rate: fp("0.001"),
+                taker_fee_rate: fp("0.002"),
+                relayer_fee_share_rate: fp("0.5"),
+                market_id: MarketId::new(MARKET_ID).unwrap(),
+                status: MarketStatus::Active,
+                min_price_tick_size: fp("0.001"),
+                min_quantity_tick_size: fp("1000000000000000"),
+            },
+            buys: vec![
+                level("1.5", "1000000000000000000"),
+                level("1", "1000000000000000000"),
+            ],
+            sells: vec![
+                level("2", "1000000000000000000"),
+                level("3", "1000000000000000000"),
+            ],
+            atomic_execution_fee_multiplier: fp("2"),
+            open_orders: vec![],
+        })
+    }
+
+    fn order_book() -> OrderBook {
+        OrderBook {
+            market_id: MarketId::new(MARKET_ID).unwrap(),
+            max_book_levels: None,
+            decimals: None,
+            cw20_adapter: None,
+            subaccount_nonce: None,
+        }
+    }
+
+    #[test]
+    fn test_query_sim() {
+        let deps = mock_dependencies_with_exchange(exchange());
+        let (base, quote) = (denom("base"), denom("quote"));
+
+        let buy_quantity = order_book()
+            .query_sim(
+                deps.as_ref(),
+                &quote,
+                &base,
+                1_002_000_000_000_000_000u128.into(),
+            )
+            .unwrap();
+        assert_eq!(buy_quantity, Uint256::from(500_000_000_000_000_000u128));
+
+        let sell_amount = order_book()
+            .query_sim(
+                deps.as_ref(),
+                &base,
+                &quote,
+                1_000_000_000_000_000_000u128.into(),
+            )
+            .unwrap();
+        assert_eq!(sell_amount, Uint256::from(1_497_005_000_000_000_000u128));
+
+        let err = order_book()
+            .query_sim(deps.as_ref(), &denom("other"), &quote, 1u64.into())
+            .unwrap_err();
+        assert_eq!(err, NeptuneError::from(SwapError::InvalidOfferAsset));
+    }
+
+    #[test]
+    fn test_swap() {
+        let deps = mock_dependencies_with_exchange(exchange());
+        let env = mock_env();
+        let (base, quote) = (denom("base"), denom("quote"));
+        let slippage = Slippage::Tolerance(Decimal256::percent(1));
+        let mut exchange = exchange();
+
+        let buy = order_book()
+            .swap(
+                deps.as_ref(),
+                &env,
+                &quote,
+                &base,
+                1_002_000_000_000_000_000u128.into(),
+                &slippage,
+            )
+            .unwrap();
+        assert_eq!(buy.ask.amount, Uint256::from(500_000_000_000_000_000u128));
+        assert_eq!(buy.min_receive, Uint256::from(495_000_000_000_000_000u128));
+        assert_eq!(buy.price_impact, Decimal256::zero());
+        exchange.execute(&buy.msgs);
+
+        let sell = order_book()
+            .swap(
+                deps.as_ref(),
+                &env,
+                &base,
+                &quote,
+                1_000_000_000_000_000_000u128.into(),
+                &slippage,
+            )
+            .unwrap();
+        assert_eq!(
+            sell.ask.amount,
+            Uint256::from(1_497_005_000_000_000_000u128)
+        );
+        assert_eq!(
+            sell.min_receive,
+            Uint256::from(1_482_034_950_000_000_000u128)
+        );
+        exchange.execute(&sell.msgs);
+
+        let [buy_order, sell_order] = &exchange.orders[..] else {
+            panic!("expected two orders, got {:?}", exchange.orders);
+        };
+        assert_eq!(buy_order.order_type, OrderType::BuyAtomic);
+        assert_eq!(buy_order.order_info.quantity, fp("500000000000000000"));
+        assert_eq!(buy_order.order_info.price, fp("2"));
+        assert_eq!(sell_order.order_type, OrderType::SellAtomic);
+        assert_eq!(sell_order.order_info.quantity, fp("1000000000000000000"));
+        // The worst price of the fill scaled down by the slippage tolerance.
+        assert_eq!(sell_order.order_info.price, fp("1.485"));
+    }
+
+    #[test]
+    fn test_swap_ask() {
+        let deps = mock_dependencies_with_exchange(exchange());
+        let (base, quote) = (denom("base"), denom("quote"));
+        let mut exchange = exchange();
+
+        let plan = order_book()
+            .swap_ask(
+                deps.as_ref(),
+                &mock_env(),
+                &quote,
+                &base,
+                1_500_000_000_000_000_000u128.into(),
+                &Slippage::Tolerance(Decimal256::percent(1)),
+            )
+            .unwrap();
+        // Both levels are crossed, each paying the 0.2% fee.
+        assert_eq!(
+            plan.offer.amount,
+            Uint256::from(3_507_000_000_000_000_000u128)
+        );
+        assert!(plan.price_impact > Decimal256::zero());
+        exchange.execute(&plan.msgs);
+        assert_eq!(exchange.orders.len(), 1);
+        assert_eq!(
+            exchange.orders[0].order_info.quantity,
+            fp("1500000000000000000")
+        );
+        assert_eq!(exchange.orders[0].order_info.price, fp("3"));
+
+        let err = order_book()
+            .query_reverse_sim(
+                deps.as_ref(),
+                &quote,
+                &base,
+                3_000_000_000_000_000_000u128.into(),
+            )
+            .unwrap_err();
+        assert_eq!(err, NeptuneError::from(SwapError::InsufficientLiquidity));
+    }
+
+    #[test]
+    fn test_subaccount() {
+        let deps = mock_dependencies_with_exchange(exchange());
+        let env = mock_env();
+        let mut exchange = exchange();
+        let order_book = OrderBook {
+            subaccount_nonce: Some(1),
+            ..order_book()
+        };
+
+        let plan = order_book
+            .swap(
+                deps.as_ref(),
+                &env,
+                &denom("base"),
+                &denom("quote"),
+                1_000_000_000_000_000_000u128.into(),
+                &Slippage::Tolerance(Decimal256::percent(1)),
+            )
+            .unwrap();
+        exchange.execute(&plan.msgs);
+        assert_eq!(
+            exchange.orders[0].order_info.subaccount_id,
+            subaccount_id(&env.contract.address, 1)
+        );
+        assert_ne!(
+            exchange.orders[0].order_info.subaccount_id,
+            get_default_subaccount_id_for_checked_address(&env.contract.address)
+        );
+    }
+
+    #[test]
+    fn test_query_ask_amount_at_price() {
+        let deps = mock_dependencies_with_exchange(exchange());
+        let (base, quote) = (denom("base"), denom("quote"));
+
+        let ask_amount = order_book()
+            .query_ask_amount_at_price(deps.as_ref(), &quote, &base, Decimal256::percent(250))
+            .unwrap();
+        assert_eq!(ask_amount, Uint256::from(1_000_000_000_000_000_000u128));
+
+        // Both levels are below the price but the walk is bounded to a single level.
+        let order_book = OrderBook {
+            max_book_levels: Some(1),
+            ..order_book()
+        };
+        let res = order_book
+            .ask_amount_at_price(deps.as_ref(), &quote, Decimal256::percent(1000))
+            .unwrap();
+        assert_eq!(
+            res,
+            AskAmountAtPrice {
+                ask_amount: 1_000_000_000_000_000_000u128.into(),
+                partial: true,
+            }
+        );
+        let err = order_book
+            .query_ask_amount_at_price(deps.as_ref(), &quote, &base, Decimal256::percent(1000))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            NeptuneError::from(SwapError::OrderBookDepthExceeded(1))
+        );
+
+        // The first level is already past the price, so the walk is complete.
+        let res = order_book
+            .ask_amount_at_price(deps.as_ref(), &quote, Decimal256::percent(150))
+            .unwrap();
+        assert_eq!(
+            res,
+            AskAmountAtPrice {
+                ask_amount: Uint256::zero(),
+                partial: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_decimals() {
+        // INJ/USDT, where a price of 10 USDT per INJ is 10^-11 in the smallest units.
+        let mut exchange = exchange();
+        let market = exchange.markets.get_mut(MARKET_ID).unwrap();
+        market.market.min_price_tick_size = fp("0.000000000000001");
+        market.buys = vec![level("0.00000000001", "1000000000000000000")];
+        let deps = mock_dependencies_with_exchange(exchange);
+        let (base, quote) = (denom("base"), denom("quote"));
+
+        // The proceeds are rounded down to the value of a tick of both the price and
+        // the quantity, a single unit of USDT, whatever the decimals declared.
+        for decimals in [None, Some(MarketDecimals { base: 18, quote: 6 })] {
+            let order_book = OrderBook {
+                decimals,
+                ..order_book()
+            };
+            let sell_amount = order_book
+                .query_sim(
+                    deps.as_ref(),
+                    &base,
+                    &quote,
+                    1_000_000_000_000_000_000u128.into(),
+                )
+                .unwrap();
+            assert_eq!(sell_amount, Uint256::from(9_980_039u64));
+        }
+
+        // Order books stored before the decimals were declared remain readable.
+        let stored: OrderBook =
+            from_json(format!(r#"{{"market_id":"{MARKET_ID}"}}"#).as_bytes()).unwrap();
+        assert_eq!(stored, order_book());
+    }
+
+    #[test]
+    fn test_cw20_adapter() {
+        let cw20 = AssetInfo::Token {
+            contract_addr: Addr::unchecked("token"),
+        };
+        let mut exchange = exchange();
+        exchange
+            .markets
+            .get_mut(MARKET_ID)
+            .unwrap()
+            .market
+            .base_denom = "factory/adapter/token".to_string();
+        let deps = mock_dependencies_with_exchange(exchange);
+        let quote = denom("quote");
+        let slippage = Slippage::Tolerance(Decimal256::percent(1));
+
+        let err = order_book()
+            .query_sim(deps.as_ref(), &cw20, &quote, 1u64.into())
+            .unwrap_err();
+        assert_eq!(err, NeptuneError::from(SwapError::InvalidAsset));
+
+        let order_book = OrderBook {
+            cw20_adapter: Some(Addr::unchecked("adapter")),
+            ..order_book()
+        };
+        let sell = order_book
+            .swap(
+                deps.as_ref(),
+                &mock_env(),
+                &cw20,
+                &quote,
+                1_000_000_000_000_000_000u128.into(),
+                &slippage,
+            )
+            .unwrap();
+        assert_eq!(sell.offer.info, cw20);
+        assert_eq!(sell.msgs.len(), 2);
+        assert_eq!(
+            sell.msgs[0],
+            send_assets(
+                &Addr::unchecked("adapter"),
+                1_000_000_000_000_000_000u128.into(),
+                cw20.clone(),
+                Binary::default(),
+            )
+            .unwrap()
+        );
+
+        let buy = order_book
+            .swap(
+                deps.as_ref(),
+                &mock_env(),
+                &quote,
+                &cw20,
+                1_002_000_000_000_000_000u128.into(),
+                &slippage,
+            )
+            .unwrap();
+        assert_eq!(buy.ask.info, cw20);
+        assert_eq!(buy.commission.len(), 1);
+        assert_eq!(buy.commission[0].0, quote);
+        assert_eq!(buy.msgs.len(), 2);
+        assert_eq!(
+            buy.msgs[1],
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "adapter".to_string(),
+                msg: to_json_binary(&Cw20AdapterExecuteMsg::RedeemAndTransfer { recipient: None })
+                    .unwrap(),
+                funds: vec![Coin::new(
+                    500_000_000_000_000_000u128,
+                    "factory/adapter/token"
+                )],
+            })
+        );
+
+        // The proceeds of a sell for a CW20 are only known to reach the minimum received.
+        let mut exchange = exchange();
+        exchange
+            .markets
+            .get_mut(MARKET_ID)
+            .unwrap()
+            .market
+            .quote_denom = "factory/adapter/token".to_string();
+        let deps = mock_dependencies_with_exchange(exchange);
+        let base = denom("base");
+        let sell = order_book
+            .swap(
+                deps.as_ref(),
+                &mock_env(),
+                &base,
+                &cw20,
+                1_000_000_000_000_000_000u128.into(),
+                &slippage,
+            )
+            .unwrap();
+        assert_eq!(sell.ask.info, cw20);
+        assert_eq!(sell.ask.amount, sell.min_receive);
+        assert_eq!(sell.msgs.len(), 2);
+        assert_eq!(
+            sell.msgs[1],
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "adapter".to_string(),
+                msg: to_json_binary(&Cw20AdapterExecuteMsg::RedeemAndTransfer { recipient: None })
+                    .unwrap(),
+                funds: vec![Coin::new(
+                    Uint128::try_from(sell.min_receive).unwrap().u128(),
+                    "factory/adapter/token"
+                )],
+            })
+        );
+    }
+
+    #[test]
+    fn test_limit_orders() {
+        let env = mock_env();
+        let mut exchange = exchange();
+        let spot_market = exchange.markets[MARKET_ID].market.clone();
+        let order = LimitOrder {
+            market_id: spot_market.market_id.clone(),
+            is_buy: false,
+            price: fp("2.5005"),
+            quantity: fp("1000500000000000000"),
+            post_only: true,
+            cid: Some("treasury-1".to_string()),
+        };
+
+        let msg = limit_order(&env, &spot_market, 0, order.clone()).unwrap();
+        exchange.execute(&[msg]);
+        let placed = &exchange.orders[0];
+        assert_eq!(placed.order_type, OrderType::SellPo);
+        // Sells are rounded up to the price tick, quantities down to the quantity tick.
+        assert_eq!(placed.order_info.price, fp("2.501"));
+        assert_eq!(placed.order_info.quantity, fp("1000000000000000000"));
+        assert_eq!(placed.order_info.cid, Some("treasury-1".to_string()));
+
+        let err = limit_order(
+            &env,
+            &spot_market,
+            0,
+            LimitOrder {
+                quantity: fp("1"),
+                ..order.clone()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err, NeptuneError::from(SwapError::OrderTooSmall));
+
+        // Orders are placed from the subaccount with the given nonce.
+        let msg = limit_order(&env, &spot_market, 1, order.clone()).unwrap();
+        exchange.execute(&[msg]);
+        assert_eq!(
+            exchange.orders[1].order_info.subaccount_id,
+            subaccount_id(&env.contract.address, 1)
+        );
+
+        // The placed orders rest on the book, along with one of another trader.
+        let resting = exchange.orders[0].clone();
+        let mut other = resting.clone();
+        other.order_info.subaccount_id = get_default_subaccount_id_for_checked_address(
+            &Addr::unchecked("inj1qgpqyqszqgpqyqszqgpqyqszqgpqyqszf7sl5j"),
+        );
+        exchange.markets.get_mut(MARKET_ID).unwrap().open_orders =
+            vec![other, resting, exchange.orders[1].clone()];
+        let deps = mock_dependencies_with_exchange(exchange.clone());
+
+        let open_orders =
+            query_open_orders(deps.as_ref(), &env, spot_market.market_id.clone(), 0).unwrap();
+        assert_eq!(open_orders.len(), 1);
+        assert_eq!(open_orders[0].quantity, fp("1000000000000000000"));
+
+        let msgs =
+            cancel_all_orders(deps.as_ref(), &env, spot_market.market_id.clone(), 1).unwrap();
+        exchange.execute(&msgs);
+        assert_eq!(exchange.cancelled, vec![mock_exchange::order_hash(2)]);
+    }
+
+    #[test]
+    fn test_market_not_found() {
+        let deps = mock_dependencies_with_exchange(MockExchange::default());
+        let err = order_book()
+            .query_sim(deps.as_ref(), &denom("quote"), &denom("base"), 1u64.into())
+            .unwrap_err();
+        assert_eq!(err, NeptuneError::from(SwapError::SpotMarketNotFound));
+    }
+
+    #[test]
+    fn test_typed_errors() {
+        let exchange = exchange();
+        let mut deps = mock_dependencies_with_exchange(exchange.clone());
+        let (base, quote) = (denom("base"), denom("quote"));
+
+        let err = order_book()
+            .query_ask_amount_at_price(deps.as_ref(), &base, &quote, Decimal256::zero())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            NeptuneError::from(SwapError::InvalidPrice(Decimal256::zero()))
+        );
+
+        // The exchange fails to answer the fee multiplier query.
+        deps.querier = deps
+            .querier
+            .with_custom_handler(move |query| match query.query_data {
+                InjectiveQuery::MarketAtomicExecutionFeeMultiplier { .. } => {
+                    SystemResult::Err(SystemError::UnsupportedRequest {
+                        kind: "fee multiplier".to_string(),
+                    })
+                }
+                _ => exchange.handle(query),
+            });
+        let err = order_book()
+            .query_sim(deps.as_ref(), &quote, &base, 1u64.into())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            NeptuneError::from(SwapError::FeeMultiplierNotFound(MARKET_ID.to_string()))
+        );
+    }
 }
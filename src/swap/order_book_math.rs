@@ -0,0 +1,516 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::Decimal256;
+use injective_cosmwasm::{exchange::types::PriceLevel, SpotMarket};
+use injective_math::FPDecimal;
+
+use crate::{error::NeptuneResult, injective::try_into_decimal_256};
+
+use super::error::SwapError;
+
+/// The decimals of the base and quote assets of a spot market.
+/// Prices and quantities on the exchange are in the smallest units of each asset.
+#[cw_serde]
+#[derive(Copy)]
+pub struct MarketDecimals {
+    pub base: u8,
+    pub quote: u8,
+}
+
+impl MarketDecimals {
+    /// Converts a price in whole assets to the exchange's price in the smallest units.
+    pub fn to_chain_price(&self, price: FPDecimal) -> FPDecimal {
+        scale(price, self.base, self.quote)
+    }
+
+    /// Converts the exchange's price in the smallest units to a price in whole assets.
+    pub fn to_human_price(&self, price: FPDecimal) -> FPDecimal {
+        scale(price, self.quote, self.base)
+    }
+}
+
+/// The parameters of a spot market which determine how market orders are filled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarketParams {
+    pub min_price_tick_size: FPDecimal,
+    pub min_quantity_tick_size: FPDecimal,
+    /// The fee rate paid by atomic market orders.
+    pub fee_rate: FPDecimal,
+}
+
+impl MarketParams {
+    /// Derives the parameters of a spot market, atomic orders pay the taker fee
+    /// scaled by the market's atomic execution fee multiplier.
+    /// The relayer's share of the fee is returned to the contract as it is its own fee recipient.
+    pub fn new(spot_market: &SpotMarket, atomic_execution_fee_multiplier: FPDecimal) -> Self {
+        Self {
+            min_price_tick_size: spot_market.min_price_tick_size,
+            min_quantity_tick_size: spot_market.min_quantity_tick_size,
+            fee_rate: atomic_execution_fee_multiplier
+                * spot_market.taker_fee_rate
+                * (FPDecimal::ONE - spot_market.relayer_fee_share_rate),
+        }
+    }
+
+    /// The tick to which simulated amounts of the quote asset are rounded down.
+    /// Orders fill at multiples of the price tick for multiples of the quantity tick,
+    /// so the value of a fill is a multiple of their product.
+    pub fn quote_tick_size(&self) -> FPDecimal {
+        self.min_price_tick_size * self.min_quantity_tick_size
+    }
+}
+
+/// The simulated fill of a market order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    /// The quantity of the base asset bought or sold.
+    pub quantity: FPDecimal,
+    /// The amount of the quote asset paid including fees, or received net of fees.
+    pub quote_amount: FPDecimal,
+    /// The value of the filled quantity at the prices of the crossed levels.
+    pub notional: FPDecimal,
+    /// The fees paid, in the quote asset.
+    pub fee: FPDecimal,
+    /// The notional divided by the filled quantity, none when nothing is filled.
+    pub average_price: Option<FPDecimal>,
+    /// The price of the last level crossed, none when the book is empty.
+    pub worst_price: Option<FPDecimal>,
+    /// The deviation of the average price from the best price.
+    pub price_impact: Decimal256,
+}
+
+/// Simulates buying as much of the base asset as `offer_amount` of the quote asset allows,
+/// walking the sell levels from the best price. The quantity is rounded down to the tick size.
+pub fn buy_with_quote(
+    params: &MarketParams,
+    sells: &[PriceLevel],
+    offer_amount: FPDecimal,
+) -> NeptuneResult<Fill> {
+    let mut remaining_offer_amount = offer_amount; // quote
+    let mut quantity = FPDecimal::ZERO; // base
+    let mut worst_price = None;
+    for sell_order in sells {
+        worst_price = Some(sell_order.p);
+        let sell_order_quote_amount = apply_fee(sell_order.q * sell_order.p, params.fee_rate);
+        if remaining_offer_amount > sell_order_quote_amount {
+            quantity += sell_order.q;
+            remaining_offer_amount -= sell_order_quote_amount;
+        } else {
+            // `sell_order.p` cannot be zero, no need to check.
+            quantity += (remaining_offer_amount
+                / ((FPDecimal::ONE + params.fee_rate) * sell_order.p))
+                .int();
+            break;
+        }
+    }
+    let quantity = tick_round_down(quantity, params.min_quantity_tick_size);
+    let quote_amount = buy_cost(params, sells, quantity).0;
+    fill(params, sells, true, quantity, quote_amount, worst_price)
+}
+
+/// Simulates buying `quantity` of the base asset rounded up to the tick size,
+/// erroring on insufficient liquidity.
+pub fn buy_quantity(
+    params: &MarketParams,
+    sells: &[PriceLevel],
+    quantity: FPDecimal,
+) -> NeptuneResult<Fill> {
+    let quantity = tick_round_up(quantity, params.min_quantity_tick_size);
+    let (quote_amount, remaining_quantity, worst_price) = buy_cost(params, sells, quantity);
+    if !remaining_quantity.is_zero() {
+        return Err(SwapError::InsufficientLiquidity.into());
+    }
+    fill(params, sells, true, quantity, quote_amount, worst_price)
+}
+
+/// Simulates selling `quantity` of the base asset rounded down to the tick size,
+/// walking the buy levels from the best price.
+pub fn sell_quantity(
+    params: &MarketParams,
+    buys: &[PriceLevel],
+    quantity: FPDecimal,
+) -> NeptuneResult<Fill> {
+    let quantity = tick_round_down(quantity, params.min_quantity_tick_size);
+    let mut quote_amount = FPDecimal::ZERO;
+    let mut worst_price = None;
+    let mut remaining_quantity = quantity;
+    for buy_order in buys {
+        worst_price = Some(buy_order.p);
+        if buy_order.q > remaining_quantity {
+            quote_amount +=
+                ((remaining_quantity * buy_order.p) / (FPDecimal::ONE + params.fee_rate)).int();
+            break;
+        } else {
+            quote_amount +=
+                ((buy_order.q * buy_order.p) / (FPDecimal::ONE + params.fee_rate)).int();
+            remaining_quantity -= buy_order.q;
+        }
+    }
+    let quote_amount = tick_round_down(quote_amount, params.quote_tick_size());
+    fill(params, buys, false, quantity, quote_amount, worst_price)
+}
+
+/// Simulates selling the smallest quantity of the base asset, a multiple of the tick size,
+/// for which [`sell_quantity`] receives at least `ask_amount` of the quote asset.
+/// Errors on insufficient liquidity.
+pub fn sell_for_quote(
+    params: &MarketParams,
+    buys: &[PriceLevel],
+    ask_amount: FPDecimal,
+) -> NeptuneResult<Fill> {
+    // The amount received is rounded down to the quote tick size.
+    let mut remaining_ask_amount = tick_round_up(ask_amount, params.quote_tick_size()); // quote
+    let mut quantity = FPDecimal::ZERO; // base
+    for buy_order in buys {
+        if remaining_ask_amount.is_zero() {
+            break;
+        }
+        let buy_order_quote_amount =
+            ((buy_order.q * buy_order.p) / (FPDecimal::ONE + params.fee_rate)).int();
+        if remaining_ask_amount >= buy_order_quote_amount {
+            quantity += buy_order.q;
+            remaining_ask_amount -= buy_order_quote_amount;
+        } else {
+            // `buy_order.p` cannot be zero, no need to check.
+            let mut fill_quantity = tick_round_up(
+                remaining_ask_amount * (FPDecimal::ONE + params.fee_rate) / buy_order.p,
+                params.min_quantity_tick_size,
+            );
+            // Guards against the rounding of the division.
+            if ((fill_quantity * buy_order.p) / (FPDecimal::ONE + params.fee_rate)).int()
+                < remaining_ask_amount
+            {
+                fill_quantity += params.min_quantity_tick_size;
+            }
+            quantity += fill_quantity;
+            remaining_ask_amount = FPDecimal::ZERO;
+        }
+    }
+    if !remaining_ask_amount.is_zero() {
+        return Err(SwapError::InsufficientLiquidity.into());
+    }
+    sell_quantity(params, buys, quantity)
+}
+
+/// Returns the quantity of the base asset, rounded down to the tick size,
+/// offered by the sell levels at a price of at most `price`.
+pub fn buy_quantity_at_price(
+    params: &MarketParams,
+    sells: &[PriceLevel],
+    price: FPDecimal,
+) -> FPDecimal {
+    let quantity = sells
+        .iter()
+        .take_while(|sell_order| sell_order.p <= price)
+        .fold(FPDecimal::ZERO, |quantity, sell_order| {
+            quantity + sell_order.q
+        });
+    tick_round_down(quantity, params.min_quantity_tick_size)
+}
+
+/// Returns the amount of the quote asset received net of fees, rounded down to the quote tick size,
+/// from selling to the buy levels at a price of at least `price`, as [`sell_quantity`] does.
+pub fn sell_amount_at_price(
+    params: &MarketParams,
+    buys: &[PriceLevel],
+    price: FPDecimal,
+) -> FPDecimal {
+    let amount = buys
+        .iter()
+        .take_while(|buy_order| buy_order.p >= price)
+        .fold(FPDecimal::ZERO, |amount, buy_order| {
+            amount + ((buy_order.q * buy_order.p) / (FPDecimal::ONE + params.fee_rate)).int()
+        });
+    tick_round_down(amount, params.quote_tick_size())
+}
+
+/// Returns the amount of the quote asset required to buy `quantity` including fees,
+/// the quantity left unfilled and the worst price crossed.
+fn buy_cost(
+    params: &MarketParams,
+    sells: &[PriceLevel],
+    quantity: FPDecimal,
+) -> (FPDecimal, FPDecimal, Option<FPDecimal>) {
+    let mut offer_amount = FPDecimal::ZERO;
+    let mut worst_price = None;
+    let mut remaining_quantity = quantity;
+    for sell_order in sells {
+        worst_price = Some(sell_order.p);
+        if sell_order.q > remaining_quantity {
+            offer_amount += apply_fee(remaining_quantity * sell_order.p, params.fee_rate);
+            remaining_quantity = FPDecimal::ZERO;
+            break;
+        } else {
+            offer_amount += apply_fee(sell_order.q * sell_order.p, params.fee_rate);
+            remaining_quantity -= sell_order.q;
+        }
+    }
+    (offer_amount, remaining_quantity, worst_price)
+}
+
+/// Completes a fill with the notional value of `quantity` against the levels,
+/// its average price and price impact relative to the best price.
+fn fill(
+    params: &MarketParams,
+    levels: &[PriceLevel],
+    is_buy: bool,
+    quantity: FPDecimal,
+    quote_amount: FPDecimal,
+    worst_price: Option<FPDecimal>,
+) -> NeptuneResult<Fill> {
+    let mut notional = FPDecimal::ZERO;
+    let mut remaining_quantity = quantity;
+    for level in levels {
+        if remaining_quantity.is_zero() {
+            break;
+        }
+        let fill_quantity = level.q.min(remaining_quantity);
+        notional += fill_quantity * level.p;
+        remaining_quantity -= fill_quantity;
+    }
+    let filled_quantity = quantity - remaining_quantity;
+    let average_price = (!filled_quantity.is_zero()).then(|| notional / filled_quantity);
+    let price_impact = match (levels.first(), average_price) {
+        (Some(best), Some(average_price)) => price_impact(best.p, average_price, is_buy)?,
+        _ => Decimal256::zero(),
+    };
+    let fee = if is_buy {
+        notional * params.fee_rate
+    } else {
+        (notional - quote_amount).max(FPDecimal::ZERO)
+    };
+    Ok(Fill {
+        quantity,
+        quote_amount,
+        notional,
+        fee,
+        average_price,
+        worst_price,
+        price_impact,
+    })
+}
+
+/// The deviation of the average price of a fill from the best price, floored at zero.
+pub fn price_impact(
+    best_price: FPDecimal,
+    average_price: FPDecimal,
+    is_buy: bool,
+) -> NeptuneResult<Decimal256> {
+    let price_impact = if is_buy {
+        FPDecimal::ONE - best_price / average_price
+    } else {
+        FPDecimal::ONE - average_price / best_price
+    };
+    try_into_decimal_256(price_impact.max(FPDecimal::ZERO))
+        .map_err(|_| SwapError::InvalidAmount(price_impact.to_string()).into())
+}
+
+/// Multiplies the integer part of `value` by one plus the fee, rounding up.
+pub fn apply_fee(value: FPDecimal, fee: FPDecimal) -> FPDecimal {
+    let res = value.int() * (FPDecimal::ONE + fee);
+    if res.is_int() {
+        res
+    } else {
+        (res + FPDecimal::ONE).int()
+    }
+}
+
+/// Multiplies `value` by `10^(to - from)`.
+fn scale(value: FPDecimal, from: u8, to: u8) -> FPDecimal {
+    if to >= from {
+        value * FPDecimal::from(10u128.pow((to - from).into()))
+    } else {
+        value / FPDecimal::from(10u128.pow((from - to).into()))
+    }
+}
+
+pub fn tick_round_up(value: FPDecimal, tick_size: FPDecimal) -> FPDecimal {
+    let tick_num = value / tick_size;
+    let tick_num = if tick_num.is_int() {
+        tick_num
+    } else {
+        (tick_num + FPDecimal::ONE).int() // no ceiling function
+    };
+    tick_num * tick_size
+}
+
+pub fn tick_round_down(value: FPDecimal, tick_size: FPDecimal) -> FPDecimal {
+    let tick_num = (value / tick_size).int();
+    tick_num * tick_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap::mock_exchange::fp;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn params() -> MarketParams {
+        MarketParams {
+            min_price_tick_size: fp("0.001"),
+            min_quantity_tick_size: fp("1000000000000000"),
+            fee_rate: fp("0.002"),
+        }
+    }
+
+    fn levels(levels: &[(&str, u128)]) -> Vec<PriceLevel> {
+        levels
+            .iter()
+            .map(|(p, q)| PriceLevel {
+                p: fp(p),
+                q: FPDecimal::from(*q),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_buy() {
+        let sells = levels(&[("2", E18), ("3", E18)]);
+
+        let fill = buy_with_quote(&params(), &sells, FPDecimal::from(1_002 * E18 / 1_000)).unwrap();
+        assert_eq!(fill.quantity, FPDecimal::from(E18 / 2));
+        assert_eq!(fill.quote_amount, FPDecimal::from(1_002 * E18 / 1_000));
+        assert_eq!(fill.notional, FPDecimal::from(E18));
+        assert_eq!(fill.fee, FPDecimal::from(2 * E18 / 1_000));
+        assert_eq!(fill.average_price, Some(fp("2")));
+        assert_eq!(fill.worst_price, Some(fp("2")));
+        assert_eq!(fill.price_impact, Decimal256::zero());
+
+        let fill = buy_quantity(&params(), &sells, FPDecimal::from(3 * E18 / 2)).unwrap();
+        assert_eq!(fill.quote_amount, FPDecimal::from(3_507 * E18 / 1_000));
+        assert_eq!(fill.notional, FPDecimal::from(7 * E18 / 2));
+        assert_eq!(fill.worst_price, Some(fp("3")));
+        // 1 - 2 / (7 / 3) = 1 / 7
+        assert!(fill.price_impact > Decimal256::permille(142));
+        assert!(fill.price_impact < Decimal256::permille(143));
+
+        assert!(buy_quantity(&params(), &sells, FPDecimal::from(3 * E18)).is_err());
+        assert_eq!(
+            buy_quantity_at_price(&params(), &sells, fp("2.5")),
+            FPDecimal::from(E18)
+        );
+    }
+
+    #[test]
+    fn test_sell() {
+        let buys = levels(&[("1.5", E18), ("1", E18)]);
+
+        // 1.5 divided by 1.002, rounded down to the quote tick of 0.001 * 10^15.
+        let fill = sell_quantity(&params(), &buys, FPDecimal::from(E18)).unwrap();
+        assert_eq!(
+            fill.quote_amount,
+            FPDecimal::from(1_497_005_000_000_000_000u128)
+        );
+        assert_eq!(fill.notional, FPDecimal::from(3 * E18 / 2));
+        assert_eq!(fill.fee, FPDecimal::from(2_995_000_000_000_000u128));
+        assert_eq!(fill.worst_price, Some(fp("1.5")));
+
+        let fill = sell_for_quote(&params(), &buys, fill.quote_amount).unwrap();
+        assert_eq!(fill.quantity, FPDecimal::from(E18));
+        assert_eq!(
+            fill.quote_amount,
+            FPDecimal::from(1_497_005_000_000_000_000u128)
+        );
+        // A quantity tick less does not receive the amount asked for.
+        let fill = sell_for_quote(&params(), &buys, FPDecimal::from(E18)).unwrap();
+        assert!(fill.quote_amount >= FPDecimal::from(E18));
+        assert!(
+            sell_quantity(
+                &params(),
+                &buys,
+                fill.quantity - params().min_quantity_tick_size
+            )
+            .unwrap()
+            .quote_amount
+                < FPDecimal::from(E18)
+        );
+        assert_eq!(
+            sell_amount_at_price(&params(), &buys, fp("1.5")),
+            FPDecimal::from(1_497_005_000_000_000_000u128)
+        );
+
+        assert!(sell_for_quote(&params(), &buys, FPDecimal::from(3 * E18)).is_err());
+        assert_eq!(
+            sell_quantity(&params(), &[], FPDecimal::from(E18)),
+            Ok(Fill {
+                quantity: FPDecimal::from(E18),
+                quote_amount: FPDecimal::ZERO,
+                notional: FPDecimal::ZERO,
+                fee: FPDecimal::ZERO,
+                average_price: None,
+                worst_price: None,
+                price_impact: Decimal256::zero(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_decimals_18_6() {
+        // INJ/USDT, a price of 10 USDT per INJ is 10^-11 in the smallest units.
+        let decimals = MarketDecimals { base: 18, quote: 6 };
+        let params = MarketParams {
+            min_price_tick_size: decimals.to_chain_price(fp("0.001")),
+            min_quantity_tick_size: fp("1000000000000000"),
+            fee_rate: fp("0.002"),
+        };
+        assert_eq!(decimals.to_chain_price(fp("10")), fp("0.00000000001"));
+        assert_eq!(decimals.to_human_price(fp("0.00000000001")), fp("10"));
+        let buys = vec![
+            PriceLevel {
+                p: decimals.to_chain_price(fp("10")),
+                q: FPDecimal::from(E18),
+            },
+            PriceLevel {
+                p: decimals.to_chain_price(fp("9")),
+                q: FPDecimal::from(E18),
+            },
+        ];
+
+        // 10 USDT divided by 1.002, the quote tick of 10^-15 * 10^15 is a single unit.
+        assert_eq!(params.quote_tick_size(), FPDecimal::ONE);
+        let fill = sell_quantity(&params, &buys, FPDecimal::from(E18)).unwrap();
+        assert_eq!(fill.quote_amount, FPDecimal::from(9_980_039u128));
+        assert_eq!(fill.worst_price, Some(fp("0.00000000001")));
+
+        let fill = sell_for_quote(&params, &buys, FPDecimal::from(9_980_039u128)).unwrap();
+        assert_eq!(fill.quantity, FPDecimal::from(E18));
+        assert_eq!(fill.quote_amount, FPDecimal::from(9_980_039u128));
+
+        // Only the first level is bid at 9.5 USDT, it is received net of fees as when sold.
+        assert_eq!(
+            sell_amount_at_price(&params, &buys, decimals.to_chain_price(fp("9.5"))),
+            FPDecimal::from(9_980_039u128)
+        );
+    }
+
+    #[test]
+    fn test_decimals_6_18() {
+        // ATOM/INJ, a price of 2.5 INJ per ATOM is 2.5 * 10^12 in the smallest units.
+        let decimals = MarketDecimals { base: 6, quote: 18 };
+        let params = MarketParams {
+            min_price_tick_size: decimals.to_chain_price(fp("0.0001")),
+            min_quantity_tick_size: fp("1000"),
+            fee_rate: fp("0.002"),
+        };
+        assert_eq!(decimals.to_chain_price(fp("2.5")), fp("2500000000000"));
+        let sells = vec![PriceLevel {
+            p: decimals.to_chain_price(fp("2.5")),
+            q: FPDecimal::from(1_000_000u128),
+        }];
+        let buys = vec![PriceLevel {
+            p: decimals.to_chain_price(fp("2")),
+            q: FPDecimal::from(1_000_000u128),
+        }];
+
+        let fill = buy_with_quote(&params, &sells, FPDecimal::from(1_002 * E18 / 1_000)).unwrap();
+        assert_eq!(fill.quantity, FPDecimal::from(400_000u128));
+        assert_eq!(fill.quote_amount, FPDecimal::from(1_002 * E18 / 1_000));
+
+        // 2 INJ divided by 1.002, rounded down to the quote tick of 10^8 * 10^3, or 10^-7 INJ.
+        let fill = sell_quantity(&params, &buys, FPDecimal::from(1_000_000u128)).unwrap();
+        assert_eq!(
+            fill.quote_amount,
+            FPDecimal::from(1_996_007_900_000_000_000u128)
+        );
+    }
+}
@@ -0,0 +1,549 @@
+use std::str::FromStr;
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{CosmosMsg, Decimal256, Deps, Env, QueryRequest, Uint256};
+use osmosis_std::types::{
+    cosmos::base::v1beta1::Coin,
+    osmosis::poolmanager::v1beta1::{
+        EstimateSwapExactAmountInRequest, EstimateSwapExactAmountInResponse,
+        EstimateSwapExactAmountOutRequest, EstimateSwapExactAmountOutResponse,
+        MsgSwapExactAmountIn, MsgSwapExactAmountOut, SwapAmountInRoute, SwapAmountOutRoute,
+    },
+};
+
+use crate::{
+    asset::{AssetAmount, AssetInfo, AssetMap},
+    error::NeptuneResult,
+    msg_wrapper::MsgWrapper,
+    query_wrapper::QueryWrapper,
+};
+
+use super::{
+    error::SwapError, plan::SwapPlan, search_ask_amount_at_price_by_sim, slippage::Slippage, Swap,
+};
+
+/// A route through Osmosis poolmanager pools.
+#[cw_serde]
+pub struct OsmosisRoute {
+    /// The denoms along the route, from one end of the pair to the other.
+    /// The route can be traded in both directions.
+    pub denoms: Vec<String>,
+    /// The pool trading each consecutive pair of denoms.
+    pub pool_ids: Vec<u64>,
+}
+
+impl OsmosisRoute {
+    /// Returns the pools and the denoms along the route in the direction of the swap.
+    fn route(
+        &self,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+    ) -> NeptuneResult<(Vec<u64>, Vec<String>)> {
+        let (
+            AssetInfo::NativeToken { denom: offer_denom },
+            AssetInfo::NativeToken { denom: ask_denom },
+        ) = (offer_asset, ask_asset)
+        else {
+            return Err(SwapError::InvalidAsset.into());
+        };
+        if self.pool_ids.is_empty() || self.denoms.len() != self.pool_ids.len() + 1 {
+            return Err(SwapError::InvalidPool.into());
+        }
+        match (self.denoms.first(), self.denoms.last()) {
+            (Some(first), Some(last)) if first == offer_denom && last == ask_denom => {
+                Ok((self.pool_ids.clone(), self.denoms.clone()))
+            }
+            (Some(first), Some(last)) if first == ask_denom && last == offer_denom => Ok((
+                self.pool_ids.iter().rev().copied().collect(),
+                self.denoms.iter().rev().cloned().collect(),
+            )),
+            _ => Err(SwapError::InvalidAsset.into()),
+        }
+    }
+
+    fn amount_in_routes(
+        &self,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+    ) -> NeptuneResult<Vec<SwapAmountInRoute>> {
+        let (pool_ids, denoms) = self.route(offer_asset, ask_asset)?;
+        Ok(pool_ids
+            .into_iter()
+            .zip(denoms.into_iter().skip(1))
+            .map(|(pool_id, token_out_denom)| SwapAmountInRoute {
+                pool_id,
+                token_out_denom,
+            })
+            .collect())
+    }
+
+    fn amount_out_routes(
+        &self,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+    ) -> NeptuneResult<Vec<SwapAmountOutRoute>> {
+        let (pool_ids, denoms) = self.route(offer_asset, ask_asset)?;
+        Ok(pool_ids
+            .into_iter()
+            .zip(denoms)
+            .map(|(pool_id, token_in_denom)| SwapAmountOutRoute {
+                pool_id,
+                token_in_denom,
+            })
+            .collect())
+    }
+}
+
+impl Swap for OsmosisRoute {
+    fn swap(
+        &self,
+        deps: Deps<QueryWrapper>,
+        env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let return_amount = self.query_sim(deps, offer_asset, ask_asset, offer_amount)?;
+        if return_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
+        }
+        let min_receive = slippage.assert(offer_amount, return_amount)?;
+        let msg = MsgSwapExactAmountIn {
+            sender: env.contract.address.to_string(),
+            routes: self.amount_in_routes(offer_asset, ask_asset)?,
+            token_in: Some(coin(offer_asset, offer_amount)?),
+            token_out_min_amount: min_receive.to_string(),
+        };
+        Ok(route_plan(
+            CosmosMsg::Stargate {
+                type_url: MsgSwapExactAmountIn::TYPE_URL.to_string(),
+                value: msg.into(),
+            },
+            (offer_asset, offer_amount),
+            (ask_asset, return_amount),
+            min_receive,
+        ))
+    }
+
+    /// Swaps for exactly the ask amount, the slippage policy bounds the amount offered.
+    fn swap_ask(
+        &self,
+        deps: Deps<QueryWrapper>,
+        env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let offer_amount = self.query_reverse_sim(deps, offer_asset, ask_asset, ask_amount)?;
+        if offer_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
+        }
+        let max_offer_amount = slippage.max_offer_amount(offer_amount, ask_amount)?;
+        let msg = MsgSwapExactAmountOut {
+            sender: env.contract.address.to_string(),
+            routes: self.amount_out_routes(offer_asset, ask_asset)?,
+            token_in_max_amount: max_offer_amount.to_string(),
+            token_out: Some(coin(ask_asset, ask_amount)?),
+        };
+        Ok(route_plan(
+            CosmosMsg::Stargate {
+                type_url: MsgSwapExactAmountOut::TYPE_URL.to_string(),
+                value: msg.into(),
+            },
+            (offer_asset, offer_amount),
+            (ask_asset, ask_amount),
+            ask_amount,
+        ))
+    }
+
+    fn query_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        if offer_amount.is_zero() {
+            return Ok(Uint256::zero());
+        }
+        let routes = self.amount_in_routes(offer_asset, ask_asset)?;
+        // The pool id is deprecated in favour of the routes but still required.
+        #[allow(deprecated)]
+        let request = EstimateSwapExactAmountInRequest {
+            pool_id: routes[0].pool_id,
+            token_in: coin_string(offer_asset, offer_amount)?,
+            routes,
+        };
+        let res: EstimateSwapExactAmountInResponse =
+            deps.querier.query(&QueryRequest::Stargate {
+                path: EstimateSwapExactAmountInRequest::PATH.to_string(),
+                data: request.into(),
+            })?;
+        Ok(Uint256::from_str(&res.token_out_amount)?)
+    }
+
+    /// Returns the amount the poolmanager requires to swap for exactly the ask amount.
+    fn query_reverse_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        if ask_amount.is_zero() {
+            return Ok(Uint256::zero());
+        }
+        let routes = self.amount_out_routes(offer_asset, ask_asset)?;
+        #[allow(deprecated)]
+        let request = EstimateSwapExactAmountOutRequest {
+            pool_id: routes[0].pool_id,
+            token_out: coin_string(ask_asset, ask_amount)?,
+            routes,
+        };
+        let res: EstimateSwapExactAmountOutResponse =
+            deps.querier.query(&QueryRequest::Stargate {
+                path: EstimateSwapExactAmountOutRequest::PATH.to_string(),
+                data: request.into(),
+            })?;
+        Ok(Uint256::from_str(&res.token_in_amount)?)
+    }
+
+    fn query_ask_amount_at_price(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        max_ratio: Decimal256,
+    ) -> NeptuneResult<Uint256> {
+        search_ask_amount_at_price_by_sim(self, deps, offer_asset, ask_asset, max_ratio)
+    }
+}
+
+/// Creates the plan of a poolmanager swap.
+/// The poolmanager does not report the commission or the spread of its pools.
+fn route_plan(
+    msg: CosmosMsg<MsgWrapper>,
+    offer: (&AssetInfo, Uint256),
+    ask: (&AssetInfo, Uint256),
+    min_receive: Uint256,
+) -> SwapPlan {
+    SwapPlan {
+        msgs: vec![msg],
+        offer: AssetAmount {
+            info: offer.0.clone(),
+            amount: offer.1,
+        },
+        ask: AssetAmount {
+            info: ask.0.clone(),
+            amount: ask.1,
+        },
+        commission: AssetMap::new(),
+        price_impact: Decimal256::zero(),
+        min_receive,
+        surplus: Uint256::zero(),
+        venue: None,
+        legs: vec![],
+    }
+}
+
+fn coin(asset: &AssetInfo, amount: Uint256) -> NeptuneResult<Coin> {
+    let AssetInfo::NativeToken { denom } = asset else {
+        return Err(SwapError::InvalidAsset.into());
+    };
+    Ok(Coin {
+        denom: denom.clone(),
+        amount: amount.to_string(),
+    })
+}
+
+/// Formats an amount the way the poolmanager estimate queries expect, e.g. `100uosmo`.
+fn coin_string(asset: &AssetInfo, amount: Uint256) -> NeptuneResult<String> {
+    let coin = coin(asset, amount)?;
+    Ok(format!("{}{}", coin.amount, coin.denom))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    use cosmwasm_std::{
+        from_json,
+        testing::{mock_env, MockApi, MockStorage},
+        to_json_binary, Binary, ContractResult, OwnedDeps, Querier, QuerierResult, StdResult,
+        SystemError, SystemResult,
+    };
+
+    use super::*;
+
+    /// Answers the poolmanager's swap estimates as if every pool returned
+    /// twice the amount offered, keeping a hundredth of it as fees.
+    struct MockPoolManager;
+
+    impl Querier for MockPoolManager {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let Ok(QueryRequest::Stargate { path, data }) =
+                from_json::<QueryRequest<QueryWrapper>>(bin_request)
+            else {
+                return SystemResult::Err(SystemError::UnsupportedRequest {
+                    kind: "non-stargate query".to_string(),
+                });
+            };
+            let res = match path.as_str() {
+                EstimateSwapExactAmountInRequest::PATH => estimate_amount_in(data),
+                EstimateSwapExactAmountOutRequest::PATH => estimate_amount_out(data),
+                _ => {
+                    return SystemResult::Err(SystemError::UnsupportedRequest { kind: path });
+                }
+            };
+            SystemResult::Ok(ContractResult::Ok(res.unwrap()))
+        }
+    }
+
+    #[allow(deprecated)]
+    fn estimate_amount_in(data: Binary) -> StdResult<Binary> {
+        let request = EstimateSwapExactAmountInRequest::try_from(data)?;
+        assert_eq!(request.pool_id, request.routes[0].pool_id);
+        let amount = request
+            .routes
+            .iter()
+            .fold(parse_amount(&request.token_in), |amount, _| {
+                amount.multiply_ratio(198u8, 100u8)
+            });
+        to_json_binary(&EstimateSwapExactAmountInResponse {
+            token_out_amount: amount.to_string(),
+        })
+    }
+
+    #[allow(deprecated)]
+    fn estimate_amount_out(data: Binary) -> StdResult<Binary> {
+        let request = EstimateSwapExactAmountOutRequest::try_from(data)?;
+        assert_eq!(request.pool_id, request.routes[0].pool_id);
+        let amount = request
+            .routes
+            .iter()
+            .fold(parse_amount(&request.token_out), |amount, _| {
+                amount.multiply_ratio(100u8, 198u8) + Uint256::one()
+            });
+        to_json_binary(&EstimateSwapExactAmountOutResponse {
+            token_in_amount: amount.to_string(),
+        })
+    }
+
+    /// Parses the amount of a coin formatted as `100uosmo`.
+    fn parse_amount(coin: &str) -> Uint256 {
+        let digits = coin.find(|c: char| !c.is_ascii_digit()).unwrap();
+        Uint256::from_str(&coin[..digits]).unwrap()
+    }
+
+    fn mock_dependencies() -> OwnedDeps<MockStorage, MockApi, MockPoolManager, QueryWrapper> {
+        OwnedDeps {
+            storage: MockStorage::default(),
+            api: MockApi::default(),
+            querier: MockPoolManager,
+            custom_query_type: PhantomData,
+        }
+    }
+
+    fn route() -> OsmosisRoute {
+        OsmosisRoute {
+            denoms: vec![
+                "uatom".to_string(),
+                "uosmo".to_string(),
+                "uusdc".to_string(),
+            ],
+            pool_ids: vec![1, 678],
+        }
+    }
+
+    fn native(denom: &str) -> AssetInfo {
+        AssetInfo::NativeToken {
+            denom: denom.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_routes() {
+        let route = route();
+        assert_eq!(
+            route
+                .amount_in_routes(&native("uusdc"), &native("uatom"))
+                .unwrap(),
+            vec![
+                SwapAmountInRoute {
+                    pool_id: 678,
+                    token_out_denom: "uosmo".to_string()
+                },
+                SwapAmountInRoute {
+                    pool_id: 1,
+                    token_out_denom: "uatom".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            route
+                .amount_out_routes(&native("uatom"), &native("uusdc"))
+                .unwrap(),
+            vec![
+                SwapAmountOutRoute {
+                    pool_id: 1,
+                    token_in_denom: "uatom".to_string()
+                },
+                SwapAmountOutRoute {
+                    pool_id: 678,
+                    token_in_denom: "uosmo".to_string()
+                },
+            ]
+        );
+        assert!(route
+            .amount_in_routes(&native("uatom"), &native("uosmo"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_query_sim() {
+        let deps = mock_dependencies();
+        let route = route();
+        let (atom, usdc) = (native("uatom"), native("uusdc"));
+
+        // Each of the two pools returns 1.98 times the amount offered.
+        assert_eq!(
+            route
+                .query_sim(deps.as_ref(), &atom, &usdc, 1_000u64.into())
+                .unwrap(),
+            Uint256::from(3_920u64)
+        );
+        assert_eq!(
+            route
+                .query_reverse_sim(deps.as_ref(), &atom, &usdc, 3_920u64.into())
+                .unwrap(),
+            Uint256::from(1_001u64)
+        );
+        // Zero amounts are not estimated.
+        assert_eq!(
+            route
+                .query_sim(deps.as_ref(), &atom, &usdc, Uint256::zero())
+                .unwrap(),
+            Uint256::zero()
+        );
+        assert_eq!(
+            route
+                .query_reverse_sim(deps.as_ref(), &atom, &usdc, Uint256::zero())
+                .unwrap(),
+            Uint256::zero()
+        );
+    }
+
+    #[test]
+    fn test_swap() {
+        let deps = mock_dependencies();
+        let env = mock_env();
+        let route = route();
+        let (atom, usdc) = (native("uatom"), native("uusdc"));
+
+        let plan = route
+            .swap(
+                deps.as_ref(),
+                &env,
+                &atom,
+                &usdc,
+                1_000u64.into(),
+                &Slippage::Tolerance(Decimal256::percent(1)),
+            )
+            .unwrap();
+        assert_eq!(plan.offer.amount, Uint256::from(1_000u64));
+        assert_eq!(plan.ask.amount, Uint256::from(3_920u64));
+        assert_eq!(plan.min_receive, Uint256::from(3_880u64));
+        assert_eq!(
+            plan.msgs,
+            vec![CosmosMsg::Stargate {
+                type_url: MsgSwapExactAmountIn::TYPE_URL.to_string(),
+                value: MsgSwapExactAmountIn {
+                    sender: env.contract.address.to_string(),
+                    routes: vec![
+                        SwapAmountInRoute {
+                            pool_id: 1,
+                            token_out_denom: "uosmo".to_string()
+                        },
+                        SwapAmountInRoute {
+                            pool_id: 678,
+                            token_out_denom: "uusdc".to_string()
+                        },
+                    ],
+                    token_in: Some(Coin {
+                        denom: "uatom".to_string(),
+                        amount: "1000".to_string()
+                    }),
+                    token_out_min_amount: "3880".to_string(),
+                }
+                .into(),
+            }]
+        );
+
+        assert_eq!(
+            route.swap(
+                deps.as_ref(),
+                &env,
+                &atom,
+                &usdc,
+                1_000u64.into(),
+                &Slippage::MinimumReceive(4_000u64.into()),
+            ),
+            Err(SwapError::SlippageExceeded {
+                minimum: 4_000u64.into(),
+                actual: 3_920u64.into()
+            }
+            .into())
+        );
+    }
+
+    #[test]
+    fn test_swap_ask() {
+        let deps = mock_dependencies();
+        let env = mock_env();
+        let route = route();
+        let (atom, usdc) = (native("uatom"), native("uusdc"));
+
+        let plan = route
+            .swap_ask(
+                deps.as_ref(),
+                &env,
+                &usdc,
+                &atom,
+                1_000u64.into(),
+                &Slippage::Tolerance(Decimal256::percent(1)),
+            )
+            .unwrap();
+        // 1_000 uatom are estimated to take 506 uosmo, which take 256 uusdc.
+        assert_eq!(plan.offer.amount, Uint256::from(256u64));
+        assert_eq!(plan.ask.amount, Uint256::from(1_000u64));
+        assert_eq!(plan.min_receive, Uint256::from(1_000u64));
+        // The ratio of offer to ask may worsen by the tolerance, 256 * 1_000 / 990.
+        assert_eq!(
+            plan.msgs,
+            vec![CosmosMsg::Stargate {
+                type_url: MsgSwapExactAmountOut::TYPE_URL.to_string(),
+                value: MsgSwapExactAmountOut {
+                    sender: env.contract.address.to_string(),
+                    routes: vec![
+                        SwapAmountOutRoute {
+                            pool_id: 678,
+                            token_in_denom: "uusdc".to_string()
+                        },
+                        SwapAmountOutRoute {
+                            pool_id: 1,
+                            token_in_denom: "uosmo".to_string()
+                        },
+                    ],
+                    token_in_max_amount: "258".to_string(),
+                    token_out: Some(Coin {
+                        denom: "uatom".to_string(),
+                        amount: "1000".to_string()
+                    }),
+                }
+                .into(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{CosmosMsg, Decimal256, Uint256};
+
+use crate::{
+    asset::{AssetAmount, AssetInfo, AssetMap},
+    error::NeptuneResult,
+    math::div_or_zero,
+    msg_wrapper::MsgWrapper,
+};
+
+use super::Exchange;
+
+/// The messages which execute a swap along with what the swap is expected to do.
+#[cw_serde]
+pub struct SwapPlan {
+    pub msgs: Vec<CosmosMsg<MsgWrapper>>,
+    /// The simulated amount of the offer asset spent.
+    pub offer: AssetAmount,
+    /// The simulated amount of the ask asset received.
+    pub ask: AssetAmount,
+    /// The fees paid to the venues.
+    pub commission: AssetMap<Uint256>,
+    /// The fraction of the return lost to the depth of the venue.
+    pub price_impact: Decimal256,
+    /// The swap fails if less than this amount of the ask asset is received.
+    pub min_receive: Uint256,
+    /// For exact-output swaps, the simulated amount received above the requested ask amount.
+    pub surplus: Uint256,
+    /// The venue executing the swap, if there is a single one.
+    pub venue: Option<Exchange>,
+    /// The plans this plan is composed of, either consecutive hops or parallel venues.
+    pub legs: Vec<SwapPlan>,
+}
+
+impl SwapPlan {
+    /// A plan which swaps nothing.
+    pub fn empty(offer_asset: &AssetInfo, ask_asset: &AssetInfo) -> Self {
+        Self {
+            msgs: vec![],
+            offer: AssetAmount {
+                info: offer_asset.clone(),
+                amount: Uint256::zero(),
+            },
+            ask: AssetAmount {
+                info: ask_asset.clone(),
+                amount: Uint256::zero(),
+            },
+            commission: AssetMap::new(),
+            price_impact: Decimal256::zero(),
+            min_receive: Uint256::zero(),
+            surplus: Uint256::zero(),
+            venue: None,
+            legs: vec![],
+        }
+    }
+
+    /// Combines consecutive hops into a single plan.
+    pub fn sequence(
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        legs: Vec<SwapPlan>,
+        min_receive: Uint256,
+    ) -> Self {
+        let mut plan = Self::empty(offer_asset, ask_asset);
+        plan.offer.amount = legs.first().map(|x| x.offer.amount).unwrap_or_default();
+        plan.ask.amount = legs.last().map(|x| x.ask.amount).unwrap_or_default();
+        plan.min_receive = min_receive;
+        plan.surplus = legs.last().map(|x| x.surplus).unwrap_or_default();
+        let mut retained = Decimal256::one();
+        for leg in &legs {
+            plan.msgs.extend(leg.msgs.iter().cloned());
+            plan.commission += leg.commission.clone();
+            retained *= Decimal256::one().saturating_sub(leg.price_impact);
+        }
+        plan.price_impact = Decimal256::one() - retained;
+        plan.legs = legs;
+        plan
+    }
+
+    /// Combines swaps of the same pair on several venues into a single plan.
+    pub fn parallel(
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        legs: Vec<SwapPlan>,
+        min_receive: Uint256,
+    ) -> Self {
+        let mut plan = Self::empty(offer_asset, ask_asset);
+        plan.min_receive = min_receive;
+        for leg in &legs {
+            plan.msgs.extend(leg.msgs.iter().cloned());
+            plan.offer.amount += leg.offer.amount;
+            plan.ask.amount += leg.ask.amount;
+            plan.surplus += leg.surplus;
+            plan.commission += leg.commission.clone();
+        }
+        // The price impact is weighted by the amount received from each venue.
+        plan.price_impact = legs.iter().fold(Decimal256::zero(), |acc, leg| {
+            acc + div_or_zero(leg.ask.amount, plan.ask.amount) * leg.price_impact
+        });
+        plan.legs = legs;
+        plan
+    }
+
+    /// Records the venue executing the plan.
+    /// Plans split across several venues record the venue of each leg instead.
+    pub fn with_venue(mut self, venue: &Exchange) -> Self {
+        if !matches!(venue, Exchange::Split(_)) {
+            self.venue = Some(venue.clone());
+        }
+        self
+    }
+
+    /// The worst ratio of offer to ask the swap can execute at.
+    pub fn worst_price(&self) -> NeptuneResult<Decimal256> {
+        Ok(Decimal256::checked_from_ratio(
+            self.offer.amount,
+            self.min_receive,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leg(offer: (&str, u64), ask: (&str, u64), price_impact: u64) -> SwapPlan {
+        let mut plan = SwapPlan::empty(
+            &AssetInfo::NativeToken {
+                denom: offer.0.to_string(),
+            },
+            &AssetInfo::NativeToken {
+                denom: ask.0.to_string(),
+            },
+        );
+        plan.offer.amount = offer.1.into();
+        plan.ask.amount = ask.1.into();
+        plan.commission = (plan.ask.info.clone(), Uint256::from(1u64)).into();
+        plan.price_impact = Decimal256::percent(price_impact);
+        plan
+    }
+
+    #[test]
+    fn test_sequence() {
+        let legs = vec![
+            leg(("a", 100), ("b", 50), 10),
+            leg(("b", 50), ("c", 25), 20),
+        ];
+        let plan = SwapPlan::sequence(
+            &legs[0].offer.info.clone(),
+            &legs[1].ask.info.clone(),
+            legs,
+            20u64.into(),
+        );
+        assert_eq!(plan.offer.amount, Uint256::from(100u64));
+        assert_eq!(plan.ask.amount, Uint256::from(25u64));
+        assert_eq!(plan.commission.len(), 2);
+        assert_eq!(plan.price_impact, Decimal256::percent(28));
+        assert_eq!(plan.worst_price().unwrap(), Decimal256::percent(500));
+    }
+
+    #[test]
+    fn test_parallel() {
+        let legs = vec![
+            leg(("a", 100), ("b", 30), 10),
+            leg(("a", 100), ("b", 10), 50),
+        ];
+        let plan = SwapPlan::parallel(
+            &legs[0].offer.info.clone(),
+            &legs[0].ask.info.clone(),
+            legs,
+            35u64.into(),
+        );
+        assert_eq!(plan.offer.amount, Uint256::from(200u64));
+        assert_eq!(plan.ask.amount, Uint256::from(40u64));
+        assert_eq!(plan.commission.sum(), Uint256::from(2u64));
+        assert_eq!(plan.price_impact, Decimal256::percent(20));
+    }
+}
@@ -0,0 +1,491 @@
+use cosmwasm_schema::{cw_serde, QueryResponses};
+use cosmwasm_std::{to_json_binary, Addr, Binary, Deps, DepsMut, MessageInfo, Order};
+use cw_storage_plus::{Bound, Item, Map, PrimaryKey};
+use neptune_auth::error::NeptAuthError;
+
+use crate::{asset::AssetInfo, error::NeptuneResult, query_wrapper::QueryWrapper};
+
+use super::{error::SwapError, Exchange};
+
+/// An administrable set of exchanges, keyed by their sorted pair of assets.
+/// Each registry is stored under its own namespaces so a contract can hold several.
+pub struct ExchangeRegistry<'a> {
+    pub exchanges: Map<'a, (&'a AssetInfo, &'a AssetInfo), Exchange>,
+    /// The only address allowed to execute the registry's messages.
+    pub admin: Item<'a, Addr>,
+}
+
+#[cw_serde]
+pub enum RegistryExecuteMsg {
+    /// Registers a venue for a pair which has none yet.
+    Register {
+        assets: [AssetInfo; 2],
+        exchange: Exchange,
+    },
+    /// Replaces the venue registered for a pair.
+    Update {
+        assets: [AssetInfo; 2],
+        exchange: Exchange,
+    },
+    Remove {
+        assets: [AssetInfo; 2],
+    },
+}
+
+#[cw_serde]
+#[derive(QueryResponses)]
+pub enum RegistryQueryMsg {
+    #[returns(Exchange)]
+    Exchange { assets: [AssetInfo; 2] },
+    /// Lists the registered exchanges in ascending order of their assets.
+    #[returns(Vec<ExchangeEntry>)]
+    List {
+        start_after: Option<[AssetInfo; 2]>,
+        limit: Option<u32>,
+    },
+}
+
+#[cw_serde]
+pub struct ExchangeEntry {
+    pub assets: [AssetInfo; 2],
+    pub exchange: Exchange,
+}
+
+impl<'a> ExchangeRegistry<'a> {
+    pub const fn new(namespace: &'a str, admin_namespace: &'a str) -> Self {
+        Self {
+            exchanges: Map::new(namespace),
+            admin: Item::new(admin_namespace),
+        }
+    }
+
+    /// Sets the admin of the registry, typically when the contract is instantiated.
+    pub fn set_admin(&self, deps: DepsMut<QueryWrapper>, admin: &Addr) -> NeptuneResult<()> {
+        Ok(self.admin.save(deps.storage, admin)?)
+    }
+
+    /// Executes a message of the registry, which must be sent by its admin.
+    pub fn execute(
+        &self,
+        deps: DepsMut<QueryWrapper>,
+        info: &MessageInfo,
+        msg: RegistryExecuteMsg,
+    ) -> NeptuneResult<()> {
+        if info.sender != self.admin.load(deps.storage)? {
+            return Err(NeptAuthError::Unauthorized(info.sender.to_string()).into());
+        }
+        match msg {
+            RegistryExecuteMsg::Register { assets, exchange } => {
+                self.register(deps, assets, exchange)
+            }
+            RegistryExecuteMsg::Update { assets, exchange } => self.update(deps, assets, exchange),
+            RegistryExecuteMsg::Remove { assets } => self.remove(deps, assets),
+        }
+    }
+
+    pub fn query(&self, deps: Deps<QueryWrapper>, msg: RegistryQueryMsg) -> NeptuneResult<Binary> {
+        Ok(match msg {
+            RegistryQueryMsg::Exchange { assets } => to_json_binary(&self.load(deps, assets)?)?,
+            RegistryQueryMsg::List { start_after, limit } => {
+                to_json_binary(&self.list(deps, start_after, limit)?)?
+            }
+        })
+    }
+
+    /// Registers a venue after validating that it trades the given assets.
+    pub fn register(
+        &self,
+        deps: DepsMut<QueryWrapper>,
+        mut assets: [AssetInfo; 2],
+        exchange: Exchange,
+    ) -> NeptuneResult<()> {
+        assets.sort_unstable();
+        if self.exchanges.has(deps.storage, (&assets[0], &assets[1])) {
+            return Err(SwapError::ExchangeAlreadyRegistered(assets).into());
+        }
+        validate_exchange(deps.as_ref(), &assets, &exchange)?;
+        self.exchanges
+            .save(deps.storage, (&assets[0], &assets[1]), &exchange)?;
+        Ok(())
+    }
+
+    /// Replaces a registered venue after validating that it trades the given assets.
+    pub fn update(
+        &self,
+        deps: DepsMut<QueryWrapper>,
+        mut assets: [AssetInfo; 2],
+        exchange: Exchange,
+    ) -> NeptuneResult<()> {
+        assets.sort_unstable();
+        if !self.exchanges.has(deps.storage, (&assets[0], &assets[1])) {
+            return Err(SwapError::PoolNotFound(assets).into());
+        }
+        validate_exchange(deps.as_ref(), &assets, &exchange)?;
+        self.exchanges
+            .save(deps.storage, (&assets[0], &assets[1]), &exchange)?;
+        Ok(())
+    }
+
+    pub fn remove(
+        &self,
+        deps: DepsMut<QueryWrapper>,
+        mut assets: [AssetInfo; 2],
+    ) -> NeptuneResult<()> {
+        assets.sort_unstable();
+        if !self.exchanges.has(deps.storage, (&assets[0], &assets[1])) {
+            return Err(SwapError::PoolNotFound(assets).into());
+        }
+        self.exchanges
+            .remove(deps.storage, (&assets[0], &assets[1]));
+        Ok(())
+    }
+
+    pub fn load(
+        &self,
+        deps: Deps<QueryWrapper>,
+        mut assets: [AssetInfo; 2],
+    ) -> NeptuneResult<Exchange> {
+        assets.sort_unstable();
+        Ok(self
+            .exchanges
+            .may_load(deps.storage, (&assets[0], &assets[1]))?
+            .ok_or(SwapError::PoolNotFound(assets))?)
+    }
+
+    /// Lists the registered exchanges in ascending order of their assets.
+    pub fn list(
+        &self,
+        deps: Deps<QueryWrapper>,
+        start_after: Option<[AssetInfo; 2]>,
+        limit: Option<u32>,
+    ) -> NeptuneResult<Vec<ExchangeEntry>> {
+        // A raw bound avoids borrowing the sorted start key.
+        let start = start_after.map(|mut assets| {
+            assets.sort_unstable();
+            Bound::ExclusiveRaw((&assets[0], &assets[1]).joined_key())
+        });
+        let entries = self
+            .exchanges
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|item| -> NeptuneResult<ExchangeEntry> {
+                let ((asset_a, asset_b), exchange) = item?;
+                Ok(ExchangeEntry {
+                    assets: [asset_a, asset_b],
+                    exchange,
+                })
+            });
+        match limit {
+            Some(limit) => entries.take(limit as usize).collect(),
+            None => entries.collect(),
+        }
+    }
+}
+
+/// Checks that the venue trades exactly the given pair of assets.
+pub fn validate_exchange(
+    deps: Deps<QueryWrapper>,
+    assets: &[AssetInfo; 2],
+    exchange: &Exchange,
+) -> NeptuneResult<()> {
+    let mismatch = || SwapError::ExchangeMismatch(assets.clone());
+    let is_pair = |asset_a: &AssetInfo, asset_b: &AssetInfo| {
+        (asset_a == &assets[0] && asset_b == &assets[1])
+            || (asset_a == &assets[1] && asset_b == &assets[0])
+    };
+    match exchange {
+        Exchange::LiquidityPool(liquidity_pool) => {
+            let pair: astroport::asset::PairInfo = deps
+                .querier
+                .query_wasm_smart(&liquidity_pool.addr, &astroport::pair::QueryMsg::Pair {})?;
+            let pair_assets: Vec<AssetInfo> =
+                pair.asset_infos.into_iter().map(Into::into).collect();
+            if pair_assets.len() != 2 || !is_pair(&pair_assets[0], &pair_assets[1]) {
+                return Err(mismatch().into());
+            }
+        }
+        Exchange::Router(router) => match (router.hops.first(), router.hops.last()) {
+            (Some(first), Some(last)) if router.hops.len() >= 2 && is_pair(first, last) => {}
+            _ => return Err(mismatch().into()),
+        },
+        #[cfg(feature = "injective")]
+        Exchange::OrderBook(order_book) => {
+            let spot_market =
+                super::order_book::query_spot_market(deps, order_book.market_id.clone())?;
+            // CW20s are traded as the denoms of the cw20-adapter.
+            let denom = |asset: &AssetInfo| order_book.denom(asset).ok();
+            let (base, quote) = (Some(spot_market.base_denom), Some(spot_market.quote_denom));
+            let (denom_a, denom_b) = (denom(&assets[0]), denom(&assets[1]));
+            if !((denom_a == base && denom_b == quote) || (denom_a == quote && denom_b == base)) {
+                return Err(mismatch().into());
+            }
+        }
+        #[cfg(feature = "osmosis")]
+        Exchange::Osmosis(osmosis_route) => {
+            let denom = |denom: Option<&String>| {
+                denom.map(|denom| AssetInfo::NativeToken {
+                    denom: denom.clone(),
+                })
+            };
+            match (
+                denom(osmosis_route.denoms.first()),
+                denom(osmosis_route.denoms.last()),
+            ) {
+                (Some(first), Some(last))
+                    if osmosis_route.denoms.len() == osmosis_route.pool_ids.len() + 1
+                        && is_pair(&first, &last) => {}
+                _ => return Err(mismatch().into()),
+            }
+        }
+        // Adapters are trusted to trade the pair they are registered for.
+        Exchange::Adapter(_) => {}
+        Exchange::Split(split) => {
+            for venue in &split.venues {
+                validate_exchange(deps, assets, venue)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{testing::mock_info, Decimal, Uint128};
+
+    use super::*;
+    use crate::{
+        error::NeptuneError,
+        swap::{
+            adapter::Adapter,
+            liquidity_pool::LiquidityPool,
+            mock_querier::{mock_dependencies_with_pools, MockAstroport},
+            simulated_pool::{Curve, SimulatedPool},
+        },
+        testing::mock_dependencies,
+    };
+
+    fn native(denom: &str) -> AssetInfo {
+        AssetInfo::NativeToken {
+            denom: denom.to_string(),
+        }
+    }
+
+    fn adapter(addr: &str) -> Exchange {
+        Exchange::Adapter(Adapter {
+            addr: Addr::unchecked(addr),
+        })
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut deps = mock_dependencies();
+        let registry = ExchangeRegistry::new("registry", "registry_admin");
+        let other_registry = ExchangeRegistry::new("other_registry", "other_registry_admin");
+
+        registry
+            .register(
+                deps.as_mut(),
+                [native("usdc"), native("atom")],
+                adapter("a"),
+            )
+            .unwrap();
+        registry
+            .register(deps.as_mut(), [native("inj"), native("usdc")], adapter("b"))
+            .unwrap();
+        assert_eq!(
+            registry.register(
+                deps.as_mut(),
+                [native("atom"), native("usdc")],
+                adapter("c")
+            ),
+            Err(NeptuneError::SwapError(
+                SwapError::ExchangeAlreadyRegistered([native("atom"), native("usdc")])
+            ))
+        );
+        assert!(other_registry
+            .load(deps.as_ref(), [native("atom"), native("usdc")])
+            .is_err());
+
+        registry
+            .update(
+                deps.as_mut(),
+                [native("atom"), native("usdc")],
+                adapter("c"),
+            )
+            .unwrap();
+        assert_eq!(
+            registry
+                .load(deps.as_ref(), [native("usdc"), native("atom")])
+                .unwrap(),
+            adapter("c")
+        );
+
+        let list = registry.list(deps.as_ref(), None, None).unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].assets, [native("atom"), native("usdc")]);
+        let list = registry
+            .list(
+                deps.as_ref(),
+                Some([native("usdc"), native("atom")]),
+                Some(5),
+            )
+            .unwrap();
+        assert_eq!(
+            list,
+            vec![ExchangeEntry {
+                assets: [native("inj"), native("usdc")],
+                exchange: adapter("b"),
+            }]
+        );
+
+        registry
+            .remove(deps.as_mut(), [native("inj"), native("usdc")])
+            .unwrap();
+        assert_eq!(
+            registry.remove(deps.as_mut(), [native("inj"), native("usdc")]),
+            Err(NeptuneError::SwapError(SwapError::PoolNotFound([
+                native("inj"),
+                native("usdc")
+            ])))
+        );
+    }
+
+    #[test]
+    fn test_execute() {
+        let mut deps = mock_dependencies();
+        let registry = ExchangeRegistry::new("registry", "registry_admin");
+        registry
+            .set_admin(deps.as_mut(), &Addr::unchecked("admin"))
+            .unwrap();
+        let msg = RegistryExecuteMsg::Register {
+            assets: [native("atom"), native("usdc")],
+            exchange: adapter("a"),
+        };
+
+        assert_eq!(
+            registry.execute(deps.as_mut(), &mock_info("anyone", &[]), msg.clone()),
+            Err(NeptuneError::Auth(NeptAuthError::Unauthorized(
+                "anyone".to_string()
+            )))
+        );
+        assert!(registry
+            .load(deps.as_ref(), [native("atom"), native("usdc")])
+            .is_err());
+
+        registry
+            .execute(deps.as_mut(), &mock_info("admin", &[]), msg)
+            .unwrap();
+        assert_eq!(
+            registry
+                .load(deps.as_ref(), [native("atom"), native("usdc")])
+                .unwrap(),
+            adapter("a")
+        );
+    }
+
+    #[test]
+    fn test_register_liquidity_pool() {
+        let mut deps = mock_dependencies_with_pools(MockAstroport::default().with_pool(
+            "pool",
+            SimulatedPool {
+                assets: [native("atom"), native("usdc")],
+                reserves: [Uint128::from(1_000_000u128); 2],
+                commission_rate: Decimal::permille(3),
+                curve: Curve::ConstantProduct,
+            },
+        ));
+        let registry = ExchangeRegistry::new("registry", "registry_admin");
+        let pool = Exchange::LiquidityPool(LiquidityPool {
+            addr: Addr::unchecked("pool"),
+        });
+
+        assert_eq!(
+            registry.register(deps.as_mut(), [native("atom"), native("inj")], pool.clone()),
+            Err(NeptuneError::SwapError(SwapError::ExchangeMismatch([
+                native("atom"),
+                native("inj")
+            ])))
+        );
+        registry
+            .register(
+                deps.as_mut(),
+                [native("usdc"), native("atom")],
+                pool.clone(),
+            )
+            .unwrap();
+        assert_eq!(
+            registry
+                .load(deps.as_ref(), [native("atom"), native("usdc")])
+                .unwrap(),
+            pool
+        );
+    }
+
+    #[cfg(feature = "injective")]
+    #[test]
+    fn test_register_order_book() {
+        use injective_cosmwasm::{MarketId, MarketStatus, SpotMarket};
+        use injective_math::FPDecimal;
+
+        use crate::swap::{
+            mock_exchange::{mock_dependencies_with_exchange, MockExchange, MockSpotMarket},
+            order_book::OrderBook,
+            order_book_math::MarketDecimals,
+        };
+
+        const MARKET_ID: &str =
+            "0x0611780ba69656949525013d947713300f56c37b6175e02f26bffa495c3208fe";
+        let fp = FPDecimal::must_from_str;
+        let mut deps =
+            mock_dependencies_with_exchange(MockExchange::default().with_market(MockSpotMarket {
+                market: SpotMarket {
+                    ticker: "INJ/USDT".to_string(),
+                    base_denom: "inj".to_string(),
+                    quote_denom: "usdt".to_string(),
+                    maker_fee_rate: fp("0.001"),
+                    taker_fee_rate: fp("0.002"),
+                    relayer_fee_share_rate: fp("0.5"),
+                    market_id: MarketId::new(MARKET_ID).unwrap(),
+                    status: MarketStatus::Active,
+                    min_price_tick_size: fp("0.001"),
+                    min_quantity_tick_size: fp("1000000000000000"),
+                },
+                buys: vec![],
+                sells: vec![],
+                atomic_execution_fee_multiplier: fp("2"),
+                open_orders: vec![],
+            }));
+        let registry = ExchangeRegistry::new("registry", "registry_admin");
+        let order_book = Exchange::OrderBook(OrderBook {
+            market_id: MarketId::new(MARKET_ID).unwrap(),
+            max_book_levels: None,
+            decimals: Some(MarketDecimals { base: 18, quote: 6 }),
+            cw20_adapter: None,
+            subaccount_nonce: None,
+        });
+
+        assert_eq!(
+            registry.register(
+                deps.as_mut(),
+                [native("inj"), native("usdc")],
+                order_book.clone()
+            ),
+            Err(NeptuneError::SwapError(SwapError::ExchangeMismatch([
+                native("inj"),
+                native("usdc")
+            ])))
+        );
+        registry
+            .register(
+                deps.as_mut(),
+                [native("usdt"), native("inj")],
+                order_book.clone(),
+            )
+            .unwrap();
+        assert_eq!(
+            registry
+                .load(deps.as_ref(), [native("inj"), native("usdt")])
+                .unwrap(),
+            order_book
+        );
+    }
+}
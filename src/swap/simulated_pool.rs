@@ -0,0 +1,460 @@
+use astroport::pair::{ReverseSimulationResponse, SimulationResponse};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Decimal, Decimal256, Deps, Env, Isqrt, StdError, Uint128, Uint256};
+
+use crate::{
+    asset::{AssetAmount, AssetInfo},
+    error::NeptuneResult,
+    query_wrapper::QueryWrapper,
+};
+
+use super::{
+    concentrated::{self, Invariant},
+    error::SwapError,
+    liquidity_pool::{price_impact, search_exact_offer_amount},
+    plan::SwapPlan,
+    slippage::Slippage,
+    stable_swap, Swap,
+};
+
+/// The invariant of a simulated pool.
+#[cw_serde]
+pub enum Curve {
+    ConstantProduct,
+    /// The stableswap invariant with the given amplification.
+    StableSwap {
+        amp: Decimal,
+    },
+    /// The invariant of Astroport concentrated liquidity pairs, with the second asset
+    /// priced in the first by the price scale. Reserves are taken as whole units.
+    Concentrated {
+        amp: Decimal,
+        gamma: Decimal,
+        price_scale: Decimal,
+    },
+}
+
+/// A pool which only exists in memory, simulating swaps the way an Astroport pair does.
+/// Constant product pools round exactly like Astroport's xyk pair.
+/// Plans have no messages, so this is only useful for tests and off-chain analysis.
+#[cw_serde]
+pub struct SimulatedPool {
+    pub assets: [AssetInfo; 2],
+    pub reserves: [Uint128; 2],
+    /// The fraction of the return kept by the pool.
+    pub commission_rate: Decimal,
+    pub curve: Curve,
+}
+
+impl SimulatedPool {
+    /// Returns the reserves of the offer and the ask asset.
+    fn reserves(&self, offer_asset: &AssetInfo) -> NeptuneResult<(Uint128, Uint128)> {
+        let (offer_pool, ask_pool) = if offer_asset == &self.assets[0] {
+            (self.reserves[0], self.reserves[1])
+        } else if offer_asset == &self.assets[1] {
+            (self.reserves[1], self.reserves[0])
+        } else {
+            return Err(SwapError::InvalidAsset.into());
+        };
+        if offer_pool.is_zero() || ask_pool.is_zero() {
+            return Err(SwapError::InsufficientLiquidity.into());
+        }
+        Ok((offer_pool, ask_pool))
+    }
+
+    /// Returns the scales of the offer and the ask asset,
+    /// the second asset of the pool being scaled by the price scale.
+    fn scales(&self, offer_asset: &AssetInfo, price_scale: Decimal) -> (Decimal256, Decimal256) {
+        if offer_asset == &self.assets[1] {
+            (to_decimal_256(price_scale), Decimal256::one())
+        } else {
+            (Decimal256::one(), to_decimal_256(price_scale))
+        }
+    }
+
+    fn other_asset(&self, asset: &AssetInfo) -> NeptuneResult<&AssetInfo> {
+        if asset == &self.assets[0] {
+            Ok(&self.assets[1])
+        } else if asset == &self.assets[1] {
+            Ok(&self.assets[0])
+        } else {
+            Err(SwapError::InvalidAsset.into())
+        }
+    }
+
+    /// Simulates a swap, answering like the pair's `Simulation` query.
+    pub fn simulate(
+        &self,
+        offer_asset: &AssetInfo,
+        offer_amount: Uint128,
+    ) -> NeptuneResult<SimulationResponse> {
+        let (offer_pool, ask_pool) = self.reserves(offer_asset)?;
+        let (offer_pool, ask_pool) = (Uint256::from(offer_pool), Uint256::from(ask_pool));
+        let offer_amount = Uint256::from(offer_amount);
+        let return_amount = match &self.curve {
+            Curve::ConstantProduct => {
+                // ask_amount = ask_pool - cp / (offer_pool + offer_amount)
+                let cp = offer_pool * ask_pool;
+                Uint256::one()
+                    * (Decimal256::from_ratio(ask_pool, 1u8)
+                        - Decimal256::from_ratio(cp, offer_pool + offer_amount))
+            }
+            Curve::StableSwap { amp } => {
+                let leverage = stable_swap::leverage(to_decimal_256(*amp));
+                let d = stable_swap::compute_d(leverage, offer_pool, ask_pool)?;
+                let ask_pool_after =
+                    stable_swap::compute_y(leverage, offer_pool + offer_amount, d)?;
+                // A unit is kept by the pool to cover the rounding of the invariant.
+                ask_pool
+                    .saturating_sub(ask_pool_after)
+                    .saturating_sub(Uint256::one())
+            }
+            Curve::Concentrated {
+                amp,
+                gamma,
+                price_scale,
+            } => {
+                let invariant = Invariant::new(to_decimal_256(*amp), to_decimal_256(*gamma))?;
+                let (offer_scale, ask_scale) = self.scales(offer_asset, *price_scale);
+                let offer_reserve = Decimal256::from_ratio(offer_pool, 1u8) * offer_scale;
+                let ask_reserve = Decimal256::from_ratio(ask_pool, 1u8) * ask_scale;
+                let d = invariant.compute_d(offer_reserve, ask_reserve)?;
+                let ask_reserve_after = invariant.compute_y(
+                    offer_reserve + Decimal256::from_ratio(offer_amount, 1u8) * offer_scale,
+                    d,
+                )?;
+                // A unit is kept by the pool to cover the rounding of the invariant.
+                (Uint256::one() * (ask_reserve.saturating_sub(ask_reserve_after) / ask_scale))
+                    .saturating_sub(Uint256::one())
+            }
+        };
+        let spread_amount = (offer_amount * Decimal256::from_ratio(ask_pool, offer_pool))
+            .saturating_sub(return_amount);
+        let commission_amount = return_amount * to_decimal_256(self.commission_rate);
+        Ok(SimulationResponse {
+            return_amount: (return_amount - commission_amount).try_into()?,
+            spread_amount: spread_amount.try_into()?,
+            commission_amount: commission_amount.try_into()?,
+        })
+    }
+
+    /// Simulates a swap in reverse, answering like the pair's `ReverseSimulation` query.
+    pub fn reverse_simulate(
+        &self,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint128,
+    ) -> NeptuneResult<ReverseSimulationResponse> {
+        let offer_asset = self.other_asset(ask_asset)?;
+        let (offer_pool, ask_pool) = self.reserves(offer_asset)?;
+        let (offer_pool, ask_pool) = (Uint256::from(offer_pool), Uint256::from(ask_pool));
+        let commission_rate = to_decimal_256(self.commission_rate);
+        let inv_one_minus_commission = Decimal256::one() / (Decimal256::one() - commission_rate);
+        let before_commission = Uint256::from(ask_amount) * inv_one_minus_commission;
+        let ask_pool_after = ask_pool
+            .checked_sub(before_commission)
+            .ok()
+            .filter(|x| !x.is_zero())
+            .ok_or(SwapError::InsufficientLiquidity)?;
+        let offer_amount = match &self.curve {
+            Curve::ConstantProduct => (offer_pool * ask_pool)
+                .multiply_ratio(1u8, ask_pool_after)
+                .saturating_sub(offer_pool),
+            Curve::StableSwap { amp } => {
+                let leverage = stable_swap::leverage(to_decimal_256(*amp));
+                let d = stable_swap::compute_d(leverage, offer_pool, ask_pool)?;
+                (stable_swap::compute_y(leverage, ask_pool_after, d)? + Uint256::one())
+                    .saturating_sub(offer_pool)
+            }
+            Curve::Concentrated {
+                amp,
+                gamma,
+                price_scale,
+            } => {
+                let invariant = Invariant::new(to_decimal_256(*amp), to_decimal_256(*gamma))?;
+                let (offer_scale, ask_scale) = self.scales(offer_asset, *price_scale);
+                let offer_reserve = Decimal256::from_ratio(offer_pool, 1u8) * offer_scale;
+                let d = invariant.compute_d(
+                    offer_reserve,
+                    Decimal256::from_ratio(ask_pool, 1u8) * ask_scale,
+                )?;
+                let offer_reserve_after = invariant
+                    .compute_y(Decimal256::from_ratio(ask_pool_after, 1u8) * ask_scale, d)?;
+                Uint256::one() * (offer_reserve_after.saturating_sub(offer_reserve) / offer_scale)
+                    + Uint256::one()
+            }
+        };
+        let spread_amount = (offer_amount * Decimal256::from_ratio(ask_pool, offer_pool))
+            .saturating_sub(before_commission);
+        let commission_amount = before_commission * commission_rate;
+        Ok(ReverseSimulationResponse {
+            offer_amount: offer_amount.try_into()?,
+            spread_amount: spread_amount.try_into()?,
+            commission_amount: commission_amount.try_into()?,
+        })
+    }
+
+    /// Returns the smallest offer amount whose simulated return is at least `ask_amount`.
+    fn exact_offer_amount(
+        &self,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<(Uint256, SimulationResponse)> {
+        let estimate = self
+            .reverse_simulate(ask_asset, ask_amount.try_into()?)?
+            .offer_amount;
+        search_exact_offer_amount(estimate.into(), ask_amount, |offer_amount| {
+            self.simulate(offer_asset, offer_amount.try_into()?)
+        })
+    }
+
+    fn plan(
+        &self,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+        sim: &SimulationResponse,
+        min_receive: Uint256,
+    ) -> SwapPlan {
+        SwapPlan {
+            msgs: vec![],
+            offer: AssetAmount {
+                info: offer_asset.clone(),
+                amount: offer_amount,
+            },
+            ask: AssetAmount {
+                info: ask_asset.clone(),
+                amount: sim.return_amount.into(),
+            },
+            commission: (ask_asset.clone(), Uint256::from(sim.commission_amount)).into(),
+            price_impact: price_impact(sim),
+            min_receive,
+            surplus: Uint256::zero(),
+            venue: None,
+            legs: vec![],
+        }
+    }
+}
+
+impl Swap for SimulatedPool {
+    fn swap(
+        &self,
+        _deps: Deps<QueryWrapper>,
+        _env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        if offer_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
+        }
+        let sim = self.simulate(offer_asset, offer_amount.try_into()?)?;
+        let min_receive = slippage.assert(offer_amount, sim.return_amount.into())?;
+        Ok(self.plan(offer_asset, ask_asset, offer_amount, &sim, min_receive))
+    }
+
+    fn swap_ask(
+        &self,
+        _deps: Deps<QueryWrapper>,
+        _env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        if ask_amount.is_zero() {
+            return Ok(SwapPlan::empty(offer_asset, ask_asset));
+        }
+        let (offer_amount, sim) = self.exact_offer_amount(offer_asset, ask_asset, ask_amount)?;
+        let min_receive = slippage
+            .assert(offer_amount, sim.return_amount.into())?
+            .max(ask_amount);
+        let mut plan = self.plan(offer_asset, ask_asset, offer_amount, &sim, min_receive);
+        plan.surplus = plan.ask.amount - ask_amount;
+        Ok(plan)
+    }
+
+    fn query_sim(
+        &self,
+        _deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        _ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        if offer_amount.is_zero() {
+            return Ok(Uint256::zero());
+        }
+        Ok(self
+            .simulate(offer_asset, offer_amount.try_into()?)?
+            .return_amount
+            .into())
+    }
+
+    /// Returns the smallest offer amount whose return is at least the ask amount,
+    /// as [`LiquidityPool`](super::liquidity_pool::LiquidityPool) does.
+    fn query_reverse_sim(
+        &self,
+        _deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        if ask_amount.is_zero() {
+            return Ok(Uint256::zero());
+        }
+        Ok(self
+            .exact_offer_amount(offer_asset, ask_asset, ask_amount)?
+            .0)
+    }
+
+    fn query_ask_amount_at_price(
+        &self,
+        _deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        _ask_asset: &AssetInfo,
+        max_ratio: Decimal256,
+    ) -> NeptuneResult<Uint256> {
+        let (offer_pool, ask_pool) = self.reserves(offer_asset)?;
+        match &self.curve {
+            Curve::ConstantProduct => {
+                let inv_max_ratio = max_ratio.inv().ok_or(SwapError::InvalidPrice(max_ratio))?;
+                let sqrt = (offer_pool.full_mul(ask_pool) * inv_max_ratio).isqrt();
+                Ok(Uint256::from(ask_pool).saturating_sub(sqrt))
+            }
+            Curve::StableSwap { amp } => stable_swap::ask_amount_at_price(
+                stable_swap::leverage(to_decimal_256(*amp)),
+                offer_pool.into(),
+                ask_pool.into(),
+                max_ratio,
+            ),
+            Curve::Concentrated {
+                amp,
+                gamma,
+                price_scale,
+            } => {
+                let (offer_scale, ask_scale) = self.scales(offer_asset, *price_scale);
+                let ask_amount = concentrated::ask_amount_at_price(
+                    Invariant::new(to_decimal_256(*amp), to_decimal_256(*gamma))?,
+                    Decimal256::from_ratio(offer_pool, 1u8) * offer_scale,
+                    Decimal256::from_ratio(ask_pool, 1u8) * ask_scale,
+                    max_ratio
+                        .checked_mul(offer_scale)
+                        .map_err(StdError::from)?
+                        .checked_div(ask_scale)?,
+                )?
+                .checked_div(ask_scale)?;
+                Ok(Uint256::one() * ask_amount)
+            }
+        }
+    }
+}
+
+fn to_decimal_256(value: Decimal) -> Decimal256 {
+    Decimal256::new(value.atomics().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(curve: Curve) -> SimulatedPool {
+        SimulatedPool {
+            assets: [
+                AssetInfo::NativeToken {
+                    denom: "uatom".to_string(),
+                },
+                AssetInfo::NativeToken {
+                    denom: "uusdc".to_string(),
+                },
+            ],
+            reserves: [1_000_000u128.into(), 1_000_000u128.into()],
+            commission_rate: Decimal::permille(3),
+            curve,
+        }
+    }
+
+    #[test]
+    fn test_constant_product() {
+        let pool = pool(Curve::ConstantProduct);
+        let sim = pool.simulate(&pool.assets[0], 1_000u128.into()).unwrap();
+        assert_eq!(
+            sim,
+            SimulationResponse {
+                return_amount: 997u128.into(),
+                spread_amount: 1u128.into(),
+                commission_amount: 2u128.into(),
+            }
+        );
+
+        let reverse_sim = pool
+            .reverse_simulate(&pool.assets[1], 997u128.into())
+            .unwrap();
+        // The reverse simulation rounds in favour of the trader.
+        assert_eq!(reverse_sim.offer_amount, Uint128::from(999u128));
+        assert!(
+            pool.simulate(&pool.assets[0], reverse_sim.offer_amount)
+                .unwrap()
+                .return_amount
+                < Uint128::from(997u128)
+        );
+
+        let (offer_amount, sim) = pool
+            .exact_offer_amount(&pool.assets[0], &pool.assets[1], 997u64.into())
+            .unwrap();
+        assert_eq!(offer_amount, Uint256::from(1_000u64));
+        assert_eq!(sim.return_amount, Uint128::from(997u128));
+
+        assert!(pool
+            .reverse_simulate(&pool.assets[1], 1_000_000u128.into())
+            .is_err());
+    }
+
+    #[test]
+    fn test_concentrated() {
+        let pool = pool(Curve::Concentrated {
+            amp: Decimal::from_ratio(10u64, 1u64),
+            gamma: Decimal::from_ratio(145u64, 1_000_000u64),
+            price_scale: Decimal::one(),
+        });
+        let sim = pool.simulate(&pool.assets[0], 1_000u128.into()).unwrap();
+        // Close to one to one around the price scale.
+        assert_eq!(sim.commission_amount, Uint128::from(2u128));
+        assert!(sim.return_amount >= Uint128::from(995u128));
+
+        let (offer_amount, sim) = pool
+            .exact_offer_amount(&pool.assets[0], &pool.assets[1], 50_000u64.into())
+            .unwrap();
+        assert!(sim.return_amount >= Uint128::from(50_000u128));
+        let sim = pool
+            .simulate(
+                &pool.assets[0],
+                (offer_amount - Uint256::one()).try_into().unwrap(),
+            )
+            .unwrap();
+        assert!(sim.return_amount < Uint128::from(50_000u128));
+    }
+
+    #[test]
+    fn test_stable_swap() {
+        let pool = pool(Curve::StableSwap {
+            amp: Decimal::from_ratio(100u64, 1u64),
+        });
+        let sim = pool.simulate(&pool.assets[0], 1_000u128.into()).unwrap();
+        // Close to one to one, less the commission and the unit kept for rounding.
+        assert_eq!(sim.commission_amount, Uint128::from(2u128));
+        assert_eq!(sim.return_amount, Uint128::from(997u128));
+
+        let (offer_amount, sim) = pool
+            .exact_offer_amount(&pool.assets[0], &pool.assets[1], 50_000u64.into())
+            .unwrap();
+        assert!(sim.return_amount >= Uint128::from(50_000u128));
+        let sim = pool
+            .simulate(
+                &pool.assets[0],
+                (offer_amount - Uint256::one()).try_into().unwrap(),
+            )
+            .unwrap();
+        assert!(sim.return_amount < Uint128::from(50_000u128));
+    }
+}
@@ -0,0 +1,263 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Decimal, Decimal256, Uint128, Uint256};
+
+use crate::{error::NeptuneResult, math::checked_div};
+
+use super::error::SwapError;
+
+/// The largest spread Astroport pairs accept.
+const MAX_ALLOWED_SPREAD: Decimal = Decimal::percent(50);
+
+/// Protection against receiving less than expected from a swap.
+#[cw_serde]
+pub enum Slippage {
+    /// The maximum spread against the venue's spot price, as in Astroport's `max_spread`.
+    /// Venues without a spot price apply it to the simulated return instead.
+    MaxSpread(Decimal256),
+    /// The expected ratio of offer to ask and the maximum deviation from it.
+    BeliefPrice {
+        belief_price: Decimal256,
+        max_spread: Decimal256,
+    },
+    /// Receive at least the simulated return reduced by the given fraction.
+    Tolerance(Decimal256),
+    /// Receive at least the given amount of the ask asset.
+    MinimumReceive(Uint256),
+}
+
+impl Slippage {
+    /// Returns the minimum amount of the ask asset that must be received
+    /// when `offer_amount` is simulated to return `return_amount`.
+    pub fn min_receive(
+        &self,
+        offer_amount: Uint256,
+        return_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        Ok(match self {
+            Slippage::MaxSpread(spread) | Slippage::Tolerance(spread) => {
+                return_amount * Decimal256::one().saturating_sub(*spread)
+            }
+            Slippage::BeliefPrice {
+                belief_price,
+                max_spread,
+            } => {
+                checked_div(offer_amount, *belief_price)?
+                    * Decimal256::one().saturating_sub(*max_spread)
+            }
+            Slippage::MinimumReceive(amount) => *amount,
+        })
+    }
+
+    /// Returns the minimum amount to be received, erroring if the simulated return is already below it.
+    pub fn assert(&self, offer_amount: Uint256, return_amount: Uint256) -> NeptuneResult<Uint256> {
+        let min_receive = self.min_receive(offer_amount, return_amount)?;
+        if return_amount < min_receive {
+            return Err(SwapError::SlippageExceeded {
+                minimum: min_receive,
+                actual: return_amount,
+            }
+            .into());
+        }
+        Ok(min_receive)
+    }
+
+    /// Returns the largest amount that may be offered for exactly `ask_amount`,
+    /// which is the offer at the worst ratio of offer to ask the policy accepts
+    /// when `offer_amount` is simulated to return `ask_amount`.
+    pub fn max_offer_amount(
+        &self,
+        offer_amount: Uint256,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        let min_receive = self.assert(offer_amount, ask_amount)?;
+        if min_receive.is_zero() {
+            return Ok(Uint128::MAX.into());
+        }
+        Ok(offer_amount.multiply_ratio(ask_amount, min_receive))
+    }
+
+    /// Returns the `belief_price` and `max_spread` which enforce this policy on an Astroport pair.
+    pub fn to_astroport(
+        &self,
+        offer_amount: Uint256,
+        return_amount: Uint256,
+    ) -> NeptuneResult<(Option<Decimal>, Option<Decimal>)> {
+        Ok(match self {
+            Slippage::MaxSpread(max_spread) => (None, Some(to_max_spread(*max_spread)?)),
+            Slippage::BeliefPrice {
+                belief_price,
+                max_spread,
+            } => (
+                Some(to_decimal(*belief_price)?),
+                Some(to_max_spread(*max_spread)?),
+            ),
+            Slippage::Tolerance(_) | Slippage::MinimumReceive(_) => {
+                let min_receive = self.min_receive(offer_amount, return_amount)?;
+                if min_receive.is_zero() {
+                    return Ok((None, Some(MAX_ALLOWED_SPREAD)));
+                }
+                // The belief price is rounded up so that the expected return never exceeds the minimum.
+                let belief_price = Decimal256::checked_from_ratio(offer_amount, min_receive)?
+                    + Decimal256::new(Uint256::one());
+                (Some(to_decimal(belief_price)?), Some(Decimal::zero()))
+            }
+        })
+    }
+
+    /// Returns the `max_spread` applied to every pair of an Astroport router swap.
+    /// The policy as a whole is enforced through the router's `minimum_receive`.
+    pub fn to_astroport_router(&self) -> NeptuneResult<Decimal> {
+        match self {
+            Slippage::MaxSpread(max_spread) | Slippage::BeliefPrice { max_spread, .. } => {
+                to_max_spread(*max_spread)
+            }
+            Slippage::Tolerance(_) | Slippage::MinimumReceive(_) => Ok(MAX_ALLOWED_SPREAD),
+        }
+    }
+}
+
+fn to_decimal(value: Decimal256) -> NeptuneResult<Decimal> {
+    Ok(Decimal::new(value.atomics().try_into()?))
+}
+
+/// Astroport pairs reject a `max_spread` above [`MAX_ALLOWED_SPREAD`].
+fn to_max_spread(max_spread: Decimal256) -> NeptuneResult<Decimal> {
+    if max_spread > MAX_ALLOWED_SPREAD.into() {
+        return Err(SwapError::MaxSpreadTooLarge(max_spread).into());
+    }
+    to_decimal(max_spread)
+}
+
+/// Divides a minimum received for a whole swap among the venues it is routed through,
+/// in proportion to the portion each venue is expected to return.
+/// The remainder of rounding down goes to the largest portion,
+/// so that the minimums sum exactly to `min_receive`.
+pub fn min_receive_portions(min_receive: Uint256, portions: &[Uint256]) -> Vec<Slippage> {
+    let total: Uint256 = portions.iter().sum();
+    if total.is_zero() {
+        return portions
+            .iter()
+            .map(|_| Slippage::MinimumReceive(Uint256::zero()))
+            .collect();
+    }
+    let mut minimums: Vec<Uint256> = portions
+        .iter()
+        .map(|portion| min_receive.multiply_ratio(*portion, total))
+        .collect();
+    let remainder = min_receive - minimums.iter().sum::<Uint256>();
+    if let Some((largest, _)) = portions
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, portion)| **portion)
+    {
+        minimums[largest] += remainder;
+    }
+    minimums.into_iter().map(Slippage::MinimumReceive).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::error::NeptuneError;
+
+    #[test]
+    fn test_min_receive() {
+        let offer_amount = Uint256::from(1_000u64);
+        let return_amount = Uint256::from(2_000u64);
+
+        let tolerance = Slippage::Tolerance(Decimal256::percent(1));
+        assert_eq!(
+            tolerance.min_receive(offer_amount, return_amount),
+            Ok(Uint256::from(1_980u64))
+        );
+
+        let belief_price = Slippage::BeliefPrice {
+            belief_price: Decimal256::from_str("0.4").unwrap(),
+            max_spread: Decimal256::percent(10),
+        };
+        assert_eq!(
+            belief_price.min_receive(offer_amount, return_amount),
+            Ok(Uint256::from(2_250u64))
+        );
+        assert_eq!(
+            belief_price.assert(offer_amount, return_amount),
+            Err(NeptuneError::SwapError(SwapError::SlippageExceeded {
+                minimum: Uint256::from(2_250u64),
+                actual: return_amount
+            }))
+        );
+
+        let minimum = Slippage::MinimumReceive(Uint256::from(1_500u64));
+        assert_eq!(
+            minimum.assert(offer_amount, return_amount),
+            Ok(Uint256::from(1_500u64))
+        );
+    }
+
+    #[test]
+    fn test_max_offer_amount() {
+        let offer_amount = Uint256::from(1_000u64);
+        let ask_amount = Uint256::from(2_000u64);
+
+        assert_eq!(
+            Slippage::Tolerance(Decimal256::percent(1)).max_offer_amount(offer_amount, ask_amount),
+            Ok(Uint256::from(1_010u64))
+        );
+        assert_eq!(
+            Slippage::MinimumReceive(Uint256::from(1_500u64))
+                .max_offer_amount(offer_amount, ask_amount),
+            Ok(Uint256::from(1_333u64))
+        );
+        assert_eq!(
+            Slippage::Tolerance(Decimal256::one()).max_offer_amount(offer_amount, ask_amount),
+            Ok(Uint128::MAX.into())
+        );
+    }
+
+    #[test]
+    fn test_to_astroport() {
+        let offer_amount = Uint256::from(1_000u64);
+        let return_amount = Uint256::from(2_000u64);
+
+        let (belief_price, max_spread) = Slippage::MinimumReceive(Uint256::from(1_600u64))
+            .to_astroport(offer_amount, return_amount)
+            .unwrap();
+        let belief_price = belief_price.unwrap();
+        assert!(belief_price > Decimal::from_str("0.625").unwrap());
+        assert_eq!(max_spread, Some(Decimal::zero()));
+        // The expected return as computed by the pair never exceeds the minimum.
+        assert!(
+            offer_amount * Decimal256::from(belief_price.inv().unwrap()) <= Uint256::from(1_600u64)
+        );
+
+        assert_eq!(
+            Slippage::MaxSpread(Decimal256::percent(60)).to_astroport(offer_amount, return_amount),
+            Err(NeptuneError::SwapError(SwapError::MaxSpreadTooLarge(
+                Decimal256::percent(60)
+            )))
+        );
+        assert_eq!(
+            Slippage::MaxSpread(Decimal256::percent(50)).to_astroport_router(),
+            Ok(MAX_ALLOWED_SPREAD)
+        );
+    }
+
+    #[test]
+    fn test_min_receive_portions() {
+        let portions = [100u64, 300, 200].map(Uint256::from);
+        assert_eq!(
+            min_receive_portions(Uint256::from(599u64), &portions),
+            vec![
+                Slippage::MinimumReceive(Uint256::from(99u64)),
+                Slippage::MinimumReceive(Uint256::from(301u64)),
+                Slippage::MinimumReceive(Uint256::from(199u64)),
+            ]
+        );
+        assert_eq!(
+            min_receive_portions(Uint256::from(10u64), &[Uint256::zero()]),
+            vec![Slippage::MinimumReceive(Uint256::zero())]
+        );
+    }
+}
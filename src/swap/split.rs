@@ -0,0 +1,433 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Decimal256, Deps, Env, Storage, Uint256};
+use cw_storage_plus::Map;
+
+use crate::{asset::AssetInfo, error::NeptuneResult, query_wrapper::QueryWrapper};
+
+use super::{
+    error::SwapError,
+    plan::SwapPlan,
+    slippage::{min_receive_portions, Slippage},
+    Exchange, Swap,
+};
+
+/// The number of chunks an amount is divided into when allocating it across venues.
+pub const SPLIT_CHUNKS: u64 = 10;
+
+/// Several venues trading the same pair.
+/// Swaps are split across the venues to maximize the amount received.
+#[cw_serde]
+pub struct Split {
+    pub venues: Vec<Exchange>,
+}
+
+/// The portion of a swap routed through a single venue.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Allocation {
+    pub offer_amount: Uint256,
+    pub ask_amount: Uint256,
+}
+
+impl Split {
+    /// Allocates the offer amount chunk by chunk, each chunk going to the venue
+    /// with the largest marginal return. For venues with diminishing returns this is
+    /// optimal up to the size of a chunk. Venues which fail to simulate are skipped.
+    pub fn allocate_offer(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+    ) -> NeptuneResult<Vec<Allocation>> {
+        let mut allocations = vec![Allocation::default(); self.venues.len()];
+        if offer_amount.is_zero() {
+            return Ok(allocations);
+        }
+        for chunk in chunks(offer_amount) {
+            let mut best: Option<(usize, Uint256, Uint256)> = None;
+            for (index, (venue, allocation)) in self.venues.iter().zip(&allocations).enumerate() {
+                let Ok(ask_amount) = venue.query_sim(
+                    deps,
+                    offer_asset,
+                    ask_asset,
+                    allocation.offer_amount + chunk,
+                ) else {
+                    continue;
+                };
+                let gain = ask_amount.saturating_sub(allocation.ask_amount);
+                if !best.is_some_and(|(_, _, best_gain)| gain <= best_gain) {
+                    best = Some((index, ask_amount, gain));
+                }
+            }
+            let (index, ask_amount, _) = best.ok_or(SwapError::InsufficientLiquidity)?;
+            allocations[index].offer_amount += chunk;
+            allocations[index].ask_amount = ask_amount;
+        }
+        Ok(allocations)
+    }
+
+    /// Allocates the ask amount chunk by chunk, each chunk going to the venue
+    /// with the smallest marginal cost. Venues which fail to simulate are skipped.
+    pub fn allocate_ask(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<Vec<Allocation>> {
+        let mut allocations = vec![Allocation::default(); self.venues.len()];
+        if ask_amount.is_zero() {
+            return Ok(allocations);
+        }
+        for chunk in chunks(ask_amount) {
+            let mut best: Option<(usize, Uint256, Uint256)> = None;
+            for (index, (venue, allocation)) in self.venues.iter().zip(&allocations).enumerate() {
+                let Ok(offer_amount) = venue.query_reverse_sim(
+                    deps,
+                    offer_asset,
+                    ask_asset,
+                    allocation.ask_amount + chunk,
+                ) else {
+                    continue;
+                };
+                let cost = offer_amount.saturating_sub(allocation.offer_amount);
+                if !best.is_some_and(|(_, _, best_cost)| cost >= best_cost) {
+                    best = Some((index, offer_amount, cost));
+                }
+            }
+            let (index, offer_amount, _) = best.ok_or(SwapError::InsufficientLiquidity)?;
+            allocations[index].ask_amount += chunk;
+            allocations[index].offer_amount = offer_amount;
+        }
+        Ok(allocations)
+    }
+}
+
+impl Swap for Split {
+    fn swap(
+        &self,
+        deps: Deps<QueryWrapper>,
+        env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let allocations = self.allocate_offer(deps, offer_asset, ask_asset, offer_amount)?;
+        let return_amount: Uint256 = allocations.iter().map(|x| x.ask_amount).sum();
+        let min_receive = slippage.assert(offer_amount, return_amount)?;
+        let allocations: Vec<_> = self
+            .venues
+            .iter()
+            .zip(allocations)
+            .filter(|(_, allocation)| !allocation.offer_amount.is_zero())
+            .collect();
+        let portions: Vec<_> = allocations.iter().map(|(_, x)| x.ask_amount).collect();
+        let mut legs = vec![];
+        for ((venue, allocation), slippage) in allocations
+            .into_iter()
+            .zip(min_receive_portions(min_receive, &portions))
+        {
+            legs.push(venue.swap(
+                deps,
+                env,
+                offer_asset,
+                ask_asset,
+                allocation.offer_amount,
+                &slippage,
+            )?);
+        }
+        Ok(SwapPlan::parallel(
+            offer_asset,
+            ask_asset,
+            legs,
+            min_receive,
+        ))
+    }
+
+    fn swap_ask(
+        &self,
+        deps: Deps<QueryWrapper>,
+        env: &Env,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+        slippage: &Slippage,
+    ) -> NeptuneResult<SwapPlan> {
+        let allocations = self.allocate_ask(deps, offer_asset, ask_asset, ask_amount)?;
+        let offer_amount: Uint256 = allocations.iter().map(|x| x.offer_amount).sum();
+        let min_receive = slippage.assert(offer_amount, ask_amount)?;
+        let allocations: Vec<_> = self
+            .venues
+            .iter()
+            .zip(allocations)
+            .filter(|(_, allocation)| !allocation.ask_amount.is_zero())
+            .collect();
+        let portions: Vec<_> = allocations.iter().map(|(_, x)| x.ask_amount).collect();
+        let mut legs = vec![];
+        for ((venue, allocation), slippage) in allocations
+            .into_iter()
+            .zip(min_receive_portions(min_receive, &portions))
+        {
+            legs.push(venue.swap_ask(
+                deps,
+                env,
+                offer_asset,
+                ask_asset,
+                allocation.ask_amount,
+                &slippage,
+            )?);
+        }
+        Ok(SwapPlan::parallel(
+            offer_asset,
+            ask_asset,
+            legs,
+            min_receive,
+        ))
+    }
+
+    fn query_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        offer_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        Ok(self
+            .allocate_offer(deps, offer_asset, ask_asset, offer_amount)?
+            .iter()
+            .map(|allocation| allocation.ask_amount)
+            .sum())
+    }
+
+    fn query_reverse_sim(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        ask_amount: Uint256,
+    ) -> NeptuneResult<Uint256> {
+        Ok(self
+            .allocate_ask(deps, offer_asset, ask_asset, ask_amount)?
+            .iter()
+            .map(|allocation| allocation.offer_amount)
+            .sum())
+    }
+
+    /// The volumes of every venue are summed, since each venue
+    /// can be traded up to the same marginal price.
+    fn query_ask_amount_at_price(
+        &self,
+        deps: Deps<QueryWrapper>,
+        offer_asset: &AssetInfo,
+        ask_asset: &AssetInfo,
+        max_ratio: Decimal256,
+    ) -> NeptuneResult<Uint256> {
+        self.venues.iter().try_fold(Uint256::zero(), |acc, venue| {
+            Ok(acc + venue.query_ask_amount_at_price(deps, offer_asset, ask_asset, max_ratio)?)
+        })
+    }
+}
+
+/// Registers an additional venue for a pair.
+/// The registered exchange is converted into a [`Split`] if necessary.
+pub fn add_venue(
+    storage: &mut dyn Storage,
+    exchanges: &Map<(&AssetInfo, &AssetInfo), Exchange>,
+    mut assets: [&AssetInfo; 2],
+    exchange: Exchange,
+) -> NeptuneResult<()> {
+    assets.sort_unstable();
+    let exchange = match exchanges.may_load(storage, (assets[0], assets[1]))? {
+        None => exchange,
+        Some(Exchange::Split(mut split)) => {
+            split.venues.push(exchange);
+            Exchange::Split(split)
+        }
+        Some(existing) => Exchange::Split(Split {
+            venues: vec![existing, exchange],
+        }),
+    };
+    exchanges.save(storage, (assets[0], assets[1]), &exchange)?;
+    Ok(())
+}
+
+/// Divides an amount into [`SPLIT_CHUNKS`] chunks which sum to the amount.
+fn chunks(amount: Uint256) -> Vec<Uint256> {
+    let chunk = amount / Uint256::from(SPLIT_CHUNKS);
+    if chunk.is_zero() {
+        return vec![amount];
+    }
+    let mut chunks = vec![chunk; SPLIT_CHUNKS as usize];
+    chunks[0] += amount - chunk * Uint256::from(SPLIT_CHUNKS);
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{
+        testing::{mock_dependencies, mock_env},
+        Addr, Decimal, Uint128,
+    };
+
+    use super::*;
+    use crate::swap::{
+        liquidity_pool::LiquidityPool,
+        mock_querier::{mock_dependencies_with_pools, MockAstroport},
+        simulated_pool::{Curve, SimulatedPool},
+    };
+
+    #[test]
+    fn test_chunks() {
+        let amounts = chunks(Uint256::from(105u64));
+        assert_eq!(amounts.len(), SPLIT_CHUNKS as usize);
+        assert_eq!(amounts[0], Uint256::from(15u64));
+        assert_eq!(
+            amounts.iter().copied().sum::<Uint256>(),
+            Uint256::from(105u64)
+        );
+
+        assert_eq!(chunks(Uint256::from(7u64)), vec![Uint256::from(7u64)]);
+    }
+
+    #[test]
+    fn test_add_venue() {
+        let mut owned_deps = mock_dependencies();
+        let deps = owned_deps.as_mut();
+        const EXCHANGES: Map<(&AssetInfo, &AssetInfo), Exchange> = Map::new("exchanges");
+
+        let asset_a = AssetInfo::NativeToken {
+            denom: "uatom".to_string(),
+        };
+        let asset_b = AssetInfo::NativeToken {
+            denom: "uusdc".to_string(),
+        };
+        let pool = |addr: &str| {
+            Exchange::LiquidityPool(LiquidityPool {
+                addr: Addr::unchecked(addr),
+            })
+        };
+
+        add_venue(
+            deps.storage,
+            &EXCHANGES,
+            [&asset_b, &asset_a],
+            pool("pool_1"),
+        )
+        .unwrap();
+        assert_eq!(
+            EXCHANGES.load(deps.storage, (&asset_a, &asset_b)).unwrap(),
+            pool("pool_1")
+        );
+
+        add_venue(
+            deps.storage,
+            &EXCHANGES,
+            [&asset_a, &asset_b],
+            pool("pool_2"),
+        )
+        .unwrap();
+        add_venue(
+            deps.storage,
+            &EXCHANGES,
+            [&asset_a, &asset_b],
+            pool("pool_3"),
+        )
+        .unwrap();
+        assert_eq!(
+            EXCHANGES.load(deps.storage, (&asset_a, &asset_b)).unwrap(),
+            Exchange::Split(Split {
+                venues: vec![pool("pool_1"), pool("pool_2"), pool("pool_3")]
+            })
+        );
+    }
+
+    #[test]
+    fn test_allocate() {
+        let (offer_asset, ask_asset) = (
+            AssetInfo::NativeToken {
+                denom: "uatom".to_string(),
+            },
+            AssetInfo::NativeToken {
+                denom: "uusdc".to_string(),
+            },
+        );
+        let pool = |reserve: u128| SimulatedPool {
+            assets: [offer_asset.clone(), ask_asset.clone()],
+            reserves: [Uint128::new(reserve); 2],
+            commission_rate: Decimal::permille(3),
+            curve: Curve::ConstantProduct,
+        };
+        let (deep, shallow) = (pool(3_000_000), pool(1_000_000));
+        let deps = mock_dependencies_with_pools(
+            MockAstroport::default()
+                .with_pool("deep", deep.clone())
+                .with_pool("shallow", shallow.clone()),
+        );
+        let venue = |addr: &str| {
+            Exchange::LiquidityPool(LiquidityPool {
+                addr: Addr::unchecked(addr),
+            })
+        };
+        let split = Split {
+            venues: vec![venue("deep"), venue("shallow")],
+        };
+        let offer_amount = Uint256::from(200_000u64);
+
+        let allocations = split
+            .allocate_offer(deps.as_ref(), &offer_asset, &ask_asset, offer_amount)
+            .unwrap();
+        // Both venues are used, the deeper one receiving the larger share.
+        assert_eq!(
+            allocations[0].offer_amount + allocations[1].offer_amount,
+            offer_amount
+        );
+        assert!(allocations[0].offer_amount > allocations[1].offer_amount);
+        assert!(!allocations[1].offer_amount.is_zero());
+        for (pool, allocation) in [&deep, &shallow].into_iter().zip(&allocations) {
+            let sim = pool
+                .simulate(&offer_asset, allocation.offer_amount.try_into().unwrap())
+                .unwrap();
+            assert_eq!(allocation.ask_amount, Uint256::from(sim.return_amount));
+        }
+        let return_amount = allocations[0].ask_amount + allocations[1].ask_amount;
+        let deep_only = deep
+            .simulate(&offer_asset, offer_amount.try_into().unwrap())
+            .unwrap()
+            .return_amount;
+        assert!(return_amount > Uint256::from(deep_only));
+
+        let allocations = split
+            .allocate_ask(deps.as_ref(), &offer_asset, &ask_asset, return_amount)
+            .unwrap();
+        assert_eq!(
+            allocations[0].ask_amount + allocations[1].ask_amount,
+            return_amount
+        );
+        assert!(allocations[0].ask_amount > allocations[1].ask_amount);
+
+        let exchange = Exchange::Split(split);
+        let plan = exchange
+            .swap(
+                deps.as_ref(),
+                &mock_env(),
+                &offer_asset,
+                &ask_asset,
+                offer_amount,
+                &Slippage::Tolerance(Decimal256::percent(1)),
+            )
+            .unwrap();
+        // Each leg records its own venue rather than the whole split.
+        assert_eq!(plan.venue, None);
+        assert_eq!(plan.legs[0].venue, Some(venue("deep")));
+        assert_eq!(plan.legs[1].venue, Some(venue("shallow")));
+        assert_eq!(plan.legs.len(), 2);
+        assert_eq!(plan.msgs.len(), 2);
+        assert_eq!(plan.ask.amount, return_amount);
+        // The minimums of the legs sum to the minimum of the whole.
+        assert_eq!(
+            plan.legs.iter().map(|leg| leg.min_receive).sum::<Uint256>(),
+            plan.min_receive
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use cosmwasm_std::{Decimal256, StdError, StdResult, Uint256, Uint512};
+
+use crate::{error::NeptuneResult, math::checked_div};
+
+/// Number of assets in a pool, Astroport stableswap pairs always have two.
+const N_COINS: u8 = 2;
+
+/// Upper bound on the number of Newton iterations used to solve the invariant.
+const MAX_ITERATIONS: u32 = 64;
+
+/// Returns the leverage `amp * n` used by the invariant of an Astroport stableswap pair.
+pub fn leverage(amp: Decimal256) -> Decimal256 {
+    amp * Decimal256::from_ratio(N_COINS, 1u8)
+}
+
+/// Computes the invariant `D` of a pool with reserves `x` and `y`, which satisfies
+/// `leverage * (x + y) + D = leverage * D + D^3 / (4 * x * y)`.
+pub fn compute_d(leverage: Decimal256, x: Uint256, y: Uint256) -> StdResult<Uint256> {
+    let sum = x + y;
+    if x.is_zero() || y.is_zero() {
+        return Ok(sum);
+    }
+    let n = Uint256::from(N_COINS);
+    let mut d = sum;
+    for _ in 0..MAX_ITERATIONS {
+        let d_p = d_product(d, x, y)?;
+        let prev = d;
+        let numerator = (sum * leverage + d_p * n).full_mul(d);
+        // `leverage - 1` is negative for an amplification below one half.
+        let denominator = (d * leverage + d_p * (n + Uint256::one())).checked_sub(d)?;
+        d = (numerator / Uint512::from(denominator)).try_into()?;
+        if d.max(prev) - d.min(prev) <= Uint256::one() {
+            return Ok(d);
+        }
+    }
+    Err(StdError::generic_err(
+        "stableswap invariant did not converge",
+    ))
+}
+
+/// Computes the reserve of one asset given the reserve `x` of the other asset and the invariant.
+pub fn compute_y(leverage: Decimal256, x: Uint256, d: Uint256) -> StdResult<Uint256> {
+    if x.is_zero() {
+        return Err(StdError::generic_err("stableswap reserve is zero"));
+    }
+    let n = Uint256::from(N_COINS);
+    let c: Uint256 = (d.full_mul(d) / Uint512::from(x * n)).try_into()?;
+    let c = checked_div(c * d, leverage)? / n;
+    let b = x + checked_div(d, leverage)?;
+    let mut y = d;
+    for _ in 0..MAX_ITERATIONS {
+        let prev = y;
+        y = (y * y + c) / (y * n + b).checked_sub(d)?;
+        if y.max(prev) - y.min(prev) <= Uint256::one() {
+            return Ok(y);
+        }
+    }
+    Err(StdError::generic_err("stableswap reserve did not converge"))
+}
+
+/// Returns `D^3 / (4 * x * y)`.
+fn d_product(d: Uint256, x: Uint256, y: Uint256) -> StdResult<Uint256> {
+    let n = Uint256::from(N_COINS);
+    let d_p: Uint256 = (d.full_mul(d) / Uint512::from(x * n)).try_into()?;
+    Ok((d_p.full_mul(d) / Uint512::from(y * n)).try_into()?)
+}
+
+/// The marginal ratio of `x` offered to `y` received at the given reserves.
+pub fn marginal_ratio(
+    leverage: Decimal256,
+    d: Uint256,
+    x: Uint256,
+    y: Uint256,
+) -> NeptuneResult<Decimal256> {
+    let d_p = d_product(d, x, y)?;
+    Ok((leverage + Decimal256::checked_from_ratio(d_p, y)?)
+        / (leverage + Decimal256::checked_from_ratio(d_p, x)?))
+}
+
+/// Returns the amount of the ask asset received when swapping until the marginal ratio
+/// of offer to ask reaches `max_ratio`. Fees are not taken into account.
+pub fn ask_amount_at_price(
+    leverage: Decimal256,
+    offer_reserve: Uint256,
+    ask_reserve: Uint256,
+    max_ratio: Decimal256,
+) -> NeptuneResult<Uint256> {
+    if offer_reserve.is_zero() || ask_reserve.is_zero() {
+        return Ok(Uint256::zero());
+    }
+    let d = compute_d(leverage, offer_reserve, ask_reserve)?;
+    let is_within_price = |ask_reserve_after: Uint256| -> NeptuneResult<bool> {
+        let offer_reserve_after = compute_y(leverage, ask_reserve_after, d)?;
+        Ok(marginal_ratio(leverage, d, offer_reserve_after, ask_reserve_after)? <= max_ratio)
+    };
+    if !is_within_price(ask_reserve)? {
+        return Ok(Uint256::zero());
+    }
+
+    // Bisect for the smallest ask reserve at which the marginal ratio is still within the price.
+    let mut lower = Uint256::zero();
+    let mut upper = ask_reserve;
+    while upper - lower > Uint256::one() {
+        let mid = lower + (upper - lower) / Uint256::from(2u8);
+        if is_within_price(mid)? {
+            upper = mid;
+        } else {
+            lower = mid;
+        }
+    }
+    Ok(ask_reserve - upper)
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::Isqrt;
+
+    use super::*;
+
+    #[test]
+    fn test_balanced_pool() {
+        let leverage = leverage(Decimal256::from_ratio(100u64, 1u64));
+        let reserve = Uint256::from(1_000_000_000_000u64);
+        let d = compute_d(leverage, reserve, reserve).unwrap();
+        assert_eq!(d, reserve * Uint256::from(2u8));
+
+        let y = compute_y(leverage, reserve, d).unwrap();
+        assert!(y.max(reserve) - y.min(reserve) <= Uint256::one());
+
+        assert_eq!(
+            marginal_ratio(leverage, d, reserve, reserve).unwrap(),
+            Decimal256::one()
+        );
+    }
+
+    #[test]
+    fn test_low_amplification() {
+        // A leverage below one no longer underflows while solving the invariant.
+        let leverage = leverage(Decimal256::percent(25));
+        let (x, y) = (Uint256::from(1_000_000u64), Uint256::from(4_000_000u64));
+        let d = compute_d(leverage, x, y).unwrap();
+        assert!(d > (x * y).isqrt() * Uint256::from(2u8) && d < x + y);
+    }
+
+    #[test]
+    fn test_ask_amount_at_price() {
+        let leverage = leverage(Decimal256::from_ratio(100u64, 1u64));
+        let reserve = Uint256::from(1_000_000_000_000u64);
+
+        assert_eq!(
+            ask_amount_at_price(leverage, reserve, reserve, Decimal256::percent(99)).unwrap(),
+            Uint256::zero()
+        );
+
+        let max_ratio = Decimal256::percent(101);
+        let ask_amount = ask_amount_at_price(leverage, reserve, reserve, max_ratio).unwrap();
+        // A constant product pool reaches the same price much sooner.
+        let sqrt: Uint256 = (reserve.full_mul(reserve)
+            * Uint512::from(Decimal256::one().atomics())
+            / Uint512::from(max_ratio.atomics()))
+        .isqrt()
+        .try_into()
+        .unwrap();
+        let constant_product = reserve - sqrt;
+        assert!(ask_amount > constant_product * Uint256::from(10u8));
+        assert!(ask_amount < reserve);
+
+        // The marginal ratio after the swap matches the price.
+        let d = compute_d(leverage, reserve, reserve).unwrap();
+        let offer_reserve = compute_y(leverage, reserve - ask_amount, d).unwrap();
+        let ratio = marginal_ratio(leverage, d, offer_reserve, reserve - ask_amount).unwrap();
+        assert!(ratio <= max_ratio);
+        assert!(ratio > Decimal256::percent(100));
+    }
+}
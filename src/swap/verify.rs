@@ -0,0 +1,167 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{CustomQuery, DepsMut, Env, Reply, SubMsg, Uint256};
+use cw_storage_plus::Map;
+
+use crate::{
+    asset::AssetInfo, error::NeptuneResult, msg_wrapper::MsgWrapper, querier::query_asset_balance,
+};
+
+use super::{error::SwapError, plan::SwapPlan};
+
+#[cw_serde]
+pub struct PendingSwap {
+    pub ask_asset: AssetInfo,
+    pub balance_before: Uint256,
+    pub min_receive: Uint256,
+}
+
+/// Verifies the amounts received by swaps in their submessage replies.
+/// Each verifier is stored under its own namespace so a contract can hold several.
+pub struct SwapVerifier<'a> {
+    /// Balance snapshots of the swaps awaiting verification, keyed by reply id.
+    pub pending_swaps: Map<'a, u64, PendingSwap>,
+}
+
+impl<'a> SwapVerifier<'a> {
+    pub const fn new(namespace: &'a str) -> Self {
+        Self {
+            pending_swaps: Map::new(namespace),
+        }
+    }
+
+    /// Wraps the messages of a swap plan in submessages, replying on success of the last one.
+    /// The contract's balance of the ask asset is recorded
+    /// so the reply can verify the amount received.
+    pub fn verified_swap(
+        &self,
+        deps: DepsMut<impl CustomQuery>,
+        env: &Env,
+        plan: SwapPlan,
+        reply_id: u64,
+    ) -> NeptuneResult<Vec<SubMsg<MsgWrapper>>> {
+        if plan.msgs.is_empty() {
+            return Ok(vec![]);
+        }
+        let balance_before =
+            query_asset_balance(deps.querier, &env.contract.address, &plan.ask.info)?;
+        self.pending_swaps.save(
+            deps.storage,
+            reply_id,
+            &PendingSwap {
+                ask_asset: plan.ask.info,
+                balance_before,
+                min_receive: plan.min_receive,
+            },
+        )?;
+        let last = plan.msgs.len() - 1;
+        Ok(plan
+            .msgs
+            .into_iter()
+            .enumerate()
+            .map(|(index, msg)| {
+                if index == last {
+                    SubMsg::reply_on_success(msg, reply_id)
+                } else {
+                    SubMsg::new(msg)
+                }
+            })
+            .collect())
+    }
+
+    /// Verifies a swap created with [`Self::verified_swap`] when handling its reply.
+    /// Returns the amount of the ask asset received, erroring if it is below the minimum.
+    pub fn verify_swap_reply(
+        &self,
+        deps: DepsMut<impl CustomQuery>,
+        env: &Env,
+        reply: &Reply,
+    ) -> NeptuneResult<Uint256> {
+        let pending = self.pending_swaps.load(deps.storage, reply.id)?;
+        self.pending_swaps.remove(deps.storage, reply.id);
+        let balance_after =
+            query_asset_balance(deps.querier, &env.contract.address, &pending.ask_asset)?;
+        let received = balance_after.saturating_sub(pending.balance_before);
+        if received < pending.min_receive {
+            return Err(SwapError::SlippageExceeded {
+                minimum: pending.min_receive,
+                actual: received,
+            }
+            .into());
+        }
+        Ok(received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{
+        coin,
+        testing::{mock_dependencies, mock_env, MOCK_CONTRACT_ADDR},
+        BankMsg, ReplyOn, SubMsgResponse, SubMsgResult,
+    };
+
+    use super::*;
+    use crate::error::NeptuneError;
+
+    #[test]
+    fn test_verify_swap_reply() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let verifier = SwapVerifier::new("pending_swaps");
+        let offer_asset = AssetInfo::NativeToken {
+            denom: "uatom".to_string(),
+        };
+        let ask_asset = AssetInfo::NativeToken {
+            denom: "uusdc".to_string(),
+        };
+        let reply = Reply {
+            id: 7,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        };
+
+        let mut plan = SwapPlan::empty(&offer_asset, &ask_asset);
+        plan.min_receive = 50u64.into();
+        plan.msgs = vec![
+            BankMsg::Burn { amount: vec![] }.into(),
+            BankMsg::Burn { amount: vec![] }.into(),
+        ];
+
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, vec![coin(100, "uusdc")]);
+        let sub_msgs = verifier
+            .verified_swap(deps.as_mut(), &env, plan.clone(), reply.id)
+            .unwrap();
+        assert_eq!(sub_msgs.len(), 2);
+        assert_eq!(sub_msgs[0].reply_on, ReplyOn::Never);
+        assert_eq!(sub_msgs[1].reply_on, ReplyOn::Success);
+
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, vec![coin(180, "uusdc")]);
+        let received = verifier
+            .verify_swap_reply(deps.as_mut(), &env, &reply)
+            .unwrap();
+        assert_eq!(received, Uint256::from(80u64));
+
+        verifier
+            .verified_swap(deps.as_mut(), &env, plan, reply.id)
+            .unwrap();
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, vec![coin(190, "uusdc")]);
+        let res = verifier.verify_swap_reply(deps.as_mut(), &env, &reply);
+        assert_eq!(
+            res,
+            Err(NeptuneError::SwapError(SwapError::SlippageExceeded {
+                minimum: 50u64.into(),
+                actual: 10u64.into()
+            }))
+        );
+        assert!(verifier
+            .pending_swaps
+            .may_load(&deps.storage, reply.id)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,30 @@
+use std::marker::PhantomData;
+
+use cosmwasm_std::{
+    testing::{self, MockApi, MockQuerier, MockStorage},
+    Addr, Env, OwnedDeps,
+};
+
+use crate::query_wrapper::QueryWrapper;
+
+/// The address of the contract in [`mock_env`].
+/// Subaccount ids are derived from the contract's address, which must be bech32.
+pub const MOCK_CONTRACT: &str = "inj1qyqszqgpqyqszqgpqyqszqgpqyqszqgpc6k6ly";
+
+/// Mock dependencies which accept the custom queries of the enabled chain.
+pub fn mock_dependencies(
+) -> OwnedDeps<MockStorage, MockApi, MockQuerier<QueryWrapper>, QueryWrapper> {
+    OwnedDeps {
+        storage: MockStorage::default(),
+        api: MockApi::default(),
+        querier: MockQuerier::new(&[]),
+        custom_query_type: PhantomData,
+    }
+}
+
+/// A mock environment whose contract is at [`MOCK_CONTRACT`].
+pub fn mock_env() -> Env {
+    let mut env = testing::mock_env();
+    env.contract.address = Addr::unchecked(MOCK_CONTRACT);
+    env
+}